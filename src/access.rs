@@ -0,0 +1,91 @@
+use chrono::{DateTime, Duration, Local};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use uuid::Uuid;
+
+/// 邀请码有效期（小时）
+pub const INVITE_VALID_HOURS: i64 = 24;
+
+/// 启动时从环境变量读取的访问控制配置
+#[derive(Debug, Clone, Default)]
+pub struct AccessConfig {
+    /// 机器人所有者的 Telegram 用户ID，未设置时不启用访问控制
+    pub owner_id: Option<u64>,
+    /// 是否将被拒绝的访问报告给所有者
+    pub report_rejections: bool,
+}
+
+impl AccessConfig {
+    pub fn is_enabled(&self) -> bool {
+        self.owner_id.is_some()
+    }
+
+    pub fn is_owner(&self, user_id: u64) -> bool {
+        self.owner_id == Some(user_id)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Invite {
+    pub created_by: u64,
+    pub created_at: DateTime<Local>,
+    pub expires_at: DateTime<Local>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum InviteError {
+    NotFound,
+    Expired,
+}
+
+/// 持久化的白名单和未使用的邀请码
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AccessControl {
+    #[serde(default)]
+    pub allowed_users: HashSet<u64>,
+    #[serde(default)]
+    pub invites: HashMap<String, Invite>,
+}
+
+impl AccessControl {
+    pub fn is_allowed(&self, config: &AccessConfig, user_id: u64) -> bool {
+        !config.is_enabled() || config.is_owner(user_id) || self.allowed_users.contains(&user_id)
+    }
+
+    /// 生成一次性邀请码
+    pub fn create_invite(&mut self, created_by: u64, now: DateTime<Local>) -> String {
+        self.remove_expired_invites(now);
+
+        let code = Uuid::new_v4().simple().to_string()[..8].to_uppercase();
+        self.invites.insert(
+            code.clone(),
+            Invite {
+                created_by,
+                created_at: now,
+                expires_at: now + Duration::hours(INVITE_VALID_HOURS),
+            },
+        );
+        code
+    }
+
+    /// 使用邀请码，成功后邀请码立即失效
+    pub fn redeem_invite(&mut self, code: &str, user_id: u64, now: DateTime<Local>) -> Result<(), InviteError> {
+        let code = code.trim().to_uppercase();
+        let invite = self.invites.remove(&code).ok_or(InviteError::NotFound)?;
+
+        if invite.expires_at < now {
+            return Err(InviteError::Expired);
+        }
+
+        self.allowed_users.insert(user_id);
+        Ok(())
+    }
+
+    pub fn revoke(&mut self, user_id: u64) -> bool {
+        self.allowed_users.remove(&user_id)
+    }
+
+    fn remove_expired_invites(&mut self, now: DateTime<Local>) {
+        self.invites.retain(|_, invite| invite.expires_at >= now);
+    }
+}
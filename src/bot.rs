@@ -1,26 +1,50 @@
-use crate::{localization, storage::Language, Medicine, ReminderService};
-use chrono::NaiveTime;
+use crate::{
+    access::InviteError, localization, parse_reminder_times, query_history, storage::Language, CallbackData, Caregiver,
+    EditField, EscalationPolicy, HistoryFilter, Medicine, PrnPolicy, PrnTakeResult, ReminderService, Schedule,
+    SkipReason, StockAlert, UserData, DEFAULT_ESCALATE_AFTER, HISTORY_PAGE_SIZE,
+};
+use crate::dialogue_storage::DialogueStorage;
+use crate::timezone;
+use crate::travel::TravelPlan;
+use chrono::{DateTime, Duration, Local, NaiveDate, NaiveTime};
+use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 use teloxide::{
-    dispatching::{dialogue, dialogue::InMemStorage, UpdateHandler},
+    dispatching::{dialogue, UpdateHandler},
     prelude::*,
     types::{InlineKeyboardButton, InlineKeyboardMarkup},
     utils::command::BotCommands,
 };
 use uuid::Uuid;
 
-type MyDialogue = Dialogue<State, InMemStorage<State>>;
+type MyDialogue = Dialogue<State, DialogueStorage>;
 type HandlerResult = Result<(), Box<dyn std::error::Error + Send + Sync>>;
 
-#[derive(Clone, Default)]
+/// 对话状态，保存在用户数据中，重启后可以继续
+#[derive(Clone, Default, Serialize, Deserialize)]
 pub enum State {
     #[default]
     Start,
     ReceiveMedicineName,
     ReceiveQuantity { name: String },
     ReceiveReminderTimes { name: String, quantity: u32 },
+    ReceiveSchedule { name: String, quantity: u32, reminder_times: Vec<NaiveTime> },
     ReceiveConfirmDoseAmount { reminder_id: String },
+    ReceiveSnoozeMinutes { reminder_id: String },
+    ReceiveSnoozeUntil { reminder_id: String },
     ReceiveRefillAmount { medicine_id: String },
+    /// `medicine_id` 为 `None` 时设置所有药品的默认照护人
+    ReceiveCaregiver { medicine_id: Option<String> },
+    ReceiveStockAlert { medicine_id: String },
+    ReceiveGraceMinutes { medicine_id: String },
+    ReceiveEscalationPolicy { medicine_id: String },
+    ReceiveExpiryMinutes { medicine_id: String },
+    ReceiveEditValue { medicine_id: String, field: EditField },
+    ReceivePauseUntil { medicine_id: String },
+    ReceiveTravelDestination,
+    ReceiveTravelDates { destination: String },
+    /// 等待用户确认旅行计划
+    ConfirmTravel { destination: String, depart_date: NaiveDate, return_date: Option<NaiveDate>, step_minutes: u32 },
 }
 
 #[derive(BotCommands, Clone)]
@@ -38,10 +62,48 @@ pub enum Command {
     Refill,
     #[command(description = "查看待确认的提醒")]
     Pending,
+    #[command(description = "跳过待确认的服药")]
+    Skip,
     #[command(description = "切换语言")]
     Language,
+    #[command(description = "记录按需服药")]
+    Take,
+    #[command(description = "查看服药记录")]
+    History(String),
+    #[command(description = "查看服药统计")]
+    Stats(String),
+    #[command(description = "设置照护人")]
+    Caregiver,
+    #[command(description = "设置库存提醒")]
+    Stock,
+    #[command(description = "设置补发提醒的宽限时间")]
+    Grace,
+    #[command(description = "设置后续提醒策略")]
+    Escalation,
+    #[command(description = "设置未确认提醒的过期时间")]
+    Expiry,
+    #[command(description = "修改药品")]
+    Edit,
+    #[command(description = "暂停药品提醒")]
+    Pause,
+    #[command(description = "恢复药品提醒")]
+    Resume,
+    #[command(description = "设置时区")]
+    Timezone(String),
+    #[command(description = "旅行模式")]
+    Travel,
+    #[command(description = "生成邀请码（仅所有者）")]
+    Invite,
+    #[command(description = "管理授权用户（仅所有者）")]
+    Users,
+    #[command(description = "使用邀请码加入")]
+    Join(String),
 }
 
+// 未授权用户收到的提示，此时尚无语言设置，因此同时显示中英文
+const UNAUTHORIZED_MESSAGE: &str = "🚫 你没有使用此机器人的权限。\n如有邀请码，请发送 /join 邀请码\n\n\
+    🚫 You are not allowed to use this bot.\nIf you have an invite code, send /join CODE";
+
 pub fn schema() -> UpdateHandler<Box<dyn std::error::Error + Send + Sync + 'static>> {
     use dptree::case;
 
@@ -54,34 +116,230 @@ pub fn schema() -> UpdateHandler<Box<dyn std::error::Error + Send + Sync + 'stat
                 .branch(case![Command::Delete].endpoint(delete_medicine))
                 .branch(case![Command::Refill].endpoint(refill_medicine))
                 .branch(case![Command::Pending].endpoint(show_pending))
-                .branch(case![Command::Language].endpoint(show_language_selection)),
+                .branch(case![Command::Skip].endpoint(show_skip_selection))
+                .branch(case![Command::Language].endpoint(show_language_selection))
+                .branch(case![Command::Take].endpoint(show_take_selection))
+                .branch(case![Command::History(args)].endpoint(show_history))
+                .branch(case![Command::Stats(args)].endpoint(show_stats))
+                .branch(case![Command::Caregiver].endpoint(show_caregiver_selection))
+                .branch(case![Command::Stock].endpoint(show_stock_selection))
+                .branch(case![Command::Grace].endpoint(show_grace_selection))
+                .branch(case![Command::Escalation].endpoint(show_escalation_selection))
+                .branch(case![Command::Expiry].endpoint(show_expiry_selection))
+                .branch(case![Command::Edit].endpoint(show_edit_selection))
+                .branch(case![Command::Pause].endpoint(show_pause_selection))
+                .branch(case![Command::Resume].endpoint(show_resume_selection))
+                .branch(case![Command::Timezone(args)].endpoint(set_timezone))
+                .branch(case![Command::Travel].endpoint(show_travel))
+                .branch(case![Command::Invite].endpoint(create_invite))
+                .branch(case![Command::Users].endpoint(list_allowed_users)),
         )
         .branch(case![State::ReceiveMedicineName].endpoint(receive_medicine_name))
         .branch(case![State::ReceiveQuantity { name }].endpoint(receive_quantity))
         .branch(case![State::ReceiveReminderTimes { name, quantity }].endpoint(receive_reminder_times))
+        .branch(case![State::ReceiveSchedule { name, quantity, reminder_times }].endpoint(receive_schedule))
         .branch(case![State::ReceiveConfirmDoseAmount { reminder_id }].endpoint(receive_confirm_dose_amount))
-        .branch(case![State::ReceiveRefillAmount { medicine_id }].endpoint(receive_refill_amount));
+        .branch(case![State::ReceiveSnoozeMinutes { reminder_id }].endpoint(receive_snooze_minutes))
+        .branch(case![State::ReceiveSnoozeUntil { reminder_id }].endpoint(receive_snooze_until))
+        .branch(case![State::ReceiveRefillAmount { medicine_id }].endpoint(receive_refill_amount))
+        .branch(case![State::ReceiveCaregiver { medicine_id }].endpoint(receive_caregiver))
+        .branch(case![State::ReceiveStockAlert { medicine_id }].endpoint(receive_stock_alert))
+        .branch(case![State::ReceiveGraceMinutes { medicine_id }].endpoint(receive_grace_minutes))
+        .branch(case![State::ReceiveEscalationPolicy { medicine_id }].endpoint(receive_escalation_policy))
+        .branch(case![State::ReceiveExpiryMinutes { medicine_id }].endpoint(receive_expiry_minutes))
+        .branch(case![State::ReceiveEditValue { medicine_id, field }].endpoint(receive_edit_value))
+        .branch(case![State::ReceivePauseUntil { medicine_id }].endpoint(receive_pause_until))
+        .branch(case![State::ReceiveTravelDestination].endpoint(receive_travel_destination))
+        .branch(case![State::ReceiveTravelDates { destination }].endpoint(receive_travel_dates))
+        .branch(
+            case![State::ConfirmTravel { destination, depart_date, return_date, step_minutes }]
+                .endpoint(discard_travel_plan),
+        );
+
+    // /join 必须在访问控制之前处理，否则新用户无法使用邀请码
+    let join_handler = teloxide::filter_command::<Command, _>()
+        .branch(case![Command::Join(code)].endpoint(join));
 
     let message_handler = Update::filter_message()
-        .enter_dialogue::<Message, InMemStorage<State>, State>()
+        .enter_dialogue::<Message, DialogueStorage, State>()
+        .branch(join_handler)
+        .branch(dptree::filter_async(is_unauthorized_message).endpoint(reject_message))
         .branch(command_handler)
         .branch(case![State::ReceiveMedicineName].endpoint(receive_medicine_name))
         .branch(case![State::ReceiveQuantity { name }].endpoint(receive_quantity))
         .branch(case![State::ReceiveReminderTimes { name, quantity }].endpoint(receive_reminder_times))
+        .branch(case![State::ReceiveSchedule { name, quantity, reminder_times }].endpoint(receive_schedule))
         .branch(case![State::ReceiveConfirmDoseAmount { reminder_id }].endpoint(receive_confirm_dose_amount))
-        .branch(case![State::ReceiveRefillAmount { medicine_id }].endpoint(receive_refill_amount));
+        .branch(case![State::ReceiveSnoozeMinutes { reminder_id }].endpoint(receive_snooze_minutes))
+        .branch(case![State::ReceiveSnoozeUntil { reminder_id }].endpoint(receive_snooze_until))
+        .branch(case![State::ReceiveRefillAmount { medicine_id }].endpoint(receive_refill_amount))
+        .branch(case![State::ReceiveCaregiver { medicine_id }].endpoint(receive_caregiver))
+        .branch(case![State::ReceiveStockAlert { medicine_id }].endpoint(receive_stock_alert))
+        .branch(case![State::ReceiveGraceMinutes { medicine_id }].endpoint(receive_grace_minutes))
+        .branch(case![State::ReceiveEscalationPolicy { medicine_id }].endpoint(receive_escalation_policy))
+        .branch(case![State::ReceiveExpiryMinutes { medicine_id }].endpoint(receive_expiry_minutes))
+        .branch(case![State::ReceiveEditValue { medicine_id, field }].endpoint(receive_edit_value))
+        .branch(case![State::ReceivePauseUntil { medicine_id }].endpoint(receive_pause_until))
+        .branch(case![State::ReceiveTravelDestination].endpoint(receive_travel_destination))
+        .branch(case![State::ReceiveTravelDates { destination }].endpoint(receive_travel_dates))
+        .branch(
+            case![State::ConfirmTravel { destination, depart_date, return_date, step_minutes }]
+                .endpoint(discard_travel_plan),
+        );
 
     let callback_query_handler = Update::filter_callback_query()
-        .enter_dialogue::<CallbackQuery, InMemStorage<State>, State>()
+        .enter_dialogue::<CallbackQuery, DialogueStorage, State>()
+        .branch(dptree::filter_async(is_unauthorized_callback).endpoint(reject_callback))
         .endpoint(handle_callback);
 
-    dialogue::enter::<Update, InMemStorage<State>, State, _>()
+    dialogue::enter::<Update, DialogueStorage, State, _>()
         .branch(message_handler)
         .branch(callback_query_handler)
 }
 
+async fn is_unauthorized_message(msg: Message, reminder_service: Arc<ReminderService>) -> bool {
+    match msg.from() {
+        Some(user) => !reminder_service.is_authorized(user.id).await,
+        None => reminder_service.access_config().is_enabled(),
+    }
+}
+
+async fn is_unauthorized_callback(q: CallbackQuery, reminder_service: Arc<ReminderService>) -> bool {
+    !reminder_service.is_authorized(q.from.id).await
+}
+
+async fn reject_message(bot: Bot, msg: Message, reminder_service: Arc<ReminderService>) -> HandlerResult {
+    if let Some(user) = msg.from() {
+        let action = msg.text().unwrap_or("<非文本消息>");
+        reminder_service.report_rejection(user, action).await;
+    }
+
+    bot.send_message(msg.chat.id, UNAUTHORIZED_MESSAGE).await?;
+    Ok(())
+}
+
+async fn reject_callback(bot: Bot, q: CallbackQuery, reminder_service: Arc<ReminderService>) -> HandlerResult {
+    let action = format!("按钮 {}", q.data.as_deref().unwrap_or_default());
+    reminder_service.report_rejection(&q.from, &action).await;
+
+    bot.answer_callback_query(q.id)
+        .text(UNAUTHORIZED_MESSAGE)
+        .show_alert(true)
+        .await?;
+    Ok(())
+}
+
+async fn join(bot: Bot, msg: Message, code: String, reminder_service: Arc<ReminderService>) -> HandlerResult {
+    let data = reminder_service.get_user_data(msg.chat.id).await;
+    let language = &data.user_settings.language;
+    let text = localization::get_text(language);
+
+    let Some(user) = msg.from() else {
+        return Ok(());
+    };
+
+    if reminder_service.is_authorized(user.id).await {
+        bot.send_message(msg.chat.id, text.already_authorized).await?;
+        return Ok(());
+    }
+
+    if code.trim().is_empty() {
+        bot.send_message(msg.chat.id, text.join_usage).await?;
+        return Ok(());
+    }
+
+    match reminder_service.redeem_invite(&code, user.id).await {
+        Ok(()) => {
+            log::info!("用户 {} ({}) 已通过邀请码加入", user.id, user.full_name());
+            bot.send_message(msg.chat.id, text.join_success).await?;
+
+            if let Some(owner_id) = reminder_service.access_config().owner_id {
+                let notice = format!("{}\n👤 {} ({})", text.user_joined_notice, user.full_name(), user.id);
+                if let Err(e) = bot.send_message(ChatId(owner_id as i64), notice).await {
+                    log::warn!("通知所有者失败: {}", e);
+                }
+            }
+        }
+        Err(error) => {
+            reminder_service.report_rejection(user, "/join（邀请码无效）").await;
+            let message = match error {
+                InviteError::NotFound => text.join_invalid_code,
+                InviteError::Expired => text.join_expired_code,
+            };
+            bot.send_message(msg.chat.id, message).await?;
+        }
+    }
+    Ok(())
+}
+
+async fn create_invite(bot: Bot, msg: Message, reminder_service: Arc<ReminderService>) -> HandlerResult {
+    let data = reminder_service.get_user_data(msg.chat.id).await;
+    let language = &data.user_settings.language;
+    let text = localization::get_text(language);
+
+    let Some(user) = msg.from().filter(|user| reminder_service.access_config().is_owner(user.id.0)) else {
+        bot.send_message(msg.chat.id, text.owner_only).await?;
+        return Ok(());
+    };
+
+    match reminder_service.create_invite(user.id).await {
+        Ok(code) => {
+            let message = format!("{}\n\n{}\n\n{}\n/join {}", text.invite_created, code, text.invite_usage, code);
+            bot.send_message(msg.chat.id, message).await?;
+        }
+        Err(e) => {
+            log::error!("Failed to create invite: {}", e);
+            bot.send_message(msg.chat.id, "❌ 生成邀请码失败 / Failed to create invite code").await?;
+        }
+    }
+    Ok(())
+}
+
+async fn list_allowed_users(bot: Bot, msg: Message, reminder_service: Arc<ReminderService>) -> HandlerResult {
+    let data = reminder_service.get_user_data(msg.chat.id).await;
+    let language = &data.user_settings.language;
+    let text = localization::get_text(language);
+
+    let is_owner = msg
+        .from()
+        .is_some_and(|user| reminder_service.access_config().is_owner(user.id.0));
+    if !is_owner {
+        bot.send_message(msg.chat.id, text.owner_only).await?;
+        return Ok(());
+    }
+
+    let mut allowed_users: Vec<u64> = reminder_service
+        .get_data()
+        .await
+        .access
+        .allowed_users
+        .into_iter()
+        .collect();
+    allowed_users.sort_unstable();
+
+    if allowed_users.is_empty() {
+        bot.send_message(msg.chat.id, text.no_allowed_users).await?;
+        return Ok(());
+    }
+
+    let keyboard: Vec<Vec<InlineKeyboardButton>> = allowed_users
+        .iter()
+        .map(|user_id| {
+            vec![InlineKeyboardButton::callback(
+                format!("🚫 {}", user_id),
+                CallbackData::RevokeUser(*user_id).encode(),
+            )]
+        })
+        .collect();
+
+    bot.send_message(msg.chat.id, text.allowed_users_title)
+        .reply_markup(InlineKeyboardMarkup::new(keyboard))
+        .await?;
+    Ok(())
+}
+
 async fn help(bot: Bot, msg: Message, reminder_service: Arc<ReminderService>) -> HandlerResult {
-    let data = reminder_service.get_data().await;
+    let data = reminder_service.get_user_data(msg.chat.id).await;
     let language = &data.user_settings.language;
     let help_text = localization::format_help_message(language);
 
@@ -90,14 +348,14 @@ async fn help(bot: Bot, msg: Message, reminder_service: Arc<ReminderService>) ->
 }
 
 async fn show_language_selection(bot: Bot, msg: Message, reminder_service: Arc<ReminderService>) -> HandlerResult {
-    let data = reminder_service.get_data().await;
+    let data = reminder_service.get_user_data(msg.chat.id).await;
     let language = &data.user_settings.language;
     let text = localization::get_text(language);
 
     let keyboard = vec![
         vec![
-            InlineKeyboardButton::callback(text.chinese_button, "lang_chinese"),
-            InlineKeyboardButton::callback(text.english_button, "lang_english"),
+            InlineKeyboardButton::callback(text.chinese_button, CallbackData::SetLanguage(Language::Chinese).encode()),
+            InlineKeyboardButton::callback(text.english_button, CallbackData::SetLanguage(Language::English).encode()),
         ],
     ];
     let markup = InlineKeyboardMarkup::new(keyboard);
@@ -117,7 +375,7 @@ async fn show_language_selection(bot: Bot, msg: Message, reminder_service: Arc<R
 }
 
 async fn start_add_medicine(bot: Bot, dialogue: MyDialogue, msg: Message, reminder_service: Arc<ReminderService>) -> HandlerResult {
-    let data = reminder_service.get_data().await;
+    let data = reminder_service.get_user_data(msg.chat.id).await;
     let language = &data.user_settings.language;
     let text = localization::get_text(language);
 
@@ -127,7 +385,7 @@ async fn start_add_medicine(bot: Bot, dialogue: MyDialogue, msg: Message, remind
 }
 
 async fn receive_medicine_name(bot: Bot, dialogue: MyDialogue, msg: Message, reminder_service: Arc<ReminderService>) -> HandlerResult {
-    let data = reminder_service.get_data().await;
+    let data = reminder_service.get_user_data(msg.chat.id).await;
     let language = &data.user_settings.language;
     let text = localization::get_text(language);
 
@@ -159,7 +417,7 @@ async fn receive_quantity(
     name: String,
     reminder_service: Arc<ReminderService>,
 ) -> HandlerResult {
-    let data = reminder_service.get_data().await;
+    let data = reminder_service.get_user_data(msg.chat.id).await;
     let language = &data.user_settings.language;
     let text = localization::get_text(language);
 
@@ -194,58 +452,53 @@ async fn receive_reminder_times(
 ) -> HandlerResult {
     match msg.text() {
         Some(times_str) => {
-            let times: Result<Vec<NaiveTime>, _> = times_str
-                .split(',')
-                .map(|s| s.trim())
-                .map(|s| NaiveTime::parse_from_str(s, "%H:%M"))
-                .collect();
-
-            match times {
-                Ok(reminder_times) if !reminder_times.is_empty() => {
-                    let data = reminder_service.get_data().await;
-                    let language = &data.user_settings.language;
-                    let text = localization::get_text(language);
+            let times = parse_reminder_times(times_str);
 
-                    let medicine = Medicine::new(name.clone(), quantity, reminder_times.clone());
+            let data = reminder_service.get_user_data(msg.chat.id).await;
+            let language = &data.user_settings.language;
+            let text = localization::get_text(language);
 
-                    if let Err(e) = reminder_service
-                        .update_data(|data| {
-                            data.medicines.insert(medicine.id, medicine);
-                        })
-                        .await {
-                        log::error!("Failed to save medicine: {}", e);
-                        let error_msg = if matches!(language, Language::Chinese) {
-                            "❌ 保存药品信息失败"
-                        } else {
-                            "❌ Failed to save medicine information"
-                        };
-                        bot.send_message(msg.chat.id, error_msg).await?;
-                        return Ok(());
-                    }
+            // 按需服用的药品没有提醒时间和服药计划，直接保存
+            if let Some(policy) = PrnPolicy::parse(times_str) {
+                let medicine = Medicine::new_as_needed(name.clone(), quantity, policy.clone());
 
-                    let times_display: Vec<String> = reminder_times
-                        .iter()
-                        .map(|t| t.format("%H:%M").to_string())
-                        .collect();
+                if let Err(e) = reminder_service
+                    .update_user_data(msg.chat.id, |data| {
+                        data.medicines.insert(medicine.id, medicine);
+                    })
+                    .await {
+                    log::error!("Failed to save medicine: {}", e);
+                    let error_msg = if matches!(language, Language::Chinese) {
+                        "❌ 保存药品信息失败"
+                    } else {
+                        "❌ Failed to save medicine information"
+                    };
+                    bot.send_message(msg.chat.id, error_msg).await?;
+                    return Ok(());
+                }
 
-                    let message = format!(
-                        "{}\n\n💊 {}：{}\n📦 {}：{}\n⏰ {}：{}",
-                        text.medicine_added,
-                        if matches!(language, Language::Chinese) { "名称" } else { "Name" },
-                        name,
-                        if matches!(language, Language::Chinese) { "数量" } else { "Quantity" },
-                        quantity,
-                        if matches!(language, Language::Chinese) { "提醒时间" } else { "Reminder times" },
-                        times_display.join(", ")
-                    );
+                let message = format!(
+                    "{}\n\n💊 {}：{}\n📦 {}：{}\n📅 {}",
+                    text.medicine_added,
+                    if matches!(language, Language::Chinese) { "名称" } else { "Name" },
+                    name,
+                    if matches!(language, Language::Chinese) { "数量" } else { "Quantity" },
+                    quantity,
+                    localization::format_prn_policy(language, &policy)
+                );
+                bot.send_message(msg.chat.id, message).await?;
+                dialogue.update(State::Start).await?;
+                return Ok(());
+            }
 
-                    bot.send_message(msg.chat.id, message).await?;
-                    dialogue.update(State::Start).await?;
+            match times {
+                Some(reminder_times) => {
+                    bot.send_message(msg.chat.id, text.enter_schedule).await?;
+                    dialogue
+                        .update(State::ReceiveSchedule { name, quantity, reminder_times })
+                        .await?;
                 }
                 _ => {
-                    let data = reminder_service.get_data().await;
-                    let language = &data.user_settings.language;
-                    let text = localization::get_text(language);
                     bot.send_message(msg.chat.id, text.invalid_time_format).await?;
                 }
             }
@@ -257,8 +510,65 @@ async fn receive_reminder_times(
     Ok(())
 }
 
+async fn receive_schedule(
+    bot: Bot,
+    dialogue: MyDialogue,
+    msg: Message,
+    (name, quantity, reminder_times): (String, u32, Vec<NaiveTime>),
+    reminder_service: Arc<ReminderService>,
+) -> HandlerResult {
+    let data = reminder_service.get_user_data(msg.chat.id).await;
+    let language = &data.user_settings.language;
+    let text = localization::get_text(language);
+
+    let Some(schedule) = msg.text().and_then(Schedule::parse) else {
+        bot.send_message(msg.chat.id, text.invalid_schedule).await?;
+        return Ok(());
+    };
+
+    let mut medicine = Medicine::new(name.clone(), quantity, reminder_times.clone());
+    medicine.schedule = schedule.clone();
+
+    if let Err(e) = reminder_service
+        .update_user_data(msg.chat.id, |data| {
+            data.medicines.insert(medicine.id, medicine);
+        })
+        .await {
+        log::error!("Failed to save medicine: {}", e);
+        let error_msg = if matches!(language, Language::Chinese) {
+            "❌ 保存药品信息失败"
+        } else {
+            "❌ Failed to save medicine information"
+        };
+        bot.send_message(msg.chat.id, error_msg).await?;
+        return Ok(());
+    }
+
+    let times_display: Vec<String> = reminder_times
+        .iter()
+        .map(|t| t.format("%H:%M").to_string())
+        .collect();
+
+    let message = format!(
+        "{}\n\n💊 {}：{}\n📦 {}：{}\n⏰ {}：{}\n📅 {}：{}",
+        text.medicine_added,
+        if matches!(language, Language::Chinese) { "名称" } else { "Name" },
+        name,
+        if matches!(language, Language::Chinese) { "数量" } else { "Quantity" },
+        quantity,
+        if matches!(language, Language::Chinese) { "提醒时间" } else { "Reminder times" },
+        times_display.join(", "),
+        if matches!(language, Language::Chinese) { "服药计划" } else { "Schedule" },
+        localization::format_schedule(language, &schedule)
+    );
+
+    bot.send_message(msg.chat.id, message).await?;
+    dialogue.update(State::Start).await?;
+    Ok(())
+}
+
 async fn list_medicines(bot: Bot, msg: Message, reminder_service: Arc<ReminderService>) -> HandlerResult {
-    let data = reminder_service.get_data().await;
+    let data = reminder_service.get_user_data(msg.chat.id).await;
     let language = &data.user_settings.language;
     let text = localization::get_text(language);
 
@@ -267,9 +577,31 @@ async fn list_medicines(bot: Bot, msg: Message, reminder_service: Arc<ReminderSe
         return Ok(());
     }
 
+    let now = Local::now();
     let mut message = format!("{}\n\n", text.medicines_list);
     for (i, medicine) in data.medicines.values().enumerate() {
         let status = if medicine.is_active { "🟢" } else { "🔴" };
+        let supply = localization::format_supply_forecast(language, &data.supply_forecast(medicine, now));
+        let mut notes = medicine.notes.as_ref().map(|notes| format!("📝 {}\n", notes)).unwrap_or_default();
+        if !medicine.is_active {
+            notes.push_str(&format!("{}\n", localization::format_pause_status(language, medicine.paused_until)));
+        }
+
+        if let Some(policy) = medicine.prn_policy() {
+            message.push_str(&format!(
+                "{}. {} {}\n📦 {}：{}\n📉 {}\n📅 {}\n{}\n",
+                i + 1,
+                status,
+                medicine.name,
+                if matches!(language, Language::Chinese) { "数量" } else { "Quantity" },
+                medicine.quantity,
+                supply,
+                localization::format_prn_policy(language, policy),
+                notes
+            ));
+            continue;
+        }
+
         let times: Vec<String> = medicine
             .reminder_times
             .iter()
@@ -277,14 +609,18 @@ async fn list_medicines(bot: Bot, msg: Message, reminder_service: Arc<ReminderSe
             .collect();
 
         message.push_str(&format!(
-            "{}. {} {}\n📦 {}：{}\n⏰ {}：{}\n\n",
+            "{}. {} {}\n📦 {}：{}\n📉 {}\n⏰ {}：{}\n📅 {}：{}\n{}\n",
             i + 1,
             status,
             medicine.name,
             if matches!(language, Language::Chinese) { "数量" } else { "Quantity" },
             medicine.quantity,
+            supply,
             if matches!(language, Language::Chinese) { "提醒时间" } else { "Reminder times" },
-            times.join(", ")
+            times.join(", "),
+            if matches!(language, Language::Chinese) { "服药计划" } else { "Schedule" },
+            localization::format_schedule(language, &medicine.schedule),
+            notes
         ));
     }
 
@@ -293,7 +629,7 @@ async fn list_medicines(bot: Bot, msg: Message, reminder_service: Arc<ReminderSe
 }
 
 async fn delete_medicine(bot: Bot, msg: Message, reminder_service: Arc<ReminderService>) -> HandlerResult {
-    let data = reminder_service.get_data().await;
+    let data = reminder_service.get_user_data(msg.chat.id).await;
     let language = &data.user_settings.language;
     let text = localization::get_text(language);
 
@@ -311,7 +647,7 @@ async fn delete_medicine(bot: Bot, msg: Message, reminder_service: Arc<ReminderS
     for medicine in data.medicines.values() {
         keyboard.push(vec![InlineKeyboardButton::callback(
             format!("🗑️ {}", medicine.name),
-            format!("delete_{}", medicine.id),
+            CallbackData::DeleteMedicine(medicine.id).encode(),
         )]);
     }
 
@@ -323,7 +659,7 @@ async fn delete_medicine(bot: Bot, msg: Message, reminder_service: Arc<ReminderS
 }
 
 async fn refill_medicine(bot: Bot, msg: Message, reminder_service: Arc<ReminderService>) -> HandlerResult {
-    let data = reminder_service.get_data().await;
+    let data = reminder_service.get_user_data(msg.chat.id).await;
     let language = &data.user_settings.language;
     let text = localization::get_text(language);
 
@@ -346,7 +682,7 @@ async fn refill_medicine(bot: Bot, msg: Message, reminder_service: Arc<ReminderS
         };
         keyboard.push(vec![InlineKeyboardButton::callback(
             remaining_text,
-            format!("refill_{}", medicine.id),
+            CallbackData::Refill(medicine.id).encode(),
         )]);
     }
 
@@ -357,8 +693,213 @@ async fn refill_medicine(bot: Bot, msg: Message, reminder_service: Arc<ReminderS
     Ok(())
 }
 
+async fn show_take_selection(bot: Bot, msg: Message, reminder_service: Arc<ReminderService>) -> HandlerResult {
+    let data = reminder_service.get_user_data(msg.chat.id).await;
+    let language = &data.user_settings.language;
+    let text = localization::get_text(language);
+
+    let keyboard: Vec<Vec<InlineKeyboardButton>> = data
+        .medicines
+        .values()
+        .filter(|medicine| medicine.is_active && medicine.prn_policy().is_some())
+        .map(|medicine| {
+            vec![InlineKeyboardButton::callback(
+                format!("💊 {} ({})", medicine.name, medicine.quantity),
+                CallbackData::TakeAsNeeded { medicine_id: medicine.id, force: false }.encode(),
+            )]
+        })
+        .collect();
+
+    if keyboard.is_empty() {
+        bot.send_message(msg.chat.id, text.no_prn_medicines).await?;
+        return Ok(());
+    }
+
+    bot.send_message(msg.chat.id, text.select_medicine_to_take)
+        .reply_markup(InlineKeyboardMarkup::new(keyboard))
+        .await?;
+    Ok(())
+}
+
+async fn show_history(bot: Bot, msg: Message, args: String, reminder_service: Arc<ReminderService>) -> HandlerResult {
+    let data = reminder_service.get_user_data(msg.chat.id).await;
+    let language = &data.user_settings.language;
+    let text = localization::get_text(language);
+
+    let Some(filter) = parse_history_args(&args, &data) else {
+        bot.send_message(msg.chat.id, text.history_usage).await?;
+        return Ok(());
+    };
+
+    let (message, markup) = render_history_page(&data, &filter, 0);
+    bot.send_message(msg.chat.id, message)
+        .reply_markup(markup)
+        .await?;
+    Ok(())
+}
+
+/// 统计默认覆盖的天数
+const DEFAULT_STATS_DAYS: i64 = 7;
+/// 统计最多覆盖的天数
+const MAX_STATS_DAYS: i64 = 365;
+
+// 参数格式：[天数] 或 weekly on|off
+async fn show_stats(bot: Bot, msg: Message, args: String, reminder_service: Arc<ReminderService>) -> HandlerResult {
+    let data = reminder_service.get_user_data(msg.chat.id).await;
+    let language = &data.user_settings.language;
+    let text = localization::get_text(language);
+    let args: Vec<String> = args.split_whitespace().map(|s| s.to_lowercase()).collect();
+
+    let days = match args.iter().map(String::as_str).collect::<Vec<_>>().as_slice() {
+        [] => DEFAULT_STATS_DAYS,
+        ["weekly", toggle @ ("on" | "off")] => {
+            let enabled = *toggle == "on";
+            if let Err(e) = reminder_service
+                .update_user_data(msg.chat.id, |user| user.user_settings.weekly_report = enabled)
+                .await {
+                log::error!("Failed to save weekly report setting: {}", e);
+                bot.send_message(msg.chat.id, "❌ 保存设置失败 / Failed to save settings").await?;
+            } else if enabled {
+                bot.send_message(msg.chat.id, text.weekly_report_enabled).await?;
+            } else {
+                bot.send_message(msg.chat.id, text.weekly_report_disabled).await?;
+            }
+            return Ok(());
+        }
+        [days] => match days.parse::<i64>() {
+            Ok(days) if (1..=MAX_STATS_DAYS).contains(&days) => days,
+            _ => {
+                bot.send_message(msg.chat.id, text.stats_usage).await?;
+                return Ok(());
+            }
+        },
+        _ => {
+            bot.send_message(msg.chat.id, text.stats_usage).await?;
+            return Ok(());
+        }
+    };
+
+    let now = Local::now();
+    let tz = data.timezone();
+    let from = timezone::start_of_day(tz, now.with_timezone(&tz).date_naive() - Duration::days(days - 1));
+    let report = data.adherence_report(from, now, now);
+    bot.send_message(
+        msg.chat.id,
+        localization::format_adherence_report(language, text.stats_title, &report),
+    )
+    .await?;
+    Ok(())
+}
+
+// 参数格式：[药品名称] [开始日期] [结束日期]，只有一个日期时表示当天
+fn parse_history_args(args: &str, data: &UserData) -> Option<HistoryFilter> {
+    let mut dates = Vec::new();
+    let mut name_parts = Vec::new();
+    for token in args.split_whitespace() {
+        match NaiveDate::parse_from_str(token, "%Y-%m-%d") {
+            Ok(date) => dates.push(date),
+            Err(_) => name_parts.push(token),
+        }
+    }
+
+    let (from, to) = match dates.as_slice() {
+        [] => (None, None),
+        [date] => (Some(*date), Some(*date)),
+        [from, to] if from <= to => (Some(*from), Some(*to)),
+        _ => return None,
+    };
+
+    let medicine_id = if name_parts.is_empty() {
+        None
+    } else {
+        // 已删除的药品也可以通过服药记录中的名称查询
+        let name = name_parts.join(" ").to_lowercase();
+        let exact = data
+            .medicines
+            .values()
+            .find(|medicine| medicine.name.to_lowercase() == name)
+            .map(|medicine| medicine.id)
+            .or_else(|| {
+                data.dose_history
+                    .iter()
+                    .find(|event| event.medicine_name.to_lowercase() == name)
+                    .map(|event| event.medicine_id)
+            });
+        let partial = || {
+            data.medicines
+                .values()
+                .find(|medicine| medicine.name.to_lowercase().contains(&name))
+                .map(|medicine| medicine.id)
+        };
+        Some(exact.or_else(partial)?)
+    };
+
+    Some(HistoryFilter { medicine_id, from, to })
+}
+
+fn render_history_page(data: &UserData, filter: &HistoryFilter, page: usize) -> (String, InlineKeyboardMarkup) {
+    let language = &data.user_settings.language;
+    let text = localization::get_text(language);
+
+    let tz = data.timezone();
+    let (events, total_pages) = query_history(&data.dose_history, filter, tz, page, HISTORY_PAGE_SIZE);
+    let page = page.min(total_pages.saturating_sub(1));
+
+    let mut message = text.history_title.to_string();
+    if total_pages > 1 {
+        message.push_str(&if matches!(language, Language::Chinese) {
+            format!("（第{}/{}页）", page + 1, total_pages)
+        } else {
+            format!(" (page {}/{})", page + 1, total_pages)
+        });
+    }
+    if filter.from.is_some() {
+        message.push_str(&format!(
+            "\n📅 {} ~ {}",
+            filter.from.map(|d| d.to_string()).unwrap_or_default(),
+            filter.to.map(|d| d.to_string()).unwrap_or_default()
+        ));
+    }
+    message.push_str("\n\n");
+
+    if events.is_empty() {
+        message.push_str(text.no_history);
+    }
+    for event in &events {
+        message.push_str(&localization::format_dose_event(language, tz, event));
+        message.push('\n');
+    }
+
+    let mut keyboard = Vec::new();
+    let mut navigation = Vec::new();
+    if page > 0 {
+        navigation.push(InlineKeyboardButton::callback(text.prev_page_button, CallbackData::History { filter: filter.clone(), page: page - 1 }.encode()));
+    }
+    if page + 1 < total_pages {
+        navigation.push(InlineKeyboardButton::callback(text.next_page_button, CallbackData::History { filter: filter.clone(), page: page + 1 }.encode()));
+    }
+    if !navigation.is_empty() {
+        keyboard.push(navigation);
+    }
+
+    if filter.medicine_id.is_some() {
+        let all = HistoryFilter { medicine_id: None, ..filter.clone() };
+        keyboard.push(vec![InlineKeyboardButton::callback(text.all_medicines_button, CallbackData::History { filter: all, page: 0 }.encode())]);
+    } else {
+        for medicine in data.medicines.values() {
+            let by_medicine = HistoryFilter { medicine_id: Some(medicine.id), ..filter.clone() };
+            keyboard.push(vec![InlineKeyboardButton::callback(
+                format!("💊 {}", medicine.name),
+                CallbackData::History { filter: by_medicine.clone(), page: 0 }.encode(),
+            )]);
+        }
+    }
+
+    (message, InlineKeyboardMarkup::new(keyboard))
+}
+
 async fn show_pending(bot: Bot, msg: Message, reminder_service: Arc<ReminderService>) -> HandlerResult {
-    let data = reminder_service.get_data().await;
+    let data = reminder_service.get_user_data(msg.chat.id).await;
     let language = &data.user_settings.language;
     let text = localization::get_text(language);
 
@@ -380,7 +921,7 @@ async fn show_pending(bot: Bot, msg: Message, reminder_service: Arc<ReminderServ
             i + 1,
             reminder.medicine_name,
             if matches!(language, Language::Chinese) { "时间" } else { "Time" },
-            reminder.scheduled_time.format("%H:%M"),
+            reminder.scheduled_time.with_timezone(&data.timezone()).format("%H:%M"),
             if matches!(language, Language::Chinese) { "提醒次数" } else { "Reminder count" },
             reminder.reminder_count
         ));
@@ -390,191 +931,1350 @@ async fn show_pending(bot: Bot, msg: Message, reminder_service: Arc<ReminderServ
     Ok(())
 }
 
-async fn handle_callback(bot: Bot, q: CallbackQuery, reminder_service: Arc<ReminderService>, dialogue: MyDialogue) -> HandlerResult {
-    if let Some(data) = &q.data {
-
-        if let Some(chat_id) = q.message.as_ref().map(|m| m.chat.id) {
-            if data.starts_with("lang_") {
-                let new_language = if data == "lang_chinese" {
-                    Language::Chinese
-                } else if data == "lang_english" {
-                    Language::English
-                } else {
-                    return Ok(());
-                };
+async fn show_skip_selection(bot: Bot, msg: Message, reminder_service: Arc<ReminderService>) -> HandlerResult {
+    let data = reminder_service.get_user_data(msg.chat.id).await;
+    let text = localization::get_text(&data.user_settings.language);
+    let tz = data.timezone();
 
-                if let Err(e) = reminder_service
-                    .update_data(|app_data| {
-                        app_data.user_settings.language = new_language.clone();
-                    })
-                    .await {
-                    log::error!("Failed to update language: {}", e);
-                    bot.send_message(chat_id, "❌ Failed to update language / 更新语言失败").await?;
-                } else {
-                    let text = localization::get_text(&new_language);
-                    bot.send_message(chat_id, text.language_changed).await?;
-                }
-            } else if data.starts_with("confirm_") {
-                let reminder_id = data.strip_prefix("confirm_").unwrap();
-                if let Ok(_uuid) = Uuid::parse_str(reminder_id) {
-                    let current_data = reminder_service.get_data().await;
-                    let language = &current_data.user_settings.language;
-                    let text = localization::get_text(language);
+    let mut pending: Vec<_> = data.pending_reminders.values().filter(|r| !r.is_confirmed).collect();
+    if pending.is_empty() {
+        bot.send_message(msg.chat.id, text.no_pending_reminders).await?;
+        return Ok(());
+    }
+    pending.sort_by_key(|reminder| reminder.scheduled_time);
 
-                    // 显示数量选择界面
-                    let keyboard = vec![
-                        vec![
-                            InlineKeyboardButton::callback(
-                                format!("1{}", text.pills_unit),
-                                format!("dose_1_{}", reminder_id)
-                            ),
-                            InlineKeyboardButton::callback(
-                                format!("2{}", text.pills_unit),
-                                format!("dose_2_{}", reminder_id)
-                            ),
-                            InlineKeyboardButton::callback(
-                                format!("3{}", text.pills_unit),
-                                format!("dose_3_{}", reminder_id)
-                            ),
-                        ],
-                        vec![
-                            InlineKeyboardButton::callback(text.custom_amount_button, format!("dose_custom_{}", reminder_id)),
-                        ],
-                    ];
-                    let markup = InlineKeyboardMarkup::new(keyboard);
-                    bot.send_message(chat_id, text.select_dose_amount)
-                        .reply_markup(markup)
-                        .await?;
-                }
-            } else if data.starts_with("snooze_") {
-                let reminder_id = data.strip_prefix("snooze_").unwrap();
-                if let Ok(uuid) = Uuid::parse_str(reminder_id) {
-                    match reminder_service.snooze_reminder(uuid).await {
-                        Ok(response) => {
-                            bot.send_message(chat_id, response).await?;
-                        }
-                        Err(error) => {
-                            bot.send_message(chat_id, format!("❌ 错误：{}", error)).await?;
-                        }
-                    }
-                }
-            } else if data.starts_with("delete_") {
-                let medicine_id = data.strip_prefix("delete_").unwrap();
-                if let Ok(uuid) = Uuid::parse_str(medicine_id) {
-                    if let Err(e) = reminder_service
-                        .update_data(|app_data| {
-                            app_data.medicines.remove(&uuid);
-                        })
-                        .await {
-                        log::error!("Failed to delete medicine: {}", e);
-                        bot.send_message(chat_id, "❌ 删除药品失败").await?;
-                    } else {
-                        bot.send_message(chat_id, "✅ 药品已删除").await?;
-                    }
-                }
-            } else if data.starts_with("refill_") {
-                let medicine_id = data.strip_prefix("refill_").unwrap();
-                if let Ok(_uuid) = Uuid::parse_str(medicine_id) {
-                    let current_data = reminder_service.get_data().await;
-                    let language = &current_data.user_settings.language;
-                    let text = localization::get_text(language);
+    let keyboard: Vec<Vec<InlineKeyboardButton>> = pending
+        .iter()
+        .map(|reminder| {
+            vec![InlineKeyboardButton::callback(
+                format!(
+                    "💊 {} ({})",
+                    reminder.medicine_name,
+                    reminder.scheduled_time.with_timezone(&tz).format("%m-%d %H:%M")
+                ),
+                CallbackData::Skip(reminder.id).encode(),
+            )]
+        })
+        .collect();
 
-                    // 显示数量选择界面
-                    let keyboard = vec![
-                        vec![
-                            InlineKeyboardButton::callback(
-                                format!("10{}", text.pieces_unit),
-                                format!("refill_10_{}", medicine_id)
-                            ),
-                            InlineKeyboardButton::callback(
-                                format!("20{}", text.pieces_unit),
-                                format!("refill_20_{}", medicine_id)
-                            ),
-                            InlineKeyboardButton::callback(
-                                format!("30{}", text.pieces_unit),
-                                format!("refill_30_{}", medicine_id)
-                            ),
-                        ],
-                        vec![
-                            InlineKeyboardButton::callback(text.custom_amount_button, format!("refill_custom_{}", medicine_id)),
-                        ],
-                    ];
-                    let markup = InlineKeyboardMarkup::new(keyboard);
-                    bot.send_message(chat_id, text.enter_refill_amount)
-                        .reply_markup(markup)
-                        .await?;
-                }
-            } else if data.starts_with("dose_") {
-                // 处理服药数量选择
-                if data.starts_with("dose_custom_") {
-                    let reminder_id = data.strip_prefix("dose_custom_").unwrap();
-                    let current_data = reminder_service.get_data().await;
-                    let language = &current_data.user_settings.language;
-                    let text = localization::get_text(language);
+    bot.send_message(msg.chat.id, text.select_skip_reminder)
+        .reply_markup(InlineKeyboardMarkup::new(keyboard))
+        .await?;
+    Ok(())
+}
 
-                    bot.send_message(chat_id, text.enter_custom_amount).await?;
-                    dialogue.update(State::ReceiveConfirmDoseAmount {
-                        reminder_id: reminder_id.to_string()
-                    }).await?;
-                } else {
-                    // 处理预设数量
-                    let parts: Vec<&str> = data.splitn(3, '_').collect();
-                    if parts.len() == 3 {
-                        if let (Ok(amount), Ok(uuid)) = (parts[1].parse::<u32>(), Uuid::parse_str(parts[2])) {
-                            match reminder_service.confirm_medicine_with_amount(uuid, amount).await {
-                                Ok(response) => {
-                                    bot.send_message(chat_id, response).await?;
-                                }
-                                Err(error) => {
-                                    bot.send_message(chat_id, format!("❌ 错误：{}", error)).await?;
-                                }
-                            }
-                        }
-                    }
-                }
-            } else if data.starts_with("refill_") && data.contains("_") {
-                // 处理补充数量选择
-                if data.starts_with("refill_custom_") {
-                    let medicine_id = data.strip_prefix("refill_custom_").unwrap();
-                    let current_data = reminder_service.get_data().await;
-                    let language = &current_data.user_settings.language;
-                    let text = localization::get_text(language);
+async fn show_caregiver_selection(bot: Bot, msg: Message, reminder_service: Arc<ReminderService>) -> HandlerResult {
+    let data = reminder_service.get_user_data(msg.chat.id).await;
+    let language = &data.user_settings.language;
+    let text = localization::get_text(language);
 
-                    bot.send_message(chat_id, text.enter_refill_amount).await?;
-                    dialogue.update(State::ReceiveRefillAmount {
-                        medicine_id: medicine_id.to_string()
-                    }).await?;
-                } else {
-                    // 处理预设数量
-                    let parts: Vec<&str> = data.splitn(3, '_').collect();
-                    if parts.len() == 3 && parts[0] == "refill" {
-                        if let (Ok(amount), Ok(uuid)) = (parts[1].parse::<u32>(), Uuid::parse_str(parts[2])) {
-                            if let Err(e) = reminder_service
-                                .update_data(|app_data| {
-                                    if let Some(medicine) = app_data.medicines.get_mut(&uuid) {
-                                        medicine.add_quantity(amount);
-                                    }
-                                })
-                                .await {
-                                log::error!("Failed to refill medicine: {}", e);
-                                bot.send_message(chat_id, "❌ 补充药品失败").await?;
-                            } else {
-                                bot.send_message(chat_id, format!("✅ 已补充{}个药品", amount)).await?;
-                            }
-                        }
-                    }
-                }
-            }
-        }
+    let describe = |caregiver: Option<&Caregiver>| match caregiver {
+        Some(caregiver) => format!("{} ({})", caregiver.chat_id, caregiver.escalate_after),
+        None => "-".to_string(),
+    };
+
+    let mut keyboard = vec![vec![InlineKeyboardButton::callback(
+        format!("{} [{}]", text.all_medicines_button, describe(data.user_settings.caregiver.as_ref())),
+        CallbackData::Caregiver(None).encode(),
+    )]];
+    for medicine in data.medicines.values() {
+        keyboard.push(vec![InlineKeyboardButton::callback(
+            format!("💊 {} [{}]", medicine.name, describe(medicine.caregiver.as_ref())),
+            CallbackData::Caregiver(Some(medicine.id)).encode(),
+        )]);
     }
 
-    bot.answer_callback_query(q.id).await?;
+    bot.send_message(msg.chat.id, text.select_caregiver_target)
+        .reply_markup(InlineKeyboardMarkup::new(keyboard))
+        .await?;
     Ok(())
 }
 
-async fn receive_confirm_dose_amount(
-    bot: Bot,
-    dialogue: MyDialogue,
+async fn show_stock_selection(bot: Bot, msg: Message, reminder_service: Arc<ReminderService>) -> HandlerResult {
+    let data = reminder_service.get_user_data(msg.chat.id).await;
+    let language = &data.user_settings.language;
+    let text = localization::get_text(language);
+
+    if data.medicines.is_empty() {
+        bot.send_message(msg.chat.id, text.no_medicines).await?;
+        return Ok(());
+    }
+
+    let keyboard: Vec<Vec<InlineKeyboardButton>> = data
+        .medicines
+        .values()
+        .map(|medicine| {
+            vec![InlineKeyboardButton::callback(
+                format!(
+                    "💊 {} [{}]",
+                    medicine.name,
+                    localization::format_stock_alert_settings(language, &medicine.stock_alert)
+                ),
+                CallbackData::StockAlert(medicine.id).encode(),
+            )]
+        })
+        .collect();
+
+    bot.send_message(msg.chat.id, text.select_stock_medicine)
+        .reply_markup(InlineKeyboardMarkup::new(keyboard))
+        .await?;
+    Ok(())
+}
+
+async fn receive_stock_alert(
+    bot: Bot,
+    dialogue: MyDialogue,
+    msg: Message,
+    medicine_id: String,
+    reminder_service: Arc<ReminderService>,
+) -> HandlerResult {
+    let data = reminder_service.get_user_data(msg.chat.id).await;
+    let language = &data.user_settings.language;
+    let text = localization::get_text(language);
+
+    let Some(stock_alert) = StockAlert::parse(msg.text().unwrap_or_default()) else {
+        bot.send_message(msg.chat.id, text.invalid_stock_alert).await?;
+        return Ok(());
+    };
+    let Ok(uuid) = Uuid::parse_str(&medicine_id) else {
+        bot.send_message(msg.chat.id, text.medicine_not_found).await?;
+        dialogue.update(State::Start).await?;
+        return Ok(());
+    };
+
+    if let Err(e) = reminder_service
+        .update_user_data(msg.chat.id, |user| {
+            if let Some(medicine) = user.medicines.get_mut(&uuid) {
+                medicine.stock_alert = stock_alert;
+                // 新的设置下重新判断是否需要提醒
+                medicine.low_stock_notified = false;
+            }
+        })
+        .await {
+        log::error!("Failed to save stock alert: {}", e);
+        bot.send_message(msg.chat.id, "❌ 保存设置失败 / Failed to save settings").await?;
+    } else {
+        bot.send_message(msg.chat.id, text.stock_alert_saved).await?;
+    }
+
+    dialogue.update(State::Start).await?;
+    Ok(())
+}
+
+// 参数格式：空（显示当前时区）、IANA 时区名称或 default
+async fn set_timezone(bot: Bot, msg: Message, args: String, reminder_service: Arc<ReminderService>) -> HandlerResult {
+    let data = reminder_service.get_user_data(msg.chat.id).await;
+    let language = &data.user_settings.language;
+    let text = localization::get_text(language);
+    let args = args.trim();
+
+    if args.is_empty() {
+        let note = if data.user_settings.timezone.is_none() { text.server_timezone_note } else { "" };
+        bot.send_message(
+            msg.chat.id,
+            format!("{}：{}{}\n\n{}", text.current_timezone, data.timezone(), note, text.timezone_usage),
+        )
+        .await?;
+        return Ok(());
+    }
+
+    let selected = if args.eq_ignore_ascii_case("default") {
+        None
+    } else {
+        match timezone::parse_timezone(args) {
+            Some(tz) => Some(tz),
+            None => {
+                bot.send_message(msg.chat.id, text.invalid_timezone).await?;
+                return Ok(());
+            }
+        }
+    };
+
+    if let Err(e) = reminder_service
+        .update_user_data(msg.chat.id, |user| user.user_settings.timezone = selected)
+        .await {
+        log::error!("Failed to save timezone: {}", e);
+        bot.send_message(msg.chat.id, "❌ 保存设置失败 / Failed to save settings").await?;
+    } else {
+        let tz = selected.unwrap_or_else(timezone::server_timezone);
+        let note = if selected.is_none() { text.server_timezone_note } else { "" };
+        bot.send_message(msg.chat.id, format!("{} {}{}", text.timezone_saved, tz, note)).await?;
+    }
+    Ok(())
+}
+
+async fn show_travel(bot: Bot, dialogue: MyDialogue, msg: Message, reminder_service: Arc<ReminderService>) -> HandlerResult {
+    let data = reminder_service.get_user_data(msg.chat.id).await;
+    let language = &data.user_settings.language;
+    let text = localization::get_text(language);
+
+    if let Some(plan) = &data.user_settings.travel {
+        let keyboard = InlineKeyboardMarkup::new(vec![vec![InlineKeyboardButton::callback(
+            text.travel_cancel_button,
+            CallbackData::TravelCancel.encode(),
+        )]]);
+        bot.send_message(msg.chat.id, localization::format_travel_plan(language, plan, &data.medicines))
+            .reply_markup(keyboard)
+            .await?;
+        return Ok(());
+    }
+
+    if data.medicines.values().all(|medicine| medicine.prn_policy().is_some()) {
+        bot.send_message(msg.chat.id, text.no_medicines).await?;
+        return Ok(());
+    }
+
+    bot.send_message(msg.chat.id, text.travel_enter_destination).await?;
+    dialogue.update(State::ReceiveTravelDestination).await?;
+    Ok(())
+}
+
+async fn receive_travel_destination(
+    bot: Bot,
+    dialogue: MyDialogue,
+    msg: Message,
+    reminder_service: Arc<ReminderService>,
+) -> HandlerResult {
+    let data = reminder_service.get_user_data(msg.chat.id).await;
+    let text = localization::get_text(&data.user_settings.language);
+
+    match timezone::parse_timezone(msg.text().unwrap_or_default()) {
+        Some(destination) => {
+            bot.send_message(msg.chat.id, text.travel_enter_dates).await?;
+            dialogue
+                .update(State::ReceiveTravelDates { destination: destination.name().to_string() })
+                .await?;
+        }
+        None => {
+            bot.send_message(msg.chat.id, text.invalid_timezone).await?;
+        }
+    }
+    Ok(())
+}
+
+async fn receive_travel_dates(
+    bot: Bot,
+    dialogue: MyDialogue,
+    msg: Message,
+    destination: String,
+    reminder_service: Arc<ReminderService>,
+) -> HandlerResult {
+    let data = reminder_service.get_user_data(msg.chat.id).await;
+    let language = &data.user_settings.language;
+    let text = localization::get_text(language);
+    let home_timezone = data.timezone();
+    let today = Local::now().with_timezone(&home_timezone).date_naive();
+
+    let (Some(destination_tz), Some((depart_date, return_date, step_minutes))) = (
+        timezone::parse_timezone(&destination),
+        TravelPlan::parse_dates(msg.text().unwrap_or_default()),
+    ) else {
+        bot.send_message(msg.chat.id, text.invalid_travel_dates).await?;
+        return Ok(());
+    };
+    if depart_date < today {
+        bot.send_message(msg.chat.id, text.invalid_travel_dates).await?;
+        return Ok(());
+    }
+
+    let plan = TravelPlan::new(
+        data.user_settings.timezone,
+        home_timezone,
+        destination_tz,
+        depart_date,
+        return_date,
+        step_minutes,
+    );
+    if plan.time_difference_minutes() == 0 {
+        bot.send_message(msg.chat.id, text.travel_no_difference).await?;
+        dialogue.update(State::Start).await?;
+        return Ok(());
+    }
+
+    let keyboard = InlineKeyboardMarkup::new(vec![vec![
+        InlineKeyboardButton::callback(text.travel_confirm_button, CallbackData::TravelConfirm.encode()),
+        InlineKeyboardButton::callback(text.travel_discard_button, CallbackData::TravelDiscard.encode()),
+    ]]);
+    bot.send_message(msg.chat.id, localization::format_travel_plan(language, &plan, &data.medicines))
+        .reply_markup(keyboard)
+        .await?;
+    dialogue
+        .update(State::ConfirmTravel { destination, depart_date, return_date, step_minutes })
+        .await?;
+    Ok(())
+}
+
+// 等待确认时收到其他消息，视为放弃
+async fn discard_travel_plan(bot: Bot, dialogue: MyDialogue, msg: Message, reminder_service: Arc<ReminderService>) -> HandlerResult {
+    let data = reminder_service.get_user_data(msg.chat.id).await;
+    let text = localization::get_text(&data.user_settings.language);
+
+    bot.send_message(msg.chat.id, text.travel_discarded).await?;
+    dialogue.update(State::Start).await?;
+    Ok(())
+}
+
+/// 宽限时间最多一天
+const MAX_GRACE_MINUTES: u32 = 24 * 60;
+
+async fn show_grace_selection(bot: Bot, msg: Message, reminder_service: Arc<ReminderService>) -> HandlerResult {
+    let data = reminder_service.get_user_data(msg.chat.id).await;
+    let language = &data.user_settings.language;
+    let text = localization::get_text(language);
+
+    if data.medicines.is_empty() {
+        bot.send_message(msg.chat.id, text.no_medicines).await?;
+        return Ok(());
+    }
+
+    let keyboard: Vec<Vec<InlineKeyboardButton>> = data
+        .medicines
+        .values()
+        .filter(|medicine| medicine.prn_policy().is_none())
+        .map(|medicine| {
+            let minutes = if matches!(language, Language::Chinese) {
+                format!("{}分钟", medicine.grace_minutes)
+            } else {
+                format!("{} min", medicine.grace_minutes)
+            };
+            vec![InlineKeyboardButton::callback(
+                format!("💊 {} [{}]", medicine.name, minutes),
+                CallbackData::Grace(medicine.id).encode(),
+            )]
+        })
+        .collect();
+
+    if keyboard.is_empty() {
+        bot.send_message(msg.chat.id, text.no_medicines).await?;
+        return Ok(());
+    }
+
+    bot.send_message(msg.chat.id, text.select_grace_medicine)
+        .reply_markup(InlineKeyboardMarkup::new(keyboard))
+        .await?;
+    Ok(())
+}
+
+async fn receive_grace_minutes(
+    bot: Bot,
+    dialogue: MyDialogue,
+    msg: Message,
+    medicine_id: String,
+    reminder_service: Arc<ReminderService>,
+) -> HandlerResult {
+    let data = reminder_service.get_user_data(msg.chat.id).await;
+    let language = &data.user_settings.language;
+    let text = localization::get_text(language);
+
+    let minutes = match msg.text().unwrap_or_default().trim().parse::<u32>() {
+        Ok(minutes) if minutes <= MAX_GRACE_MINUTES => minutes,
+        _ => {
+            bot.send_message(msg.chat.id, text.invalid_grace_minutes).await?;
+            return Ok(());
+        }
+    };
+    let Ok(uuid) = Uuid::parse_str(&medicine_id) else {
+        bot.send_message(msg.chat.id, text.medicine_not_found).await?;
+        dialogue.update(State::Start).await?;
+        return Ok(());
+    };
+
+    if let Err(e) = reminder_service
+        .update_user_data(msg.chat.id, |user| {
+            if let Some(medicine) = user.medicines.get_mut(&uuid) {
+                medicine.grace_minutes = minutes;
+            }
+        })
+        .await {
+        log::error!("Failed to save grace window: {}", e);
+        bot.send_message(msg.chat.id, "❌ 保存设置失败 / Failed to save settings").await?;
+    } else {
+        bot.send_message(msg.chat.id, text.grace_saved).await?;
+    }
+
+    dialogue.update(State::Start).await?;
+    Ok(())
+}
+
+/// 提醒最多一周后过期
+const MAX_EXPIRY_MINUTES: u32 = 7 * 24 * 60;
+
+async fn show_expiry_selection(bot: Bot, msg: Message, reminder_service: Arc<ReminderService>) -> HandlerResult {
+    let data = reminder_service.get_user_data(msg.chat.id).await;
+    let language = &data.user_settings.language;
+    let text = localization::get_text(language);
+
+    let keyboard: Vec<Vec<InlineKeyboardButton>> = data
+        .medicines
+        .values()
+        .filter(|medicine| medicine.prn_policy().is_none())
+        .map(|medicine| {
+            vec![InlineKeyboardButton::callback(
+                format!(
+                    "💊 {} [{}]",
+                    medicine.name,
+                    localization::format_reminder_expiry(language, medicine.expire_after_minutes)
+                ),
+                CallbackData::Expiry(medicine.id).encode(),
+            )]
+        })
+        .collect();
+
+    if keyboard.is_empty() {
+        bot.send_message(msg.chat.id, text.no_medicines).await?;
+        return Ok(());
+    }
+
+    bot.send_message(msg.chat.id, text.select_expiry_medicine)
+        .reply_markup(InlineKeyboardMarkup::new(keyboard))
+        .await?;
+    Ok(())
+}
+
+async fn receive_expiry_minutes(
+    bot: Bot,
+    dialogue: MyDialogue,
+    msg: Message,
+    medicine_id: String,
+    reminder_service: Arc<ReminderService>,
+) -> HandlerResult {
+    let data = reminder_service.get_user_data(msg.chat.id).await;
+    let language = &data.user_settings.language;
+    let text = localization::get_text(language);
+
+    let input = msg.text().unwrap_or_default().trim();
+    let expire_after_minutes = if input.eq_ignore_ascii_case("next") || input == "下次" {
+        None
+    } else {
+        match input.parse::<u32>() {
+            Ok(minutes) if (1..=MAX_EXPIRY_MINUTES).contains(&minutes) => Some(minutes),
+            _ => {
+                bot.send_message(msg.chat.id, text.invalid_expiry_minutes).await?;
+                return Ok(());
+            }
+        }
+    };
+    let Ok(uuid) = Uuid::parse_str(&medicine_id) else {
+        bot.send_message(msg.chat.id, text.medicine_not_found).await?;
+        dialogue.update(State::Start).await?;
+        return Ok(());
+    };
+
+    if let Err(e) = reminder_service
+        .update_user_data(msg.chat.id, |user| {
+            if let Some(medicine) = user.medicines.get_mut(&uuid) {
+                medicine.expire_after_minutes = expire_after_minutes;
+            }
+        })
+        .await {
+        log::error!("Failed to save reminder expiry: {}", e);
+        bot.send_message(msg.chat.id, "❌ 保存设置失败 / Failed to save settings").await?;
+    } else {
+        bot.send_message(msg.chat.id, text.expiry_saved).await?;
+    }
+
+    dialogue.update(State::Start).await?;
+    Ok(())
+}
+
+async fn show_escalation_selection(bot: Bot, msg: Message, reminder_service: Arc<ReminderService>) -> HandlerResult {
+    let data = reminder_service.get_user_data(msg.chat.id).await;
+    let language = &data.user_settings.language;
+    let text = localization::get_text(language);
+
+    let keyboard: Vec<Vec<InlineKeyboardButton>> = data
+        .medicines
+        .values()
+        .filter(|medicine| medicine.prn_policy().is_none())
+        .map(|medicine| {
+            vec![InlineKeyboardButton::callback(
+                format!(
+                    "💊 {} [{}]",
+                    medicine.name,
+                    localization::format_escalation_policy(language, &medicine.escalation)
+                ),
+                CallbackData::Escalation(medicine.id).encode(),
+            )]
+        })
+        .collect();
+
+    if keyboard.is_empty() {
+        bot.send_message(msg.chat.id, text.no_medicines).await?;
+        return Ok(());
+    }
+
+    bot.send_message(msg.chat.id, text.select_escalation_medicine)
+        .reply_markup(InlineKeyboardMarkup::new(keyboard))
+        .await?;
+    Ok(())
+}
+
+async fn receive_escalation_policy(
+    bot: Bot,
+    dialogue: MyDialogue,
+    msg: Message,
+    medicine_id: String,
+    reminder_service: Arc<ReminderService>,
+) -> HandlerResult {
+    let data = reminder_service.get_user_data(msg.chat.id).await;
+    let language = &data.user_settings.language;
+    let text = localization::get_text(language);
+
+    let input = msg.text().unwrap_or_default().trim();
+    let policy = if input.eq_ignore_ascii_case("default") || input == "默认" {
+        EscalationPolicy::default()
+    } else {
+        match EscalationPolicy::parse(input) {
+            Some(policy) => policy,
+            None => {
+                bot.send_message(msg.chat.id, text.invalid_escalation_policy).await?;
+                return Ok(());
+            }
+        }
+    };
+    let Ok(uuid) = Uuid::parse_str(&medicine_id) else {
+        bot.send_message(msg.chat.id, text.medicine_not_found).await?;
+        dialogue.update(State::Start).await?;
+        return Ok(());
+    };
+
+    if let Err(e) = reminder_service
+        .update_user_data(msg.chat.id, |user| {
+            if let Some(medicine) = user.medicines.get_mut(&uuid) {
+                medicine.escalation = policy;
+            }
+        })
+        .await {
+        log::error!("Failed to save escalation policy: {}", e);
+        bot.send_message(msg.chat.id, "❌ 保存设置失败 / Failed to save settings").await?;
+    } else {
+        bot.send_message(msg.chat.id, text.escalation_saved).await?;
+    }
+
+    dialogue.update(State::Start).await?;
+    Ok(())
+}
+
+async fn receive_caregiver(
+    bot: Bot,
+    dialogue: MyDialogue,
+    msg: Message,
+    medicine_id: Option<String>,
+    reminder_service: Arc<ReminderService>,
+) -> HandlerResult {
+    let data = reminder_service.get_user_data(msg.chat.id).await;
+    let language = &data.user_settings.language;
+    let text = localization::get_text(language);
+
+    let input = msg.text().unwrap_or_default().trim();
+    let caregiver = if input.eq_ignore_ascii_case("off") {
+        None
+    } else {
+        let mut parts = input.split_whitespace();
+        let chat_id = parts.next().and_then(|s| s.parse::<i64>().ok());
+        let escalate_after = match parts.next() {
+            Some(s) => s.parse::<u32>().ok().filter(|&n| n > 0),
+            None => Some(DEFAULT_ESCALATE_AFTER),
+        };
+
+        match (chat_id, escalate_after, parts.next()) {
+            (Some(chat_id), Some(escalate_after), None) => Some(Caregiver {
+                chat_id,
+                escalate_after,
+                patient_name: msg.from().map(|user| user.full_name()),
+            }),
+            _ => {
+                bot.send_message(msg.chat.id, text.invalid_caregiver).await?;
+                return Ok(());
+            }
+        }
+    };
+
+    let medicine_uuid = medicine_id.as_deref().and_then(|id| Uuid::parse_str(id).ok());
+    let is_removed = caregiver.is_none();
+    if let Err(e) = reminder_service
+        .update_user_data(msg.chat.id, |user| match medicine_uuid {
+            Some(uuid) => {
+                if let Some(medicine) = user.medicines.get_mut(&uuid) {
+                    medicine.caregiver = caregiver;
+                }
+            }
+            None => user.user_settings.caregiver = caregiver,
+        })
+        .await {
+        log::error!("Failed to save caregiver: {}", e);
+        bot.send_message(msg.chat.id, "❌ 保存照护人失败 / Failed to save caregiver").await?;
+    } else if is_removed {
+        bot.send_message(msg.chat.id, text.caregiver_removed).await?;
+    } else {
+        bot.send_message(msg.chat.id, text.caregiver_set).await?;
+    }
+
+    dialogue.update(State::Start).await?;
+    Ok(())
+}
+
+async fn show_edit_selection(bot: Bot, msg: Message, reminder_service: Arc<ReminderService>) -> HandlerResult {
+    let data = reminder_service.get_user_data(msg.chat.id).await;
+    let text = localization::get_text(&data.user_settings.language);
+
+    if data.medicines.is_empty() {
+        bot.send_message(msg.chat.id, text.no_medicines).await?;
+        return Ok(());
+    }
+
+    let keyboard: Vec<Vec<InlineKeyboardButton>> = data
+        .medicines
+        .values()
+        .map(|medicine| {
+            vec![InlineKeyboardButton::callback(
+                format!("💊 {}", medicine.name),
+                CallbackData::EditMedicine(medicine.id).encode(),
+            )]
+        })
+        .collect();
+
+    bot.send_message(msg.chat.id, text.select_edit_medicine)
+        .reply_markup(InlineKeyboardMarkup::new(keyboard))
+        .await?;
+    Ok(())
+}
+
+// 药品可修改的字段，每行两个
+fn edit_field_keyboard(language: &Language, medicine: &Medicine) -> InlineKeyboardMarkup {
+    let fields: Vec<EditField> = EditField::ALL
+        .into_iter()
+        .filter(|field| field.applies_to(medicine))
+        .collect();
+    let keyboard: Vec<Vec<InlineKeyboardButton>> = fields
+        .chunks(2)
+        .map(|fields| {
+            fields
+                .iter()
+                .map(|&field| {
+                    InlineKeyboardButton::callback(
+                        localization::format_edit_field(language, field),
+                        CallbackData::EditField { medicine_id: medicine.id, field }.encode(),
+                    )
+                })
+                .collect()
+        })
+        .collect();
+    InlineKeyboardMarkup::new(keyboard)
+}
+
+async fn receive_edit_value(
+    bot: Bot,
+    dialogue: MyDialogue,
+    msg: Message,
+    (medicine_id, field): (String, EditField),
+    reminder_service: Arc<ReminderService>,
+) -> HandlerResult {
+    let language = reminder_service.get_user_data(msg.chat.id).await.user_settings.language;
+    let text = localization::get_text(&language);
+    let input = msg.text().unwrap_or_default();
+
+    // 在最新的数据上修改，避免覆盖期间的其他变化或恢复已删除的药品
+    let mut result = Err(text.medicine_not_found);
+    let saved = reminder_service
+        .update_user_data(msg.chat.id, |user| {
+            if field == EditField::Times && user.is_travelling() {
+                result = Err(text.edit_times_while_travelling);
+                return;
+            }
+            let Some(medicine) = Uuid::parse_str(&medicine_id)
+                .ok()
+                .and_then(|id| user.medicines.get_mut(&id))
+            else {
+                return;
+            };
+            if !medicine.apply_edit(field, input) {
+                result = Err(text.invalid_edit_value);
+                return;
+            }
+            let medicine = medicine.clone();
+            // 改名时同步更新未处理的提醒，服药记录保留原来的名称
+            if field == EditField::Name {
+                for reminder in user.pending_reminders.values_mut() {
+                    if reminder.medicine_id == medicine.id && !reminder.is_confirmed {
+                        reminder.medicine_name = medicine.name.clone();
+                    }
+                }
+            }
+            result = Ok(medicine);
+        })
+        .await;
+
+    match (saved, result) {
+        (Err(e), _) => {
+            log::error!("Failed to save medicine: {}", e);
+            bot.send_message(msg.chat.id, "❌ 保存设置失败 / Failed to save settings").await?;
+        }
+        (Ok(()), Ok(medicine)) => {
+            let message = format!(
+                "{}\n\n💊 {}\n✏️ {}：{}",
+                text.medicine_updated,
+                medicine.name,
+                localization::format_edit_field(&language, field),
+                localization::format_edit_value(&language, &medicine, field)
+            );
+            // 保留字段菜单，方便继续修改其他项目
+            bot.send_message(msg.chat.id, message)
+                .reply_markup(edit_field_keyboard(&language, &medicine))
+                .await?;
+        }
+        (Ok(()), Err(error)) => {
+            bot.send_message(msg.chat.id, error).await?;
+            // 输入无效时保持当前状态，等待重新输入
+            if error == text.invalid_edit_value {
+                return Ok(());
+            }
+        }
+    }
+
+    dialogue.update(State::Start).await?;
+    Ok(())
+}
+
+async fn show_pause_selection(bot: Bot, msg: Message, reminder_service: Arc<ReminderService>) -> HandlerResult {
+    let data = reminder_service.get_user_data(msg.chat.id).await;
+    let text = localization::get_text(&data.user_settings.language);
+
+    let keyboard: Vec<Vec<InlineKeyboardButton>> = data
+        .medicines
+        .values()
+        .filter(|medicine| medicine.is_active)
+        .map(|medicine| {
+            vec![InlineKeyboardButton::callback(
+                format!("💊 {}", medicine.name),
+                CallbackData::Pause(medicine.id).encode(),
+            )]
+        })
+        .collect();
+
+    if keyboard.is_empty() {
+        bot.send_message(msg.chat.id, text.no_medicines).await?;
+        return Ok(());
+    }
+
+    bot.send_message(msg.chat.id, text.select_pause_medicine)
+        .reply_markup(InlineKeyboardMarkup::new(keyboard))
+        .await?;
+    Ok(())
+}
+
+async fn show_resume_selection(bot: Bot, msg: Message, reminder_service: Arc<ReminderService>) -> HandlerResult {
+    let data = reminder_service.get_user_data(msg.chat.id).await;
+    let language = &data.user_settings.language;
+    let text = localization::get_text(language);
+
+    let keyboard: Vec<Vec<InlineKeyboardButton>> = data
+        .medicines
+        .values()
+        .filter(|medicine| !medicine.is_active)
+        .map(|medicine| {
+            vec![InlineKeyboardButton::callback(
+                format!("💊 {} {}", medicine.name, localization::format_pause_status(language, medicine.paused_until)),
+                CallbackData::Resume(medicine.id).encode(),
+            )]
+        })
+        .collect();
+
+    if keyboard.is_empty() {
+        bot.send_message(msg.chat.id, text.no_paused_medicines).await?;
+        return Ok(());
+    }
+
+    bot.send_message(msg.chat.id, text.select_resume_medicine)
+        .reply_markup(InlineKeyboardMarkup::new(keyboard))
+        .await?;
+    Ok(())
+}
+
+async fn receive_pause_until(
+    bot: Bot,
+    dialogue: MyDialogue,
+    msg: Message,
+    medicine_id: String,
+    reminder_service: Arc<ReminderService>,
+) -> HandlerResult {
+    let data = reminder_service.get_user_data(msg.chat.id).await;
+    let text = localization::get_text(&data.user_settings.language);
+    let today = Local::now().with_timezone(&data.timezone()).date_naive();
+
+    // `-` 表示暂停到手动恢复，否则恢复日期必须在今天之后
+    let input = msg.text().unwrap_or_default().trim();
+    let until = if input == "-" {
+        None
+    } else {
+        match NaiveDate::parse_from_str(input, "%Y-%m-%d") {
+            Ok(date) if date > today => Some(date),
+            _ => {
+                bot.send_message(msg.chat.id, text.invalid_pause_until).await?;
+                return Ok(());
+            }
+        }
+    };
+
+    let Ok(medicine_id) = Uuid::parse_str(&medicine_id) else {
+        bot.send_message(msg.chat.id, text.medicine_not_found).await?;
+        dialogue.update(State::Start).await?;
+        return Ok(());
+    };
+    match reminder_service.pause_medicine(msg.chat.id, medicine_id, until).await {
+        Ok(message) => {
+            bot.send_message(msg.chat.id, message).await?;
+        }
+        Err(error) => {
+            bot.send_message(msg.chat.id, format!("❌ 错误：{}", error)).await?;
+        }
+    }
+
+    dialogue.update(State::Start).await?;
+    Ok(())
+}
+
+// 将按钮所在的选择消息替换为结果，只保留 `keyboard` 中的按钮，无法编辑时发送新消息
+async fn reply_in_place(
+    bot: &Bot,
+    q: &CallbackQuery,
+    chat_id: ChatId,
+    text: String,
+    keyboard: Option<InlineKeyboardMarkup>,
+) -> HandlerResult {
+    if let Some(message) = &q.message {
+        let mut request = bot.edit_message_text(chat_id, message.id, text.clone());
+        if let Some(keyboard) = keyboard.clone() {
+            request = request.reply_markup(keyboard);
+        }
+        if request.await.is_ok() {
+            return Ok(());
+        }
+    }
+    let mut request = bot.send_message(chat_id, text);
+    if let Some(keyboard) = keyboard {
+        request = request.reply_markup(keyboard);
+    }
+    request.await?;
+    Ok(())
+}
+
+// 确认服药后的撤销按钮，每项为提醒ID和按钮文字
+fn undo_keyboard(undoable: &[(Uuid, String)]) -> Option<InlineKeyboardMarkup> {
+    if undoable.is_empty() {
+        return None;
+    }
+    let rows = undoable
+        .iter()
+        .map(|(reminder_id, label)| vec![InlineKeyboardButton::callback(label.clone(), CallbackData::Undo(*reminder_id).encode())])
+        .collect::<Vec<_>>();
+    Some(InlineKeyboardMarkup::new(rows))
+}
+
+async fn handle_callback(bot: Bot, q: CallbackQuery, reminder_service: Arc<ReminderService>, dialogue: MyDialogue) -> HandlerResult {
+    let (Some(data), Some(chat_id)) = (q.data.as_deref(), q.message.as_ref().map(|m| m.chat.id)) else {
+        bot.answer_callback_query(q.id).await?;
+        return Ok(());
+    };
+
+    let callback = match CallbackData::decode(data) {
+        Ok(callback) => callback,
+        Err(error) => {
+            // 旧版本的按钮和无法解析的按钮都提示重新使用命令
+            log::info!("忽略按钮 {}: {}", data, error);
+            let text = localization::get_text(&reminder_service.get_user_data(chat_id).await.user_settings.language);
+            bot.answer_callback_query(q.id)
+                .text(text.button_outdated)
+                .show_alert(true)
+                .await?;
+            return Ok(());
+        }
+    };
+
+    match callback {
+        CallbackData::SetLanguage(new_language) => {
+            if let Err(e) = reminder_service
+                .update_user_data(chat_id, |app_data| {
+                    app_data.user_settings.language = new_language.clone();
+                })
+                .await {
+                log::error!("Failed to update language: {}", e);
+                bot.send_message(chat_id, "❌ Failed to update language / 更新语言失败").await?;
+            } else {
+                let text = localization::get_text(&new_language);
+                bot.send_message(chat_id, text.language_changed).await?;
+            }
+        }
+        CallbackData::Confirm(reminder_id) => {
+            let current_data = reminder_service.get_user_data(chat_id).await;
+            let language = &current_data.user_settings.language;
+            let text = localization::get_text(language);
+
+            // 已确认的提醒（重复点击或旧消息的按钮）不再显示数量选择
+            if current_data.pending_reminders.get(&reminder_id).is_some_and(|r| r.is_confirmed) {
+                let message = localization::format_already_confirmed(
+                    language,
+                    current_data.timezone(),
+                    current_data.taken_event(reminder_id),
+                );
+                bot.send_message(chat_id, message).await?;
+            } else {
+                // 显示数量选择界面
+                let keyboard = vec![
+                    (1..=3)
+                        .map(|amount| {
+                            InlineKeyboardButton::callback(
+                                format!("{}{}", amount, text.pills_unit),
+                                CallbackData::DoseAmount { reminder_id, amount }.encode(),
+                            )
+                        })
+                        .collect(),
+                    vec![
+                        InlineKeyboardButton::callback(text.custom_amount_button, CallbackData::DoseCustom(reminder_id).encode()),
+                    ],
+                ];
+                let markup = InlineKeyboardMarkup::new(keyboard);
+                bot.send_message(chat_id, text.select_dose_amount)
+                    .reply_markup(markup)
+                    .await?;
+            }
+        }
+        CallbackData::DoseAmount { reminder_id, amount } => {
+            match reminder_service.confirm_medicine_with_amount(chat_id, reminder_id, amount).await {
+                Ok(response) => {
+                    let language = reminder_service.get_user_data(chat_id).await.user_settings.language;
+                    let undo = vec![(reminder_id, localization::get_text(&language).undo_button.to_string())];
+                    reply_in_place(&bot, &q, chat_id, response, undo_keyboard(&undo)).await?;
+                }
+                Err(error) => {
+                    reply_in_place(&bot, &q, chat_id, format!("❌ 错误：{}", error), None).await?;
+                }
+            }
+        }
+        CallbackData::DoseCustom(reminder_id) => {
+            let text = localization::get_text(&reminder_service.get_user_data(chat_id).await.user_settings.language);
+
+            reply_in_place(&bot, &q, chat_id, text.enter_custom_amount.to_string(), None).await?;
+            dialogue.update(State::ReceiveConfirmDoseAmount {
+                reminder_id: reminder_id.to_string()
+            }).await?;
+        }
+        CallbackData::Snooze(reminder_id) => {
+            let language = reminder_service.get_user_data(chat_id).await.user_settings.language;
+            let text = localization::get_text(&language);
+
+            // 显示延迟时间选择界面
+            let keyboard = vec![
+                SNOOZE_CHOICES
+                    .iter()
+                    .map(|&minutes| {
+                        InlineKeyboardButton::callback(
+                            localization::format_snooze_duration(&language, minutes),
+                            CallbackData::SnoozeFor { reminder_id, minutes }.encode(),
+                        )
+                    })
+                    .collect(),
+                vec![
+                    InlineKeyboardButton::callback(text.snooze_custom_button, CallbackData::SnoozeCustom(reminder_id).encode()),
+                    InlineKeyboardButton::callback(text.snooze_until_button, CallbackData::SnoozeUntil(reminder_id).encode()),
+                ],
+            ];
+            bot.send_message(chat_id, text.snooze_prompt)
+                .reply_markup(InlineKeyboardMarkup::new(keyboard))
+                .await?;
+        }
+        CallbackData::SnoozeFor { reminder_id, minutes } => {
+            let until = Local::now() + Duration::minutes(i64::from(minutes));
+            match reminder_service.snooze_reminder(chat_id, reminder_id, until).await {
+                Ok(response) => {
+                    reply_in_place(&bot, &q, chat_id, response, None).await?;
+                }
+                Err(error) => {
+                    reply_in_place(&bot, &q, chat_id, format!("❌ 错误：{}", error), None).await?;
+                }
+            }
+        }
+        CallbackData::SnoozeCustom(reminder_id) => {
+            let text = localization::get_text(&reminder_service.get_user_data(chat_id).await.user_settings.language);
+            reply_in_place(&bot, &q, chat_id, text.enter_snooze_minutes.to_string(), None).await?;
+            dialogue.update(State::ReceiveSnoozeMinutes {
+                reminder_id: reminder_id.to_string()
+            }).await?;
+        }
+        CallbackData::SnoozeUntil(reminder_id) => {
+            let text = localization::get_text(&reminder_service.get_user_data(chat_id).await.user_settings.language);
+            reply_in_place(&bot, &q, chat_id, text.enter_snooze_until.to_string(), None).await?;
+            dialogue.update(State::ReceiveSnoozeUntil {
+                reminder_id: reminder_id.to_string()
+            }).await?;
+        }
+        CallbackData::Undo(reminder_id) => {
+            match reminder_service.undo_confirmation(chat_id, reminder_id).await {
+                Ok(response) => {
+                    reply_in_place(&bot, &q, chat_id, response, None).await?;
+                }
+                Err(error) => {
+                    bot.send_message(chat_id, error).await?;
+                }
+            }
+        }
+        CallbackData::TakeAll(timestamp) => {
+            // 按默认数量确认同一时间的全部提醒
+            match reminder_service.confirm_all(chat_id, timestamp).await {
+                Ok(response) => {
+                    // 每种已确认的药品都可以单独撤销
+                    let current_data = reminder_service.get_user_data(chat_id).await;
+                    let mut undo: Vec<(Uuid, String)> = current_data
+                        .pending_reminders
+                        .values()
+                        .filter(|r| r.scheduled_time.timestamp() == timestamp && current_data.taken_event(r.id).is_some())
+                        .map(|r| (r.id, format!("↩️ {}", r.medicine_name)))
+                        .collect();
+                    undo.sort_by(|a, b| a.1.cmp(&b.1));
+                    let mut request = bot.send_message(chat_id, response);
+                    if let Some(keyboard) = undo_keyboard(&undo) {
+                        request = request.reply_markup(keyboard);
+                    }
+                    request.await?;
+                }
+                Err(error) => {
+                    bot.send_message(chat_id, format!("❌ 错误：{}", error)).await?;
+                }
+            }
+        }
+        CallbackData::Skip(reminder_id) => {
+            let language = reminder_service.get_user_data(chat_id).await.user_settings.language;
+            let text = localization::get_text(&language);
+
+            // 显示跳过原因选择界面
+            let mut keyboard: Vec<Vec<InlineKeyboardButton>> = SkipReason::ALL
+                .chunks(2)
+                .map(|reasons| {
+                    reasons
+                        .iter()
+                        .map(|&reason| {
+                            InlineKeyboardButton::callback(
+                                localization::format_skip_reason(&language, reason),
+                                CallbackData::SkipWithReason { reminder_id, reason: Some(reason) }.encode(),
+                            )
+                        })
+                        .collect()
+                })
+                .collect();
+            keyboard.push(vec![InlineKeyboardButton::callback(
+                text.skip_no_reason_button,
+                CallbackData::SkipWithReason { reminder_id, reason: None }.encode(),
+            )]);
+            bot.send_message(chat_id, text.skip_reason_prompt)
+                .reply_markup(InlineKeyboardMarkup::new(keyboard))
+                .await?;
+        }
+        CallbackData::SkipWithReason { reminder_id, reason } => {
+            match reminder_service.skip_reminder(chat_id, reminder_id, reason).await {
+                Ok(response) => {
+                    reply_in_place(&bot, &q, chat_id, response, None).await?;
+                }
+                Err(error) => {
+                    reply_in_place(&bot, &q, chat_id, format!("❌ 错误：{}", error), None).await?;
+                }
+            }
+        }
+        CallbackData::TakeAsNeeded { medicine_id, force } => {
+            match reminder_service.take_as_needed(chat_id, medicine_id, force).await {
+                Ok(PrnTakeResult::Taken(response)) => {
+                    bot.send_message(chat_id, response).await?;
+                }
+                Ok(PrnTakeResult::Refused(reason)) => {
+                    let text = localization::get_text(&reminder_service.get_user_data(chat_id).await.user_settings.language);
+                    let keyboard = InlineKeyboardMarkup::new(vec![vec![InlineKeyboardButton::callback(
+                        text.take_anyway_button,
+                        CallbackData::TakeAsNeeded { medicine_id, force: true }.encode(),
+                    )]]);
+                    bot.send_message(chat_id, reason).reply_markup(keyboard).await?;
+                }
+                Err(error) => {
+                    bot.send_message(chat_id, format!("❌ 错误：{}", error)).await?;
+                }
+            }
+        }
+        CallbackData::History { filter, page } => {
+            if let Some(message) = q.message.as_ref() {
+                let user_data = reminder_service.get_user_data(chat_id).await;
+                let (text, markup) = render_history_page(&user_data, &filter, page);
+                // 原地更新消息，内容未变化时 Telegram 会返回错误，忽略即可
+                if let Err(e) = bot
+                    .edit_message_text(chat_id, message.id, text)
+                    .reply_markup(markup)
+                    .await
+                {
+                    log::debug!("更新服药记录消息失败: {}", e);
+                }
+            }
+        }
+        CallbackData::Caregiver(medicine_id) => {
+            let text = localization::get_text(&reminder_service.get_user_data(chat_id).await.user_settings.language);
+
+            bot.send_message(chat_id, text.enter_caregiver).await?;
+            dialogue.update(State::ReceiveCaregiver {
+                medicine_id: medicine_id.map(|id| id.to_string())
+            }).await?;
+        }
+        CallbackData::StockAlert(medicine_id) => {
+            let text = localization::get_text(&reminder_service.get_user_data(chat_id).await.user_settings.language);
+
+            bot.send_message(chat_id, text.enter_stock_alert).await?;
+            dialogue.update(State::ReceiveStockAlert {
+                medicine_id: medicine_id.to_string()
+            }).await?;
+        }
+        CallbackData::Grace(medicine_id) => {
+            let text = localization::get_text(&reminder_service.get_user_data(chat_id).await.user_settings.language);
+
+            bot.send_message(chat_id, text.enter_grace_minutes).await?;
+            dialogue.update(State::ReceiveGraceMinutes {
+                medicine_id: medicine_id.to_string()
+            }).await?;
+        }
+        CallbackData::Expiry(medicine_id) => {
+            let text = localization::get_text(&reminder_service.get_user_data(chat_id).await.user_settings.language);
+
+            bot.send_message(chat_id, text.enter_expiry_minutes).await?;
+            dialogue.update(State::ReceiveExpiryMinutes {
+                medicine_id: medicine_id.to_string()
+            }).await?;
+        }
+        CallbackData::Escalation(medicine_id) => {
+            let user_data = reminder_service.get_user_data(chat_id).await;
+            let language = &user_data.user_settings.language;
+            let text = localization::get_text(language);
+
+            if let Some(medicine) = user_data.medicines.get(&medicine_id) {
+                bot.send_message(
+                    chat_id,
+                    format!(
+                        "💊 {} [{}]\n\n{}",
+                        medicine.name,
+                        localization::format_escalation_policy(language, &medicine.escalation),
+                        text.enter_escalation_policy
+                    ),
+                )
+                .await?;
+                dialogue.update(State::ReceiveEscalationPolicy {
+                    medicine_id: medicine_id.to_string()
+                }).await?;
+            }
+        }
+        CallbackData::EditMedicine(medicine_id) => {
+            let user_data = reminder_service.get_user_data(chat_id).await;
+            let language = &user_data.user_settings.language;
+            match user_data.medicines.get(&medicine_id) {
+                Some(medicine) => {
+                    let text = localization::get_text(language);
+                    let message = format!("💊 {}\n\n{}", medicine.name, text.select_edit_field);
+                    reply_in_place(&bot, &q, chat_id, message, Some(edit_field_keyboard(language, medicine))).await?;
+                }
+                None => {
+                    bot.send_message(chat_id, localization::get_text(language).medicine_not_found).await?;
+                }
+            }
+        }
+        CallbackData::EditField { medicine_id, field } => {
+            let user_data = reminder_service.get_user_data(chat_id).await;
+            let language = &user_data.user_settings.language;
+            match user_data.medicines.get(&medicine_id) {
+                Some(_) if field == EditField::Times && user_data.is_travelling() => {
+                    let text = localization::get_text(language);
+                    reply_in_place(&bot, &q, chat_id, text.edit_times_while_travelling.to_string(), None).await?;
+                }
+                Some(medicine) if field.applies_to(medicine) => {
+                    let prompt = localization::format_edit_prompt(language, medicine, field);
+                    reply_in_place(&bot, &q, chat_id, prompt, None).await?;
+                    dialogue.update(State::ReceiveEditValue {
+                        medicine_id: medicine_id.to_string(),
+                        field,
+                    }).await?;
+                }
+                Some(_) => {}
+                None => {
+                    bot.send_message(chat_id, localization::get_text(language).medicine_not_found).await?;
+                }
+            }
+        }
+        CallbackData::Pause(medicine_id) => {
+            let user_data = reminder_service.get_user_data(chat_id).await;
+            let text = localization::get_text(&user_data.user_settings.language);
+            match user_data.medicines.get(&medicine_id) {
+                Some(medicine) => {
+                    let message = format!("💊 {}\n\n{}", medicine.name, text.enter_pause_until);
+                    reply_in_place(&bot, &q, chat_id, message, None).await?;
+                    dialogue.update(State::ReceivePauseUntil {
+                        medicine_id: medicine_id.to_string(),
+                    }).await?;
+                }
+                None => {
+                    bot.send_message(chat_id, text.medicine_not_found).await?;
+                }
+            }
+        }
+        CallbackData::Resume(medicine_id) => {
+            match reminder_service.resume_medicine(chat_id, medicine_id).await {
+                Ok(message) => reply_in_place(&bot, &q, chat_id, message, None).await?,
+                Err(error) => reply_in_place(&bot, &q, chat_id, format!("❌ 错误：{}", error), None).await?,
+            }
+        }
+        CallbackData::TravelConfirm | CallbackData::TravelDiscard => {
+            let text = localization::get_text(&reminder_service.get_user_data(chat_id).await.user_settings.language);
+            let Some(State::ConfirmTravel { destination, depart_date, return_date, step_minutes }) = dialogue.get().await? else {
+                return Ok(());
+            };
+            dialogue.update(State::Start).await?;
+            let Some(destination) = timezone::parse_timezone(&destination) else {
+                return Ok(());
+            };
+            if callback == CallbackData::TravelDiscard {
+                bot.send_message(chat_id, text.travel_discarded).await?;
+                return Ok(());
+            }
+
+            let now = Local::now();
+            let mut message = None;
+            if let Err(e) = reminder_service
+                .update_user_data(chat_id, |user| {
+                    user.user_settings.travel = Some(TravelPlan::new(
+                        user.user_settings.timezone,
+                        user.timezone(),
+                        destination,
+                        depart_date,
+                        return_date,
+                        step_minutes,
+                    ));
+                    // 当天出发时立即开始调整
+                    message = user.update_travel(now).map(|event| {
+                        localization::format_travel_event(&user.user_settings.language, event, user, now)
+                    });
+                })
+                .await {
+                log::error!("Failed to save travel plan: {}", e);
+                bot.send_message(chat_id, "❌ 保存设置失败 / Failed to save settings").await?;
+                return Ok(());
+            }
+
+            bot.send_message(chat_id, text.travel_saved).await?;
+            if let Some(message) = message {
+                bot.send_message(chat_id, message).await?;
+            }
+        }
+        CallbackData::TravelCancel => {
+            let mut cancelled = false;
+            if let Err(e) = reminder_service
+                .update_user_data(chat_id, |user| cancelled = user.end_travel())
+                .await {
+                log::error!("Failed to cancel travel plan: {}", e);
+                bot.send_message(chat_id, "❌ 保存设置失败 / Failed to save settings").await?;
+            } else if cancelled {
+                let text = localization::get_text(&reminder_service.get_user_data(chat_id).await.user_settings.language);
+                bot.send_message(chat_id, text.travel_cancelled).await?;
+            }
+        }
+        CallbackData::CaregiverAcknowledge { patient_chat_id, reminder_id }
+        | CallbackData::CaregiverHandled { patient_chat_id, reminder_id } => {
+            let result = if matches!(callback, CallbackData::CaregiverAcknowledge { .. }) {
+                reminder_service.caregiver_acknowledge(chat_id, ChatId(patient_chat_id), reminder_id).await
+            } else {
+                reminder_service.caregiver_mark_handled(chat_id, ChatId(patient_chat_id), reminder_id).await
+            };
+            match result {
+                Ok(response) => {
+                    bot.send_message(chat_id, response).await?;
+                }
+                Err(error) => {
+                    bot.send_message(chat_id, format!("❌ {}", error)).await?;
+                }
+            }
+        }
+        CallbackData::RevokeUser(user_id) => {
+            let text = localization::get_text(&reminder_service.get_user_data(chat_id).await.user_settings.language);
+            if !reminder_service.access_config().is_owner(q.from.id.0) {
+                bot.send_message(chat_id, text.owner_only).await?;
+            } else {
+                match reminder_service.revoke_user(user_id).await {
+                    Ok(_) => {
+                        log::info!("已移除用户 {} 的使用权限", user_id);
+                        bot.send_message(chat_id, text.user_revoked).await?;
+                    }
+                    Err(e) => {
+                        log::error!("Failed to revoke user: {}", e);
+                        bot.send_message(chat_id, "❌ 移除用户失败 / Failed to remove user").await?;
+                    }
+                }
+            }
+        }
+        CallbackData::DeleteMedicine(medicine_id) => {
+            if let Err(e) = reminder_service
+                .update_user_data(chat_id, |app_data| {
+                    app_data.medicines.remove(&medicine_id);
+                })
+                .await {
+                log::error!("Failed to delete medicine: {}", e);
+                bot.send_message(chat_id, "❌ 删除药品失败").await?;
+            } else {
+                bot.send_message(chat_id, "✅ 药品已删除").await?;
+            }
+        }
+        CallbackData::Refill(medicine_id) => {
+            let text = localization::get_text(&reminder_service.get_user_data(chat_id).await.user_settings.language);
+
+            // 显示数量选择界面
+            let keyboard = vec![
+                [10, 20, 30]
+                    .into_iter()
+                    .map(|amount| {
+                        InlineKeyboardButton::callback(
+                            format!("{}{}", amount, text.pieces_unit),
+                            CallbackData::RefillAmount { medicine_id, amount }.encode(),
+                        )
+                    })
+                    .collect(),
+                vec![
+                    InlineKeyboardButton::callback(text.custom_amount_button, CallbackData::RefillCustom(medicine_id).encode()),
+                ],
+            ];
+            let markup = InlineKeyboardMarkup::new(keyboard);
+            bot.send_message(chat_id, text.enter_refill_amount)
+                .reply_markup(markup)
+                .await?;
+        }
+        CallbackData::RefillCustom(medicine_id) => {
+            let text = localization::get_text(&reminder_service.get_user_data(chat_id).await.user_settings.language);
+
+            bot.send_message(chat_id, text.enter_refill_amount).await?;
+            dialogue.update(State::ReceiveRefillAmount {
+                medicine_id: medicine_id.to_string()
+            }).await?;
+        }
+        CallbackData::RefillAmount { medicine_id, amount } => {
+            if let Err(e) = reminder_service
+                .update_user_data(chat_id, |app_data| {
+                    if let Some(medicine) = app_data.medicines.get_mut(&medicine_id) {
+                        medicine.add_quantity(amount);
+                    }
+                })
+                .await {
+                log::error!("Failed to refill medicine: {}", e);
+                bot.send_message(chat_id, "❌ 补充药品失败").await?;
+            } else {
+                bot.send_message(chat_id, format!("✅ 已补充{}个药品", amount)).await?;
+            }
+        }
+    }
+
+    bot.answer_callback_query(q.id).await?;
+    Ok(())
+}
+
+async fn receive_confirm_dose_amount(
+    bot: Bot,
+    dialogue: MyDialogue,
     msg: Message,
     reminder_id: String,
     reminder_service: Arc<ReminderService>,
@@ -582,9 +2282,15 @@ async fn receive_confirm_dose_amount(
     match msg.text().and_then(|text| text.parse::<u32>().ok()) {
         Some(amount) if amount > 0 => {
             if let Ok(uuid) = Uuid::parse_str(&reminder_id) {
-                match reminder_service.confirm_medicine_with_amount(uuid, amount).await {
+                match reminder_service.confirm_medicine_with_amount(msg.chat.id, uuid, amount).await {
                     Ok(response) => {
-                        bot.send_message(msg.chat.id, response).await?;
+                        let language = reminder_service.get_user_data(msg.chat.id).await.user_settings.language;
+                        let undo = vec![(uuid, localization::get_text(&language).undo_button.to_string())];
+                        let mut request = bot.send_message(msg.chat.id, response);
+                        if let Some(keyboard) = undo_keyboard(&undo) {
+                            request = request.reply_markup(keyboard);
+                        }
+                        request.await?;
                         dialogue.update(State::Start).await?;
                     }
                     Err(error) => {
@@ -604,6 +2310,77 @@ async fn receive_confirm_dose_amount(
     Ok(())
 }
 
+/// 延迟按钮提供的分钟数
+const SNOOZE_CHOICES: [u32; 3] = [10, 30, 60];
+
+/// 自定义延迟最多一天
+const MAX_SNOOZE_MINUTES: u32 = 24 * 60;
+
+async fn receive_snooze_minutes(
+    bot: Bot,
+    dialogue: MyDialogue,
+    msg: Message,
+    reminder_id: String,
+    reminder_service: Arc<ReminderService>,
+) -> HandlerResult {
+    let text = localization::get_text(&reminder_service.get_user_data(msg.chat.id).await.user_settings.language);
+
+    let minutes = match msg.text().unwrap_or_default().trim().parse::<u32>() {
+        Ok(minutes) if (1..=MAX_SNOOZE_MINUTES).contains(&minutes) => minutes,
+        _ => {
+            bot.send_message(msg.chat.id, text.invalid_snooze_minutes).await?;
+            return Ok(());
+        }
+    };
+
+    let until = Local::now() + Duration::minutes(i64::from(minutes));
+    snooze_until(bot, dialogue, msg.chat.id, &reminder_id, until, reminder_service).await
+}
+
+async fn receive_snooze_until(
+    bot: Bot,
+    dialogue: MyDialogue,
+    msg: Message,
+    reminder_id: String,
+    reminder_service: Arc<ReminderService>,
+) -> HandlerResult {
+    let data = reminder_service.get_user_data(msg.chat.id).await;
+    let text = localization::get_text(&data.user_settings.language);
+
+    let Ok(time) = NaiveTime::parse_from_str(msg.text().unwrap_or_default().trim(), "%H:%M") else {
+        bot.send_message(msg.chat.id, text.invalid_snooze_until).await?;
+        return Ok(());
+    };
+
+    let until = timezone::next_time_of_day(data.timezone(), time, Local::now());
+    snooze_until(bot, dialogue, msg.chat.id, &reminder_id, until, reminder_service).await
+}
+
+// 延迟提醒并结束对话
+async fn snooze_until(
+    bot: Bot,
+    dialogue: MyDialogue,
+    chat_id: ChatId,
+    reminder_id: &str,
+    until: DateTime<Local>,
+    reminder_service: Arc<ReminderService>,
+) -> HandlerResult {
+    if let Ok(uuid) = Uuid::parse_str(reminder_id) {
+        match reminder_service.snooze_reminder(chat_id, uuid, until).await {
+            Ok(response) => {
+                bot.send_message(chat_id, response).await?;
+            }
+            Err(error) => {
+                bot.send_message(chat_id, format!("❌ 错误：{}", error)).await?;
+            }
+        }
+    } else {
+        bot.send_message(chat_id, "❌ 无效的提醒ID").await?;
+    }
+    dialogue.update(State::Start).await?;
+    Ok(())
+}
+
 async fn receive_refill_amount(
     bot: Bot,
     dialogue: MyDialogue,
@@ -615,7 +2392,7 @@ async fn receive_refill_amount(
         Some(amount) if amount > 0 => {
             if let Ok(uuid) = Uuid::parse_str(&medicine_id) {
                 if let Err(e) = reminder_service
-                    .update_data(|app_data| {
+                    .update_user_data(msg.chat.id, |app_data| {
                         if let Some(medicine) = app_data.medicines.get_mut(&uuid) {
                             medicine.add_quantity(amount);
                         }
@@ -0,0 +1,248 @@
+use crate::storage::Language;
+use crate::{EditField, HistoryFilter, SkipReason};
+use chrono::NaiveDate;
+use uuid::Uuid;
+
+/// 回调数据的协议版本。按钮格式不兼容地变化时递增，旧版本的按钮会被拒绝
+pub const CALLBACK_VERSION: &str = "1";
+
+/// Telegram 限制回调数据最多64字节
+pub const MAX_CALLBACK_BYTES: usize = 64;
+
+const SEPARATOR: char = ':';
+
+/// 内联按钮的回调数据。编码格式：`{版本}:{标签}:{字段}...`，ID 使用不带连字符的32位十六进制
+#[derive(Debug, Clone, PartialEq)]
+pub enum CallbackData {
+    SetLanguage(Language),
+    /// 提醒的“已服药”按钮，打开数量选择
+    Confirm(Uuid),
+    DoseAmount { reminder_id: Uuid, amount: u32 },
+    DoseCustom(Uuid),
+    /// 提醒的“稍后提醒”按钮，打开延迟时间选择
+    Snooze(Uuid),
+    SnoozeFor { reminder_id: Uuid, minutes: u32 },
+    SnoozeCustom(Uuid),
+    SnoozeUntil(Uuid),
+    /// 提醒的“跳过”按钮，打开原因选择
+    Skip(Uuid),
+    SkipWithReason { reminder_id: Uuid, reason: Option<SkipReason> },
+    /// 按默认数量确认计划在同一时间（时间戳）的全部提醒
+    TakeAll(i64),
+    Undo(Uuid),
+    TakeAsNeeded { medicine_id: Uuid, force: bool },
+    History { filter: HistoryFilter, page: usize },
+    /// 设置照护人，`None` 表示所有药品
+    Caregiver(Option<Uuid>),
+    CaregiverAcknowledge { patient_chat_id: i64, reminder_id: Uuid },
+    CaregiverHandled { patient_chat_id: i64, reminder_id: Uuid },
+    StockAlert(Uuid),
+    Grace(Uuid),
+    Expiry(Uuid),
+    Escalation(Uuid),
+    RevokeUser(u64),
+    DeleteMedicine(Uuid),
+    /// 补充药品，打开数量选择
+    Refill(Uuid),
+    RefillAmount { medicine_id: Uuid, amount: u32 },
+    RefillCustom(Uuid),
+    /// 编辑药品，打开字段菜单
+    EditMedicine(Uuid),
+    EditField { medicine_id: Uuid, field: EditField },
+    /// 暂停药品，询问恢复日期
+    Pause(Uuid),
+    Resume(Uuid),
+    TravelConfirm,
+    TravelDiscard,
+    TravelCancel,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CallbackError {
+    /// 旧版本（或旧格式）的按钮
+    Outdated,
+    /// 当前版本但内容无法解析
+    Invalid,
+}
+
+impl std::fmt::Display for CallbackError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CallbackError::Outdated => write!(f, "outdated callback data"),
+            CallbackError::Invalid => write!(f, "invalid callback data"),
+        }
+    }
+}
+
+impl std::error::Error for CallbackError {}
+
+impl CallbackData {
+    pub fn encode(&self) -> String {
+        let id = |id: &Uuid| id.simple().to_string();
+        let fields: Vec<String> = match self {
+            CallbackData::SetLanguage(language) => vec![
+                "l".into(),
+                match language {
+                    Language::Chinese => "zh".into(),
+                    Language::English => "en".into(),
+                },
+            ],
+            CallbackData::Confirm(reminder_id) => vec!["c".into(), id(reminder_id)],
+            CallbackData::DoseAmount { reminder_id, amount } => vec!["d".into(), id(reminder_id), amount.to_string()],
+            CallbackData::DoseCustom(reminder_id) => vec!["dc".into(), id(reminder_id)],
+            CallbackData::Snooze(reminder_id) => vec!["s".into(), id(reminder_id)],
+            CallbackData::SnoozeFor { reminder_id, minutes } => vec!["sf".into(), id(reminder_id), minutes.to_string()],
+            CallbackData::SnoozeCustom(reminder_id) => vec!["sc".into(), id(reminder_id)],
+            CallbackData::SnoozeUntil(reminder_id) => vec!["su".into(), id(reminder_id)],
+            CallbackData::Skip(reminder_id) => vec!["k".into(), id(reminder_id)],
+            CallbackData::SkipWithReason { reminder_id, reason } => vec![
+                "kr".into(),
+                id(reminder_id),
+                reason.map_or("-", |reason| reason.code()).into(),
+            ],
+            CallbackData::TakeAll(timestamp) => vec!["ta".into(), timestamp.to_string()],
+            CallbackData::Undo(reminder_id) => vec!["u".into(), id(reminder_id)],
+            CallbackData::TakeAsNeeded { medicine_id, force } => {
+                vec!["p".into(), id(medicine_id), if *force { "1" } else { "0" }.into()]
+            }
+            CallbackData::History { filter, page } => {
+                let date = |date: Option<NaiveDate>| date.map_or("-".to_string(), |d| d.format("%Y%m%d").to_string());
+                vec![
+                    "h".into(),
+                    page.to_string(),
+                    filter.medicine_id.as_ref().map_or("*".to_string(), id),
+                    date(filter.from),
+                    date(filter.to),
+                ]
+            }
+            CallbackData::Caregiver(medicine_id) => vec!["cg".into(), medicine_id.as_ref().map_or("*".to_string(), id)],
+            CallbackData::CaregiverAcknowledge { patient_chat_id, reminder_id } => {
+                vec!["ca".into(), patient_chat_id.to_string(), id(reminder_id)]
+            }
+            CallbackData::CaregiverHandled { patient_chat_id, reminder_id } => {
+                vec!["ch".into(), patient_chat_id.to_string(), id(reminder_id)]
+            }
+            CallbackData::StockAlert(medicine_id) => vec!["st".into(), id(medicine_id)],
+            CallbackData::Grace(medicine_id) => vec!["gr".into(), id(medicine_id)],
+            CallbackData::Expiry(medicine_id) => vec!["ex".into(), id(medicine_id)],
+            CallbackData::Escalation(medicine_id) => vec!["es".into(), id(medicine_id)],
+            CallbackData::RevokeUser(user_id) => vec!["rv".into(), user_id.to_string()],
+            CallbackData::DeleteMedicine(medicine_id) => vec!["x".into(), id(medicine_id)],
+            CallbackData::Refill(medicine_id) => vec!["r".into(), id(medicine_id)],
+            CallbackData::RefillAmount { medicine_id, amount } => vec!["ra".into(), id(medicine_id), amount.to_string()],
+            CallbackData::RefillCustom(medicine_id) => vec!["rc".into(), id(medicine_id)],
+            CallbackData::EditMedicine(medicine_id) => vec!["e".into(), id(medicine_id)],
+            CallbackData::EditField { medicine_id, field } => vec!["ef".into(), id(medicine_id), field.code().into()],
+            CallbackData::Pause(medicine_id) => vec!["pa".into(), id(medicine_id)],
+            CallbackData::Resume(medicine_id) => vec!["re".into(), id(medicine_id)],
+            CallbackData::TravelConfirm => vec!["tc".into()],
+            CallbackData::TravelDiscard => vec!["td".into()],
+            CallbackData::TravelCancel => vec!["tx".into()],
+        };
+
+        let mut data = CALLBACK_VERSION.to_string();
+        for field in fields {
+            data.push(SEPARATOR);
+            data.push_str(&field);
+        }
+        debug_assert!(data.len() <= MAX_CALLBACK_BYTES, "回调数据超过64字节: {}", data);
+        data
+    }
+
+    pub fn decode(data: &str) -> Result<Self, CallbackError> {
+        let mut parts = data.split(SEPARATOR);
+        if parts.next() != Some(CALLBACK_VERSION) || !data.contains(SEPARATOR) {
+            return Err(CallbackError::Outdated);
+        }
+        let tag = parts.next().ok_or(CallbackError::Invalid)?;
+        let fields: Vec<&str> = parts.collect();
+
+        let uuid = |s: &str| Uuid::parse_str(s).map_err(|_| CallbackError::Invalid);
+        let number = |s: &str| s.parse::<u32>().map_err(|_| CallbackError::Invalid);
+        let optional_uuid = |s: &str| match s {
+            "*" => Ok(None),
+            s => uuid(s).map(Some),
+        };
+        let date = |s: &str| match s {
+            "-" => Ok(None),
+            s => NaiveDate::parse_from_str(s, "%Y%m%d").map(Some).map_err(|_| CallbackError::Invalid),
+        };
+
+        let callback = match (tag, fields.as_slice()) {
+            ("l", ["zh"]) => CallbackData::SetLanguage(Language::Chinese),
+            ("l", ["en"]) => CallbackData::SetLanguage(Language::English),
+            ("c", [reminder_id]) => CallbackData::Confirm(uuid(reminder_id)?),
+            ("d", [reminder_id, amount]) => CallbackData::DoseAmount {
+                reminder_id: uuid(reminder_id)?,
+                amount: number(amount)?,
+            },
+            ("dc", [reminder_id]) => CallbackData::DoseCustom(uuid(reminder_id)?),
+            ("s", [reminder_id]) => CallbackData::Snooze(uuid(reminder_id)?),
+            ("sf", [reminder_id, minutes]) => CallbackData::SnoozeFor {
+                reminder_id: uuid(reminder_id)?,
+                minutes: number(minutes)?,
+            },
+            ("sc", [reminder_id]) => CallbackData::SnoozeCustom(uuid(reminder_id)?),
+            ("su", [reminder_id]) => CallbackData::SnoozeUntil(uuid(reminder_id)?),
+            ("k", [reminder_id]) => CallbackData::Skip(uuid(reminder_id)?),
+            ("kr", [reminder_id, reason]) => CallbackData::SkipWithReason {
+                reminder_id: uuid(reminder_id)?,
+                reason: match *reason {
+                    "-" => None,
+                    code => Some(SkipReason::from_code(code).ok_or(CallbackError::Invalid)?),
+                },
+            },
+            ("ta", [timestamp]) => CallbackData::TakeAll(timestamp.parse().map_err(|_| CallbackError::Invalid)?),
+            ("u", [reminder_id]) => CallbackData::Undo(uuid(reminder_id)?),
+            ("p", [medicine_id, force]) => CallbackData::TakeAsNeeded {
+                medicine_id: uuid(medicine_id)?,
+                force: match *force {
+                    "0" => false,
+                    "1" => true,
+                    _ => return Err(CallbackError::Invalid),
+                },
+            },
+            ("h", [page, medicine_id, from, to]) => CallbackData::History {
+                filter: HistoryFilter {
+                    medicine_id: optional_uuid(medicine_id)?,
+                    from: date(from)?,
+                    to: date(to)?,
+                },
+                page: page.parse().map_err(|_| CallbackError::Invalid)?,
+            },
+            ("cg", [medicine_id]) => CallbackData::Caregiver(optional_uuid(medicine_id)?),
+            ("ca", [patient_chat_id, reminder_id]) => CallbackData::CaregiverAcknowledge {
+                patient_chat_id: patient_chat_id.parse().map_err(|_| CallbackError::Invalid)?,
+                reminder_id: uuid(reminder_id)?,
+            },
+            ("ch", [patient_chat_id, reminder_id]) => CallbackData::CaregiverHandled {
+                patient_chat_id: patient_chat_id.parse().map_err(|_| CallbackError::Invalid)?,
+                reminder_id: uuid(reminder_id)?,
+            },
+            ("st", [medicine_id]) => CallbackData::StockAlert(uuid(medicine_id)?),
+            ("gr", [medicine_id]) => CallbackData::Grace(uuid(medicine_id)?),
+            ("ex", [medicine_id]) => CallbackData::Expiry(uuid(medicine_id)?),
+            ("es", [medicine_id]) => CallbackData::Escalation(uuid(medicine_id)?),
+            ("rv", [user_id]) => CallbackData::RevokeUser(user_id.parse().map_err(|_| CallbackError::Invalid)?),
+            ("x", [medicine_id]) => CallbackData::DeleteMedicine(uuid(medicine_id)?),
+            ("r", [medicine_id]) => CallbackData::Refill(uuid(medicine_id)?),
+            ("ra", [medicine_id, amount]) => CallbackData::RefillAmount {
+                medicine_id: uuid(medicine_id)?,
+                amount: number(amount)?,
+            },
+            ("rc", [medicine_id]) => CallbackData::RefillCustom(uuid(medicine_id)?),
+            ("e", [medicine_id]) => CallbackData::EditMedicine(uuid(medicine_id)?),
+            ("ef", [medicine_id, field]) => CallbackData::EditField {
+                medicine_id: uuid(medicine_id)?,
+                field: EditField::from_code(field).ok_or(CallbackError::Invalid)?,
+            },
+            ("pa", [medicine_id]) => CallbackData::Pause(uuid(medicine_id)?),
+            ("re", [medicine_id]) => CallbackData::Resume(uuid(medicine_id)?),
+            ("tc", []) => CallbackData::TravelConfirm,
+            ("td", []) => CallbackData::TravelDiscard,
+            ("tx", []) => CallbackData::TravelCancel,
+            _ => return Err(CallbackError::Invalid),
+        };
+        Ok(callback)
+    }
+}
@@ -0,0 +1,62 @@
+use crate::ReminderService;
+use serde::{de::DeserializeOwned, Serialize};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use teloxide::dispatching::dialogue::Storage;
+use teloxide::types::ChatId;
+
+type StorageFuture<T> = Pin<Box<dyn Future<Output = Result<T, DialogueStorageError>> + Send + 'static>>;
+type DialogueStorageError = Box<dyn std::error::Error + Send + Sync>;
+
+/// 将对话状态保存在用户数据中，随 `AppData` 一起持久化，重启后对话可以继续
+pub struct DialogueStorage {
+    reminder_service: Arc<ReminderService>,
+}
+
+impl DialogueStorage {
+    pub fn new(reminder_service: Arc<ReminderService>) -> Arc<Self> {
+        Arc::new(Self { reminder_service })
+    }
+}
+
+impl<D> Storage<D> for DialogueStorage
+where
+    D: Serialize + DeserializeOwned + Send + 'static,
+{
+    type Error = DialogueStorageError;
+
+    fn remove_dialogue(self: Arc<Self>, chat_id: ChatId) -> StorageFuture<()>
+    where
+        D: Send + 'static,
+    {
+        Box::pin(async move { self.reminder_service.set_dialogue_state(chat_id, None).await })
+    }
+
+    fn update_dialogue(self: Arc<Self>, chat_id: ChatId, dialogue: D) -> StorageFuture<()>
+    where
+        D: Send + 'static,
+    {
+        Box::pin(async move {
+            let state = serde_json::to_value(&dialogue)?;
+            self.reminder_service.set_dialogue_state(chat_id, Some(state)).await
+        })
+    }
+
+    fn get_dialogue(self: Arc<Self>, chat_id: ChatId) -> StorageFuture<Option<D>> {
+        Box::pin(async move {
+            let Some(state) = self.reminder_service.get_dialogue_state(chat_id).await else {
+                return Ok(None);
+            };
+
+            // 升级后无法解析的旧状态直接丢弃，让用户重新开始
+            match serde_json::from_value(state) {
+                Ok(dialogue) => Ok(Some(dialogue)),
+                Err(e) => {
+                    log::warn!("无法恢复聊天 {} 的对话状态: {}", chat_id, e);
+                    Ok(None)
+                }
+            }
+        })
+    }
+}
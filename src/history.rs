@@ -0,0 +1,138 @@
+use chrono::{DateTime, Local, NaiveDate};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// 每页显示的服药记录条数
+pub const HISTORY_PAGE_SIZE: usize = 10;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DoseStatus {
+    Taken,
+    Skipped,
+    Missed,
+    Snoozed,
+}
+
+/// 跳过服药的原因
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SkipReason {
+    Forgot,
+    SideEffects,
+    DoctorAdvice,
+    OutOfStock,
+}
+
+impl SkipReason {
+    pub const ALL: [SkipReason; 4] = [
+        SkipReason::Forgot,
+        SkipReason::SideEffects,
+        SkipReason::DoctorAdvice,
+        SkipReason::OutOfStock,
+    ];
+
+    /// 回调数据中使用的短代码
+    pub fn code(self) -> &'static str {
+        match self {
+            SkipReason::Forgot => "forgot",
+            SkipReason::SideEffects => "side",
+            SkipReason::DoctorAdvice => "doctor",
+            SkipReason::OutOfStock => "stock",
+        }
+    }
+
+    pub fn from_code(code: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|reason| reason.code() == code)
+    }
+}
+
+/// 服药记录中的一条事件，只追加不修改
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DoseEvent {
+    pub id: Uuid,
+    pub medicine_id: Uuid,
+    pub medicine_name: String,
+    pub status: DoseStatus,
+    /// 计划服药时间，按需服药时为 `None`
+    pub scheduled_time: Option<DateTime<Local>>,
+    /// 事件实际发生的时间
+    pub actual_time: DateTime<Local>,
+    pub amount: u32,
+    /// 对应的提醒ID
+    #[serde(default)]
+    pub reminder_id: Option<Uuid>,
+    /// 跳过服药的原因
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub skip_reason: Option<SkipReason>,
+}
+
+impl DoseEvent {
+    pub fn new(
+        medicine_id: Uuid,
+        medicine_name: String,
+        status: DoseStatus,
+        scheduled_time: Option<DateTime<Local>>,
+        amount: u32,
+    ) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            medicine_id,
+            medicine_name,
+            status,
+            scheduled_time,
+            actual_time: Local::now(),
+            amount,
+            reminder_id: None,
+            skip_reason: None,
+        }
+    }
+
+    pub fn with_reminder(mut self, reminder_id: Uuid) -> Self {
+        self.reminder_id = Some(reminder_id);
+        self
+    }
+
+    pub fn with_skip_reason(mut self, reason: Option<SkipReason>) -> Self {
+        self.skip_reason = reason;
+        self
+    }
+}
+
+pub type DoseHistory = Vec<DoseEvent>;
+
+/// 服药记录的筛选条件，日期范围包含起止日期
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct HistoryFilter {
+    pub medicine_id: Option<Uuid>,
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+}
+
+impl HistoryFilter {
+    /// 日期按 `tz` 时区比较
+    pub fn matches(&self, event: &DoseEvent, tz: Tz) -> bool {
+        let date = event.actual_time.with_timezone(&tz).date_naive();
+        self.medicine_id.is_none_or(|id| event.medicine_id == id)
+            && self.from.is_none_or(|from| date >= from)
+            && self.to.is_none_or(|to| date <= to)
+    }
+}
+
+/// 按时间倒序返回指定页（从0开始）的记录以及总页数
+pub fn query_history<'a>(
+    history: &'a [DoseEvent],
+    filter: &HistoryFilter,
+    tz: Tz,
+    page: usize,
+    page_size: usize,
+) -> (Vec<&'a DoseEvent>, usize) {
+    let mut matched: Vec<&DoseEvent> = history.iter().filter(|event| filter.matches(event, tz)).collect();
+    matched.sort_by_key(|event| std::cmp::Reverse(event.actual_time));
+
+    let page_size = page_size.max(1);
+    let total_pages = matched.len().div_ceil(page_size);
+    let page_events = matched.into_iter().skip(page * page_size).take(page_size).collect();
+    (page_events, total_pages)
+}
@@ -1,12 +1,28 @@
+pub mod access;
 pub mod bot;
+pub mod callback;
+pub mod dialogue_storage;
+pub mod history;
 pub mod localization;
 pub mod medicine;
 pub mod reminder;
+pub mod schedule;
+pub mod scheduler;
+pub mod sqlite_storage;
+pub mod stats;
+pub mod stock;
 pub mod storage;
+pub mod timezone;
+pub mod travel;
 
 #[cfg(test)]
 mod test_localization;
 
+pub use callback::*;
+pub use history::*;
 pub use medicine::*;
 pub use reminder::*;
+pub use schedule::*;
+pub use sqlite_storage::*;
+pub use stock::*;
 pub use storage::*;
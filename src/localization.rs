@@ -1,4 +1,12 @@
-use crate::storage::Language;
+use crate::storage::{Language, UNDO_WINDOW_MINUTES};
+use crate::stats::AdherenceReport;
+use crate::travel::{TravelEvent, TravelPlan};
+use crate::{
+    DoseEvent, DoseStatus, EditField, EscalationAction, EscalationPolicy, Medicine, MedicineStore, PendingReminder, PrnPolicy,
+    Recurrence, Schedule, SkipReason, StockAlert, StockLevel, SupplyForecast, UserData,
+};
+use chrono::{DateTime, Duration, Local, NaiveDate, NaiveTime, Weekday};
+use chrono_tz::Tz;
 
 pub struct LocalizedText {
     pub help_title: &'static str,
@@ -37,6 +45,127 @@ pub struct LocalizedText {
     pub enter_custom_amount: &'static str,
     pub pills_unit: &'static str,
     pub pieces_unit: &'static str,
+    pub owner_only: &'static str,
+    pub invite_created: &'static str,
+    pub invite_usage: &'static str,
+    pub join_usage: &'static str,
+    pub join_success: &'static str,
+    pub join_invalid_code: &'static str,
+    pub join_expired_code: &'static str,
+    pub already_authorized: &'static str,
+    pub user_joined_notice: &'static str,
+    pub allowed_users_title: &'static str,
+    pub no_allowed_users: &'static str,
+    pub user_revoked: &'static str,
+    pub select_caregiver_target: &'static str,
+    pub all_medicines_button: &'static str,
+    pub enter_caregiver: &'static str,
+    pub invalid_caregiver: &'static str,
+    pub caregiver_set: &'static str,
+    pub caregiver_removed: &'static str,
+    pub escalation_title: &'static str,
+    pub acknowledge_button: &'static str,
+    pub mark_handled_button: &'static str,
+    pub caregiver_acknowledged: &'static str,
+    pub caregiver_handled: &'static str,
+    pub escalation_acknowledged: &'static str,
+    pub escalation_handled: &'static str,
+    pub not_caregiver: &'static str,
+    pub reminder_not_found: &'static str,
+    pub reminder_already_closed: &'static str,
+    pub enter_schedule: &'static str,
+    pub invalid_schedule: &'static str,
+    pub select_medicine_to_take: &'static str,
+    pub no_prn_medicines: &'static str,
+    pub prn_dose_recorded: &'static str,
+    pub next_dose_allowed: &'static str,
+    pub take_anyway_button: &'static str,
+    pub as_needed: &'static str,
+    pub history_title: &'static str,
+    pub no_history: &'static str,
+    pub prev_page_button: &'static str,
+    pub next_page_button: &'static str,
+    pub history_usage: &'static str,
+    pub stats_title: &'static str,
+    pub weekly_report_title: &'static str,
+    pub no_stats: &'static str,
+    pub stats_usage: &'static str,
+    pub weekly_report_enabled: &'static str,
+    pub weekly_report_disabled: &'static str,
+    pub low_stock_title: &'static str,
+    pub out_of_stock_title: &'static str,
+    pub out_of_stock_reminder: &'static str,
+    pub refill_button: &'static str,
+    pub select_stock_medicine: &'static str,
+    pub enter_stock_alert: &'static str,
+    pub invalid_stock_alert: &'static str,
+    pub stock_alert_saved: &'static str,
+    pub late_reminder_notice: &'static str,
+    pub missed_doses_title: &'static str,
+    pub expired_reminders_title: &'static str,
+    pub select_grace_medicine: &'static str,
+    pub enter_grace_minutes: &'static str,
+    pub invalid_grace_minutes: &'static str,
+    pub grace_saved: &'static str,
+    pub select_escalation_medicine: &'static str,
+    pub enter_escalation_policy: &'static str,
+    pub invalid_escalation_policy: &'static str,
+    pub escalation_saved: &'static str,
+    pub select_expiry_medicine: &'static str,
+    pub enter_expiry_minutes: &'static str,
+    pub invalid_expiry_minutes: &'static str,
+    pub expiry_saved: &'static str,
+    pub follow_ups_marked_missed: &'static str,
+    pub follow_ups_caregiver_notified: &'static str,
+    pub follow_ups_stopped: &'static str,
+    pub snooze_prompt: &'static str,
+    pub snooze_custom_button: &'static str,
+    pub snooze_until_button: &'static str,
+    pub enter_snooze_minutes: &'static str,
+    pub invalid_snooze_minutes: &'static str,
+    pub enter_snooze_until: &'static str,
+    pub invalid_snooze_until: &'static str,
+    pub reminder_snoozed: &'static str,
+    pub skip_button: &'static str,
+    pub select_skip_reminder: &'static str,
+    pub skip_reason_prompt: &'static str,
+    pub skip_no_reason_button: &'static str,
+    pub dose_skipped: &'static str,
+    pub take_all_button: &'static str,
+    pub undo_button: &'static str,
+    pub button_outdated: &'static str,
+    pub select_edit_medicine: &'static str,
+    pub select_edit_field: &'static str,
+    pub invalid_edit_value: &'static str,
+    pub edit_times_while_travelling: &'static str,
+    pub medicine_updated: &'static str,
+    pub select_pause_medicine: &'static str,
+    pub enter_pause_until: &'static str,
+    pub invalid_pause_until: &'static str,
+    pub select_resume_medicine: &'static str,
+    pub no_paused_medicines: &'static str,
+    pub medicine_not_paused: &'static str,
+    pub medicines_resumed: &'static str,
+    pub dose_undone: &'static str,
+    pub current_timezone: &'static str,
+    pub server_timezone_note: &'static str,
+    pub timezone_usage: &'static str,
+    pub invalid_timezone: &'static str,
+    pub timezone_saved: &'static str,
+    pub travel_enter_destination: &'static str,
+    pub travel_enter_dates: &'static str,
+    pub invalid_travel_dates: &'static str,
+    pub travel_no_difference: &'static str,
+    pub travel_plan_title: &'static str,
+    pub travel_confirm_button: &'static str,
+    pub travel_discard_button: &'static str,
+    pub travel_cancel_button: &'static str,
+    pub travel_saved: &'static str,
+    pub travel_discarded: &'static str,
+    pub travel_cancelled: &'static str,
+    pub travel_started: &'static str,
+    pub travel_adjusted: &'static str,
+    pub travel_returned: &'static str,
 }
 
 const CHINESE_TEXT: LocalizedText = LocalizedText {
@@ -47,7 +176,24 @@ const CHINESE_TEXT: LocalizedText = LocalizedText {
         /delete - 删除药品\n\
         /refill - 补充药品数量\n\
         /pending - 查看待确认的提醒\n\
+        /skip - 跳过待确认的服药\n\
         /language - 切换语言\n\
+        /take - 记录按需服药\n\
+        /history - 查看服药记录\n\
+        /stats - 查看服药统计\n\
+        /stock - 设置库存提醒\n\
+        /grace - 设置补发提醒的宽限时间\n\
+        /escalation - 设置未确认时的后续提醒策略\n\
+        /expiry - 设置未确认提醒的过期时间\n\
+        /edit - 修改药品的名称、库存、提醒时间、每次服用数量、备注或服药计划\n\
+        /pause - 暂停药品的提醒，可设置自动恢复的日期\n\
+        /resume - 恢复暂停的药品\n\
+        /timezone - 设置时区\n\
+        /travel - 旅行模式，逐日调整服药时间\n\
+        /caregiver - 设置照护人\n\
+        /invite - 生成邀请码（仅所有者）\n\
+        /users - 管理授权用户（仅所有者）\n\
+        /join - 使用邀请码加入\n\
         /help - 显示此帮助信息",
     help_usage: "💡 使用说明：\n\
         1. 使用 /add 添加药品，设置名称、数量和提醒时间\n\
@@ -58,7 +204,7 @@ const CHINESE_TEXT: LocalizedText = LocalizedText {
     add_medicine_prompt: "请输入药品名称：",
     enter_medicine_name: "请输入药品名称：",
     enter_quantity: "请输入药品数量：",
-    enter_reminder_times: "请输入提醒时间（格式：HH:MM，多个时间用逗号分隔）：\n例如：08:00,20:00",
+    enter_reminder_times: "请输入提醒时间（格式：HH:MM，多个时间用逗号分隔）：\n例如：08:00,20:00\n\n按需服用的药品请输入：prn 最短间隔小时 每日最多次数\n例如：prn 4h 6",
     medicine_added: "✅ 药品添加成功！",
     invalid_time_format: "❌ 时间格式错误！请使用 HH:MM 格式，例如：08:00,20:00",
     invalid_quantity: "❌ 数量格式错误！请输入有效的数字。",
@@ -87,6 +233,139 @@ const CHINESE_TEXT: LocalizedText = LocalizedText {
     enter_custom_amount: "请输入自定义数量：",
     pills_unit: "片",
     pieces_unit: "个",
+    owner_only: "❌ 只有机器人所有者可以使用此命令",
+    invite_created: "🎟️ 已生成一次性邀请码（24小时内有效）：",
+    invite_usage: "请让对方向机器人发送：",
+    join_usage: "请输入邀请码，例如：/join ABCD1234",
+    join_success: "✅ 已获得使用权限！使用 /help 查看可用命令。",
+    join_invalid_code: "❌ 邀请码无效或已被使用",
+    join_expired_code: "❌ 邀请码已过期，请向所有者索取新的邀请码",
+    already_authorized: "✅ 你已经有使用权限了",
+    user_joined_notice: "👤 新用户已通过邀请码加入：",
+    allowed_users_title: "👥 已授权的用户（点击移除）：",
+    no_allowed_users: "📭 暂无其他授权用户。使用 /invite 生成邀请码。",
+    user_revoked: "✅ 已移除该用户的使用权限",
+    select_caregiver_target: "请选择照护人负责的药品：",
+    all_medicines_button: "👥 所有药品",
+    enter_caregiver: "请输入照护人的 Chat ID，以及可选的未确认提醒次数（默认3次），例如：123456789 3\n照护人需要先向机器人发送过消息。\n输入 off 取消照护人",
+    invalid_caregiver: "❌ 格式错误！请输入 Chat ID 和可选的次数，例如：123456789 3，或输入 off",
+    caregiver_set: "✅ 照护人已设置",
+    caregiver_removed: "✅ 照护人已取消",
+    escalation_title: "⚠️ 服药提醒长时间未确认",
+    acknowledge_button: "👀 已知晓",
+    mark_handled_button: "✅ 已处理",
+    caregiver_acknowledged: "👀 你的照护人已知晓你尚未确认服药",
+    caregiver_handled: "✅ 你的照护人已将此次服药标记为已处理",
+    escalation_acknowledged: "👀 已通知对方你已知晓",
+    escalation_handled: "✅ 已标记为已处理，将不再提醒",
+    not_caregiver: "❌ 你不是该提醒的照护人",
+    reminder_not_found: "提醒信息未找到",
+    reminder_already_closed: "该提醒已确认或已处理",
+    enter_schedule: "请输入服药计划：\n\
+        • daily - 每天\n\
+        • mon,wed,fri - 每周指定几天\n\
+        • every 2d - 每隔2天\n\
+        • every 8h - 每隔8小时（从第一个提醒时间开始）\n\
+        • cycle 21/7 - 服用21天，停药7天\n\
+        可在后面加上 from 2024-01-01 to 2024-01-31 指定疗程起止日期",
+    invalid_schedule: "❌ 服药计划格式错误！例如：daily、mon,wed,fri、every 2d、every 8h、cycle 21/7",
+    select_medicine_to_take: "请选择要服用的按需药品：",
+    no_prn_medicines: "📭 暂无按需服用的药品。添加药品时输入 prn 4h 6 即可设置为按需服用。",
+    prn_dose_recorded: "✅ 已记录服药",
+    next_dose_allowed: "下次可服药时间",
+    take_anyway_button: "⚠️ 仍然服用",
+    as_needed: "按需服用",
+    history_title: "📜 服药记录",
+    no_history: "📭 暂无符合条件的服药记录。",
+    prev_page_button: "◀️ 上一页",
+    next_page_button: "下一页 ▶️",
+    history_usage: "❌ 参数错误！用法：/history [药品名称] [开始日期] [结束日期]\n例如：/history 维生素C 2024-01-01 2024-01-31",
+    stats_title: "📊 服药统计",
+    weekly_report_title: "📊 每周服药报告",
+    no_stats: "📭 这段时间内暂无按计划服药的记录。",
+    stats_usage: "❌ 参数错误！用法：/stats [天数]，或 /stats weekly on|off 开关每周报告",
+    weekly_report_enabled: "✅ 已开启每周服药报告，每周一上午发送。",
+    weekly_report_disabled: "🔕 已关闭每周服药报告。",
+    low_stock_title: "⚠️ 药品库存不足",
+    out_of_stock_title: "🚫 药品已用完",
+    out_of_stock_reminder: "🚫 到了服药时间，但药品已经用完，无法服药！请尽快补充：",
+    refill_button: "📦 补充药品",
+    select_stock_medicine: "请选择要设置库存提醒的药品：",
+    enter_stock_alert: "请输入库存提醒设置：数量阈值 [天数]\n例如：10 5 表示剩余不超过10个或预计不足5天用完时提醒\n阈值为0表示不按数量提醒，输入 default 恢复默认（不足3天时提醒）",
+    invalid_stock_alert: "❌ 格式错误！例如：10 5、0 7 或 default",
+    stock_alert_saved: "✅ 库存提醒设置已保存",
+    late_reminder_notice: "⚠️ 机器人暂停期间错过了这次提醒，现在补发：",
+    missed_doses_title: "❌ 机器人暂停期间，以下服药已超过宽限时间，记为错过：",
+    expired_reminders_title: "⌛ 以下提醒超时未确认，已记为错过：",
+    select_grace_medicine: "请选择要设置宽限时间的药品：",
+    enter_grace_minutes: "请输入宽限时间（分钟）：\n机器人暂停期间到期的服药，在宽限时间内恢复时会补发提醒，超过则记为错过。",
+    invalid_grace_minutes: "❌ 请输入0到1440之间的分钟数",
+    grace_saved: "✅ 宽限时间已保存",
+    select_escalation_medicine: "请选择要设置后续提醒策略的药品：",
+    enter_escalation_policy: "请输入后续提醒策略：间隔分钟数 [最多次数] [之后的处理]\n\
+        例如：\n\
+        5,10,15 - 5、10分钟后各提醒一次，之后每15分钟提醒直到确认\n\
+        10,30 4 missed - 最多提醒4次，之后记为错过\n\
+        15 3 caregiver - 最多提醒3次，之后通知照护人\n\
+        30 2 stop - 最多提醒2次，之后不再提醒\n\
+        default - 恢复默认",
+    invalid_escalation_policy: "❌ 格式错误，间隔为1到1440分钟，最多10个，处理方式为 missed、caregiver 或 stop",
+    escalation_saved: "✅ 后续提醒策略已保存",
+    select_expiry_medicine: "请选择要设置提醒过期时间的药品：",
+    enter_expiry_minutes: "请输入提醒在计划时间之后多少分钟过期：\n过期仍未确认的提醒会记为错过，不再提醒。输入 next 表示到下一次服药为止（默认）。",
+    invalid_expiry_minutes: "❌ 请输入1到10080之间的分钟数，或 next",
+    expiry_saved: "✅ 提醒过期时间已保存",
+    follow_ups_marked_missed: "❌ 多次提醒仍未确认，本次服药已记为错过",
+    follow_ups_caregiver_notified: "📣 多次提醒仍未确认，已通知照护人，不再提醒",
+    follow_ups_stopped: "🔕 多次提醒仍未确认，不再提醒。服药后仍可点击“已服药”确认",
+    snooze_prompt: "⏰ 要延迟多久？",
+    snooze_custom_button: "✏️ 自定义",
+    snooze_until_button: "🕐 到指定时间",
+    enter_snooze_minutes: "请输入延迟的分钟数：",
+    invalid_snooze_minutes: "❌ 请输入1到1440之间的分钟数",
+    enter_snooze_until: "请输入再次提醒的时间（HH:MM），已过的时间表示明天：",
+    invalid_snooze_until: "❌ 时间格式错误，请使用 HH:MM 格式",
+    reminder_snoozed: "⏰ 已延迟提醒，再次提醒时间：",
+    skip_button: "⏭️ 跳过",
+    select_skip_reminder: "请选择要跳过的提醒：",
+    skip_reason_prompt: "⏭️ 跳过本次服药的原因？",
+    skip_no_reason_button: "不填写原因",
+    dose_skipped: "⏭️ 已跳过本次服药，库存不变",
+    take_all_button: "✅ 全部已服药",
+    undo_button: "↩️ 撤销",
+    button_outdated: "⌛ 这个按钮已过期，请重新使用相应的命令",
+    select_edit_medicine: "请选择要修改的药品：",
+    select_edit_field: "请选择要修改的项目：",
+    invalid_edit_value: "❌ 输入无效，请重新输入：",
+    edit_times_while_travelling: "❌ 旅行期间提醒时间按旅行计划调整，请返回后再修改",
+    medicine_updated: "✅ 药品已更新",
+    select_pause_medicine: "请选择要暂停提醒的药品：",
+    enter_pause_until: "请输入恢复提醒的日期（YYYY-MM-DD），当天起自动恢复；\n输入 - 暂停到手动使用 /resume 恢复：",
+    invalid_pause_until: "❌ 日期无效，请输入今天之后的日期（YYYY-MM-DD），或输入 -：",
+    select_resume_medicine: "请选择要恢复提醒的药品：",
+    no_paused_medicines: "没有暂停中的药品",
+    medicine_not_paused: "该药品没有暂停",
+    medicines_resumed: "▶️ 已恢复提醒",
+    dose_undone: "↩️ 已撤销服药确认，库存已恢复，提醒重新打开",
+    current_timezone: "🌍 当前时区",
+    server_timezone_note: "（服务器时区）",
+    timezone_usage: "使用 /timezone 时区名称 设置时区，例如 /timezone Asia/Shanghai；/timezone default 恢复使用服务器时区。\n提醒时间按所设时区计算。",
+    invalid_timezone: "❌ 无法识别的时区，请使用 IANA 时区名称，例如 Asia/Shanghai、Europe/London、America/New_York",
+    timezone_saved: "✅ 时区已设置为",
+    travel_enter_destination: "✈️ 请输入目的地时区（IANA 名称），例如 Europe/Paris：",
+    travel_enter_dates: "请输入出发日期、返回日期（可选）和每天调整的分钟数（可选，默认60），例如：\n2024-05-01 2024-05-20 60",
+    invalid_travel_dates: "❌ 格式错误。日期格式为 YYYY-MM-DD，出发日期不能早于今天，返回日期须晚于出发日期，每天调整1到720分钟",
+    travel_no_difference: "目的地与当前时区没有时差，无需调整服药时间。",
+    travel_plan_title: "✈️ 旅行计划",
+    travel_confirm_button: "✅ 应用计划",
+    travel_discard_button: "❌ 放弃",
+    travel_cancel_button: "🏠 结束旅行模式",
+    travel_saved: "✅ 旅行计划已保存，出发当天开始逐日调整服药时间。",
+    travel_discarded: "已放弃旅行计划。",
+    travel_cancelled: "🏠 已结束旅行模式，时区和服药时间已恢复。",
+    travel_started: "✈️ 旅行模式已开始，时区已切换为目的地时区。今天的服药时间：",
+    travel_adjusted: "✈️ 今天的服药时间已调整：",
+    travel_returned: "🏠 欢迎回来！时区和服药时间已恢复。",
 };
 
 const ENGLISH_TEXT: LocalizedText = LocalizedText {
@@ -97,7 +376,24 @@ const ENGLISH_TEXT: LocalizedText = LocalizedText {
         /delete - Delete medicine\n\
         /refill - Refill medicine quantity\n\
         /pending - View pending reminders\n\
+        /skip - Skip a pending dose\n\
         /language - Switch language\n\
+        /take - Record an as-needed dose\n\
+        /history - View dose history\n\
+        /stats - View adherence statistics\n\
+        /stock - Set low-stock alerts\n\
+        /grace - Set the late-reminder grace window\n\
+        /escalation - Set the follow-up policy for unconfirmed reminders\n\
+        /expiry - Set when unconfirmed reminders expire\n\
+        /edit - Change a medicine's name, stock, times, dose amount, notes or schedule\n\
+        /pause - Pause a medicine's reminders, optionally until a date\n\
+        /resume - Resume a paused medicine\n\
+        /timezone - Set your timezone\n\
+        /travel - Travel mode, shift dosing times day by day\n\
+        /caregiver - Set a caregiver\n\
+        /invite - Create an invite code (owner only)\n\
+        /users - Manage allowed users (owner only)\n\
+        /join - Join with an invite code\n\
         /help - Show this help message",
     help_usage: "💡 Usage Instructions:\n\
         1. Use /add to add medicine, set name, quantity and reminder times\n\
@@ -108,7 +404,7 @@ const ENGLISH_TEXT: LocalizedText = LocalizedText {
     add_medicine_prompt: "Please enter medicine name:",
     enter_medicine_name: "Please enter medicine name:",
     enter_quantity: "Please enter medicine quantity:",
-    enter_reminder_times: "Please enter reminder times (format: HH:MM, separate multiple times with commas):\nExample: 08:00,20:00",
+    enter_reminder_times: "Please enter reminder times (format: HH:MM, separate multiple times with commas):\nExample: 08:00,20:00\n\nFor as-needed medicine enter: prn <min interval hours> <max doses per day>\nExample: prn 4h 6",
     medicine_added: "✅ Medicine added successfully!",
     invalid_time_format: "❌ Invalid time format! Please use HH:MM format, example: 08:00,20:00",
     invalid_quantity: "❌ Invalid quantity format! Please enter a valid number.",
@@ -137,6 +433,139 @@ const ENGLISH_TEXT: LocalizedText = LocalizedText {
     enter_custom_amount: "Please enter custom amount:",
     pills_unit: "pills",
     pieces_unit: "pcs",
+    owner_only: "❌ Only the bot owner can use this command",
+    invite_created: "🎟️ One-time invite code created (valid for 24 hours):",
+    invite_usage: "Ask the other person to send this to the bot:",
+    join_usage: "Please enter an invite code, e.g.: /join ABCD1234",
+    join_success: "✅ Access granted! Use /help to see available commands.",
+    join_invalid_code: "❌ Invalid or already used invite code",
+    join_expired_code: "❌ Invite code expired, please ask the owner for a new one",
+    already_authorized: "✅ You already have access",
+    user_joined_notice: "👤 A new user joined with an invite code:",
+    allowed_users_title: "👥 Allowed users (tap to remove):",
+    no_allowed_users: "📭 No other allowed users. Use /invite to create an invite code.",
+    user_revoked: "✅ User access removed",
+    select_caregiver_target: "Please select which medicines the caregiver is responsible for:",
+    all_medicines_button: "👥 All medicines",
+    enter_caregiver: "Please enter the caregiver's Chat ID and an optional number of unanswered reminders (default 3), e.g.: 123456789 3\nThe caregiver must have messaged the bot before.\nEnter off to remove the caregiver",
+    invalid_caregiver: "❌ Invalid format! Enter a Chat ID and an optional count, e.g.: 123456789 3, or enter off",
+    caregiver_set: "✅ Caregiver set",
+    caregiver_removed: "✅ Caregiver removed",
+    escalation_title: "⚠️ Medicine reminder not confirmed",
+    acknowledge_button: "👀 Acknowledge",
+    mark_handled_button: "✅ Mark handled",
+    caregiver_acknowledged: "👀 Your caregiver knows you have not confirmed your dose yet",
+    caregiver_handled: "✅ Your caregiver marked this dose as handled",
+    escalation_acknowledged: "👀 The patient has been told you acknowledged",
+    escalation_handled: "✅ Marked as handled, no more reminders",
+    not_caregiver: "❌ You are not the caregiver for this reminder",
+    reminder_not_found: "Reminder information not found",
+    reminder_already_closed: "This reminder is already confirmed or handled",
+    enter_schedule: "Please enter the dosing schedule:\n\
+        • daily - every day\n\
+        • mon,wed,fri - specific weekdays\n\
+        • every 2d - every 2 days\n\
+        • every 8h - every 8 hours (starting from the first reminder time)\n\
+        • cycle 21/7 - 21 days on, 7 days off\n\
+        Optionally add from 2024-01-01 to 2024-01-31 to set the course dates",
+    invalid_schedule: "❌ Invalid schedule! Examples: daily, mon,wed,fri, every 2d, every 8h, cycle 21/7",
+    select_medicine_to_take: "Please select the as-needed medicine to take:",
+    no_prn_medicines: "📭 No as-needed medicines. Enter prn 4h 6 when adding a medicine to make it as-needed.",
+    prn_dose_recorded: "✅ Dose recorded",
+    next_dose_allowed: "Next dose allowed",
+    take_anyway_button: "⚠️ Take anyway",
+    as_needed: "As needed",
+    history_title: "📜 Dose History",
+    no_history: "📭 No matching dose records.",
+    prev_page_button: "◀️ Previous",
+    next_page_button: "Next ▶️",
+    history_usage: "❌ Invalid arguments! Usage: /history [medicine name] [start date] [end date]\nExample: /history Vitamin C 2024-01-01 2024-01-31",
+    stats_title: "📊 Adherence Statistics",
+    weekly_report_title: "📊 Weekly Adherence Report",
+    no_stats: "📭 No scheduled doses in this period.",
+    stats_usage: "❌ Invalid arguments! Usage: /stats [days], or /stats weekly on|off to toggle the weekly report",
+    weekly_report_enabled: "✅ Weekly adherence report enabled, sent every Monday morning.",
+    weekly_report_disabled: "🔕 Weekly adherence report disabled.",
+    low_stock_title: "⚠️ Running low on medicine",
+    out_of_stock_title: "🚫 Medicine used up",
+    out_of_stock_reminder: "🚫 A dose is due, but this medicine has run out! Please refill soon:",
+    refill_button: "📦 Refill",
+    select_stock_medicine: "Select a medicine to set low-stock alerts:",
+    enter_stock_alert: "Enter the low-stock alert: threshold [days]\nExample: 10 5 alerts when 10 or fewer are left or supply lasts less than 5 days\nA threshold of 0 disables the quantity alert; enter default to restore the default (less than 3 days)",
+    invalid_stock_alert: "❌ Invalid format! Example: 10 5, 0 7 or default",
+    stock_alert_saved: "✅ Low-stock alert saved",
+    late_reminder_notice: "⚠️ This reminder was missed while the bot was paused, sending it now:",
+    missed_doses_title: "❌ While the bot was paused, these doses passed their grace window and were recorded as missed:",
+    expired_reminders_title: "⌛ These reminders were not confirmed in time and were recorded as missed:",
+    select_grace_medicine: "Select a medicine to set its grace window:",
+    enter_grace_minutes: "Enter the grace window in minutes:\nDoses that come due while the bot is paused are still reminded if it recovers within this window; otherwise they are recorded as missed.",
+    invalid_grace_minutes: "❌ Please enter a number of minutes between 0 and 1440",
+    grace_saved: "✅ Grace window saved",
+    select_escalation_medicine: "Select a medicine to set its follow-up policy:",
+    enter_escalation_policy: "Enter the follow-up policy: intervals in minutes [max follow-ups] [then]\n\
+        Examples:\n\
+        5,10,15 - remind after 5 and 10 minutes, then every 15 minutes until confirmed\n\
+        10,30 4 missed - at most 4 follow-ups, then record the dose as missed\n\
+        15 3 caregiver - at most 3 follow-ups, then notify the caregiver\n\
+        30 2 stop - at most 2 follow-ups, then stop reminding\n\
+        default - restore the default",
+    invalid_escalation_policy: "❌ Invalid format. Use up to 10 intervals of 1-1440 minutes; the action is missed, caregiver or stop",
+    escalation_saved: "✅ Follow-up policy saved",
+    select_expiry_medicine: "Select a medicine to set when its reminders expire:",
+    enter_expiry_minutes: "Enter how many minutes after the scheduled time a reminder expires:\nReminders still unconfirmed then are recorded as missed and stop. Enter next to keep them until the next dose (default).",
+    invalid_expiry_minutes: "❌ Please enter a number of minutes between 1 and 10080, or next",
+    expiry_saved: "✅ Reminder expiry saved",
+    follow_ups_marked_missed: "❌ Still not confirmed after several reminders, the dose was recorded as missed",
+    follow_ups_caregiver_notified: "📣 Still not confirmed after several reminders, your caregiver was notified and reminders stopped",
+    follow_ups_stopped: "🔕 Still not confirmed after several reminders, reminders stopped. You can still tap Taken once you take it",
+    snooze_prompt: "⏰ Snooze for how long?",
+    snooze_custom_button: "✏️ Custom",
+    snooze_until_button: "🕐 Until a time",
+    enter_snooze_minutes: "Enter the number of minutes to snooze:",
+    invalid_snooze_minutes: "❌ Please enter a number of minutes between 1 and 1440",
+    enter_snooze_until: "Enter the time to remind again (HH:MM); a time that has passed means tomorrow:",
+    invalid_snooze_until: "❌ Invalid time format, please use HH:MM",
+    reminder_snoozed: "⏰ Reminder snoozed, will remind again at ",
+    skip_button: "⏭️ Skip",
+    select_skip_reminder: "Select the reminder to skip:",
+    skip_reason_prompt: "⏭️ Why are you skipping this dose?",
+    skip_no_reason_button: "No reason",
+    dose_skipped: "⏭️ Dose skipped, stock unchanged",
+    take_all_button: "✅ Took all",
+    undo_button: "↩️ Undo",
+    button_outdated: "⌛ This button is outdated, please use the command again",
+    select_edit_medicine: "Select the medicine to edit:",
+    select_edit_field: "Choose what to change:",
+    invalid_edit_value: "❌ Invalid value, please try again:",
+    edit_times_while_travelling: "❌ Reminder times follow your travel plan while travelling; change them after you return",
+    medicine_updated: "✅ Medicine updated",
+    select_pause_medicine: "Select the medicine to pause:",
+    enter_pause_until: "Enter the date to resume reminders (YYYY-MM-DD); they resume automatically from that day.\nEnter - to pause until you use /resume:",
+    invalid_pause_until: "❌ Invalid date, please enter a date after today (YYYY-MM-DD), or -:",
+    select_resume_medicine: "Select the medicine to resume:",
+    no_paused_medicines: "No medicines are paused",
+    medicine_not_paused: "This medicine is not paused",
+    medicines_resumed: "▶️ Reminders resumed",
+    dose_undone: "↩️ Dose confirmation undone, stock restored and the reminder reopened",
+    current_timezone: "🌍 Current timezone",
+    server_timezone_note: " (server timezone)",
+    timezone_usage: "Use /timezone NAME to set your timezone, e.g. /timezone Europe/London; /timezone default goes back to the server timezone.\nReminder times follow the timezone you set.",
+    invalid_timezone: "❌ Unknown timezone, please use an IANA timezone name such as Asia/Shanghai, Europe/London or America/New_York",
+    timezone_saved: "✅ Timezone set to",
+    travel_enter_destination: "✈️ Enter the destination timezone (IANA name), e.g. Europe/Paris:",
+    travel_enter_dates: "Enter the departure date, an optional return date and optional minutes to shift per day (default 60), e.g.:\n2024-05-01 2024-05-20 60",
+    invalid_travel_dates: "❌ Invalid format. Dates use YYYY-MM-DD, departure can't be in the past, return must be after departure, and the daily shift is 1 to 720 minutes",
+    travel_no_difference: "The destination has no time difference from your current timezone, no need to shift dosing times.",
+    travel_plan_title: "✈️ Travel Plan",
+    travel_confirm_button: "✅ Apply plan",
+    travel_discard_button: "❌ Discard",
+    travel_cancel_button: "🏠 End travel mode",
+    travel_saved: "✅ Travel plan saved. Dosing times will shift day by day from the departure date.",
+    travel_discarded: "Travel plan discarded.",
+    travel_cancelled: "🏠 Travel mode ended, your timezone and dosing times have been restored.",
+    travel_started: "✈️ Travel mode started and your timezone is now the destination's. Today's dosing times:",
+    travel_adjusted: "✈️ Today's dosing times have been shifted:",
+    travel_returned: "🏠 Welcome back! Your timezone and dosing times have been restored.",
 };
 
 pub fn get_text(language: &Language) -> &'static LocalizedText {
@@ -164,6 +593,598 @@ pub fn format_reminder_message(language: &Language, medicine_name: &str, time: &
     )
 }
 
+/// 同一时间多种药品的提醒，`doses` 为药品名称和每次服用数量
+pub fn format_grouped_reminder_message(language: &Language, doses: &[(String, u32)], time: &str) -> String {
+    let text = get_text(language);
+    let is_chinese = matches!(language, Language::Chinese);
+    let mut message = format!(
+        "{}\n\n⏰ {}：{}\n",
+        text.reminder_message,
+        if is_chinese { "时间" } else { "Time" },
+        time
+    );
+    for (medicine_name, amount) in doses {
+        message.push_str(&format!("\n💊 {} ×{}", medicine_name, amount));
+    }
+    message.push_str(&format!(
+        "\n\n{}",
+        if is_chinese { "请逐个确认，或点击“全部已服药”按默认数量确认全部" } else { "Confirm each medicine, or tap \"Took all\" to confirm them all with their usual amounts" }
+    ));
+    message
+}
+
+/// 提醒的当前状态，显示在原提醒消息中；`outcome` 为该提醒最后的服药记录（不含延迟），未处理时返回 `None`
+pub fn format_reminder_status(
+    language: &Language,
+    tz: Tz,
+    reminder: &PendingReminder,
+    outcome: Option<&DoseEvent>,
+) -> Option<String> {
+    let is_chinese = matches!(language, Language::Chinese);
+    if !reminder.is_confirmed {
+        return reminder.snoozed_until.map(|until| {
+            let until = until.with_timezone(&tz).format("%H:%M");
+            if is_chinese { format!("⏰ 延迟到 {}", until) } else { format!("⏰ Snoozed until {}", until) }
+        });
+    }
+
+    let status = match outcome {
+        Some(event) if event.status == DoseStatus::Taken => {
+            let time = event.actual_time.with_timezone(&tz).format("%H:%M");
+            if is_chinese {
+                format!("✅ 已于 {} 服药，{}片", time, event.amount)
+            } else {
+                let unit = if event.amount == 1 { "pill" } else { "pills" };
+                format!("✅ Taken at {}, {} {}", time, event.amount, unit)
+            }
+        }
+        Some(event) => {
+            let mut status = format_dose_status(language, event.status).to_string();
+            if let Some(reason) = event.skip_reason {
+                status.push_str(&format!(" - {}", format_skip_reason(language, reason)));
+            }
+            status
+        }
+        None => if is_chinese { "✅ 已处理".to_string() } else { "✅ Handled".to_string() },
+    };
+    Some(status)
+}
+
+/// 按提醒的最新状态重新生成提醒消息。`entries` 为同一条消息中的提醒、每次服用数量和状态
+pub fn format_reminder_view(language: &Language, tz: Tz, entries: &[(&PendingReminder, u32, Option<String>)]) -> String {
+    let text = get_text(language);
+    let is_chinese = matches!(language, Language::Chinese);
+    let Some((first, _, _)) = entries.first() else {
+        return String::new();
+    };
+
+    let is_follow_up = entries.iter().any(|(reminder, _, _)| !reminder.is_confirmed && reminder.reminder_count > 1);
+    let title = match (is_follow_up, is_chinese) {
+        (true, true) => "🔔 再次提醒吃药！",
+        (true, false) => "🔔 Medicine Reminder Again!",
+        (false, _) => text.reminder_message,
+    };
+    let mut message = format!(
+        "{}\n\n⏰ {}：{}\n",
+        title,
+        if is_chinese { "时间" } else { "Time" },
+        first.scheduled_time.with_timezone(&tz).format("%H:%M")
+    );
+
+    for (reminder, amount, status) in entries {
+        message.push_str(&format!("\n💊 {} ×{}", reminder.medicine_name, amount));
+        match status {
+            Some(status) => message.push_str(&format!("\n{}", status)),
+            None if reminder.reminder_count > 1 => message.push_str(&format!(
+                "\n📊 {}：{}",
+                if is_chinese { "提醒次数" } else { "Reminder count" },
+                reminder.reminder_count
+            )),
+            None => {}
+        }
+    }
+
+    if entries.iter().any(|(reminder, _, _)| !reminder.is_confirmed) {
+        message.push_str(&format!(
+            "\n\n{}",
+            if is_chinese { "请点击下面的按钮确认已服药" } else { "Please click the button below to confirm you have taken the medicine" }
+        ));
+    }
+    message
+}
+
+pub fn format_escalation_message(
+    language: &Language,
+    patient: &str,
+    medicine_name: &str,
+    time: &str,
+    reminder_count: u32,
+) -> String {
+    let text = get_text(language);
+    format!(
+        "{}\n\n👤 {}：{}\n💊 {}：{}\n⏰ {}：{}\n📊 {}：{}",
+        text.escalation_title,
+        if matches!(language, Language::Chinese) { "患者" } else { "Patient" },
+        patient,
+        if matches!(language, Language::Chinese) { "药品" } else { "Medicine" },
+        medicine_name,
+        if matches!(language, Language::Chinese) { "原定时间" } else { "Scheduled time" },
+        time,
+        if matches!(language, Language::Chinese) { "提醒次数" } else { "Reminder count" },
+        reminder_count
+    )
+}
+
+/// 暂停状态，`until` 为自动恢复的日期
+pub fn format_pause_status(language: &Language, until: Option<NaiveDate>) -> String {
+    match (until, matches!(language, Language::Chinese)) {
+        (Some(until), true) => format!("⏸️ 已暂停，{} 起自动恢复", until.format("%Y-%m-%d")),
+        (Some(until), false) => format!("⏸️ Paused, resumes automatically on {}", until.format("%Y-%m-%d")),
+        (None, true) => "⏸️ 已暂停，使用 /resume 恢复".to_string(),
+        (None, false) => "⏸️ Paused, use /resume to resume".to_string(),
+    }
+}
+
+pub fn format_edit_field(language: &Language, field: EditField) -> &'static str {
+    let is_chinese = matches!(language, Language::Chinese);
+    match (field, is_chinese) {
+        (EditField::Name, true) => "名称",
+        (EditField::Name, false) => "Name",
+        (EditField::Stock, true) => "库存",
+        (EditField::Stock, false) => "Stock",
+        (EditField::Times, true) => "提醒时间",
+        (EditField::Times, false) => "Reminder times",
+        (EditField::DoseAmount, true) => "每次服用数量",
+        (EditField::DoseAmount, false) => "Dose amount",
+        (EditField::Notes, true) => "备注",
+        (EditField::Notes, false) => "Notes",
+        (EditField::Schedule, true) => "服药计划",
+        (EditField::Schedule, false) => "Schedule",
+    }
+}
+
+/// 药品字段的当前值
+pub fn format_edit_value(language: &Language, medicine: &Medicine, field: EditField) -> String {
+    match field {
+        EditField::Name => medicine.name.clone(),
+        EditField::Stock => medicine.quantity.to_string(),
+        EditField::Times => medicine
+            .reminder_times
+            .iter()
+            .map(|t| t.format("%H:%M").to_string())
+            .collect::<Vec<_>>()
+            .join(", "),
+        EditField::DoseAmount => medicine.dose_amount.to_string(),
+        EditField::Notes => medicine
+            .notes
+            .clone()
+            .unwrap_or_else(|| if matches!(language, Language::Chinese) { "无" } else { "None" }.to_string()),
+        EditField::Schedule => format_schedule(language, &medicine.schedule),
+    }
+}
+
+/// 修改药品字段时的提示，包括当前值
+pub fn format_edit_prompt(language: &Language, medicine: &Medicine, field: EditField) -> String {
+    let is_chinese = matches!(language, Language::Chinese);
+    let instructions = match (field, is_chinese) {
+        (EditField::Name, true) => "请输入新的名称：",
+        (EditField::Name, false) => "Please enter the new name:",
+        (EditField::Stock, true) => "请输入当前的剩余数量：",
+        (EditField::Stock, false) => "Please enter the quantity you have left:",
+        (EditField::Times, true) => "请输入新的提醒时间（格式：HH:MM，多个时间用逗号分隔）：\n例如：08:00,20:00",
+        (EditField::Times, false) => "Please enter the new reminder times (format: HH:MM, separated by commas):\nExample: 08:00,20:00",
+        (EditField::DoseAmount, true) => "请输入每次服用的数量：",
+        (EditField::DoseAmount, false) => "Please enter the amount taken per dose:",
+        (EditField::Notes, true) => "请输入备注，输入 - 清除备注：",
+        (EditField::Notes, false) => "Please enter the notes, or - to clear them:",
+        (EditField::Schedule, _) => get_text(language).enter_schedule,
+    };
+    format!(
+        "💊 {}\n✏️ {}：{}\n\n{}",
+        medicine.name,
+        format_edit_field(language, field),
+        format_edit_value(language, medicine, field),
+        instructions
+    )
+}
+
+pub fn format_schedule(language: &Language, schedule: &Schedule) -> String {
+    let is_chinese = matches!(language, Language::Chinese);
+
+    let mut result = match &schedule.recurrence {
+        Recurrence::Daily => if is_chinese { "每天".to_string() } else { "Every day".to_string() },
+        Recurrence::Weekdays { days } => {
+            let names: Vec<&str> = days.iter().map(|day| format_weekday(language, *day)).collect();
+            if is_chinese {
+                format!("每周{}", names.join("、"))
+            } else {
+                format!("Every {}", names.join(", "))
+            }
+        }
+        Recurrence::EveryNDays { days } => {
+            if is_chinese { format!("每{}天", days) } else { format!("Every {} days", days) }
+        }
+        Recurrence::EveryNHours { hours } => {
+            if is_chinese { format!("每{}小时", hours) } else { format!("Every {} hours", hours) }
+        }
+        Recurrence::Cycle { days_on, days_off } => {
+            if is_chinese {
+                format!("服用{}天，停药{}天", days_on, days_off)
+            } else {
+                format!("{} days on, {} days off", days_on, days_off)
+            }
+        }
+    };
+
+    match (schedule.start_date, schedule.end_date) {
+        (None, None) => {}
+        (start, end) => {
+            let format_date = |date: Option<chrono::NaiveDate>| {
+                date.map(|d| d.format("%Y-%m-%d").to_string()).unwrap_or_default()
+            };
+            result.push_str(&format!(" ({} ~ {})", format_date(start), format_date(end)));
+        }
+    }
+
+    result
+}
+
+pub fn format_prn_policy(language: &Language, policy: &PrnPolicy) -> String {
+    let text = get_text(language);
+    if matches!(language, Language::Chinese) {
+        format!(
+            "{}（间隔至少{}小时，每天最多{}次）",
+            text.as_needed, policy.min_interval_hours, policy.max_daily_doses
+        )
+    } else {
+        format!(
+            "{} (at least {} hours apart, at most {} per day)",
+            text.as_needed, policy.min_interval_hours, policy.max_daily_doses
+        )
+    }
+}
+
+pub fn format_escalation_policy(language: &Language, policy: &EscalationPolicy) -> String {
+    let is_chinese = matches!(language, Language::Chinese);
+    let intervals = policy.intervals.iter().map(u32::to_string).collect::<Vec<_>>().join("/");
+    let Some(max) = policy.max_follow_ups else {
+        return if is_chinese {
+            format!("{}分钟，直到确认", intervals)
+        } else {
+            format!("{} min, until confirmed", intervals)
+        };
+    };
+
+    let after = match (policy.after, is_chinese) {
+        (EscalationAction::MarkMissed, true) => "记为错过",
+        (EscalationAction::MarkMissed, false) => "mark missed",
+        (EscalationAction::NotifyCaregiver, true) => "通知照护人",
+        (EscalationAction::NotifyCaregiver, false) => "notify caregiver",
+        (EscalationAction::Stop, true) => "停止提醒",
+        (EscalationAction::Stop, false) => "stop",
+    };
+    if is_chinese {
+        format!("{}分钟，最多{}次，之后{}", intervals, max, after)
+    } else {
+        format!("{} min, at most {}, then {}", intervals, max, after)
+    }
+}
+
+pub fn format_reminder_expiry(language: &Language, expire_after_minutes: Option<u32>) -> String {
+    match (expire_after_minutes, matches!(language, Language::Chinese)) {
+        (Some(minutes), _) => format_snooze_duration(language, minutes),
+        (None, true) => "到下一次服药".to_string(),
+        (None, false) => "until next dose".to_string(),
+    }
+}
+
+pub fn format_snooze_duration(language: &Language, minutes: u32) -> String {
+    let is_chinese = matches!(language, Language::Chinese);
+    match (minutes % 60, is_chinese) {
+        (0, true) => format!("{}小时", minutes / 60),
+        (0, false) => format!("{} h", minutes / 60),
+        (_, true) => format!("{}分钟", minutes),
+        (_, false) => format!("{} min", minutes),
+    }
+}
+
+pub fn format_supply_forecast(language: &Language, forecast: &SupplyForecast) -> String {
+    let is_chinese = matches!(language, Language::Chinese);
+    match (forecast.runs_out_on, forecast.days_left) {
+        (Some(date), Some(days)) => {
+            if is_chinese {
+                format!("预计 {} 用完（还剩 {} 天）", date.format("%Y-%m-%d"), days)
+            } else {
+                format!("Runs out on {} ({} days left)", date.format("%Y-%m-%d"), days)
+            }
+        }
+        _ => if is_chinese { "暂无法预计用完时间" } else { "No run-out date forecast" }.to_string(),
+    }
+}
+
+/// 旅行计划中最多列出的天数
+const MAX_LISTED_TRAVEL_DAYS: i64 = 14;
+
+fn format_time_difference(minutes: i64) -> String {
+    let sign = if minutes < 0 { '-' } else { '+' };
+    format!("{}{}:{:02}", sign, minutes.abs() / 60, minutes.abs() % 60)
+}
+
+fn format_times(times: &[NaiveTime]) -> String {
+    times.iter().map(|time| time.format("%H:%M").to_string()).collect::<Vec<_>>().join(", ")
+}
+
+pub fn format_travel_plan(language: &Language, plan: &TravelPlan, medicines: &MedicineStore) -> String {
+    let is_chinese = matches!(language, Language::Chinese);
+    let text = get_text(language);
+    let mut medicines: Vec<&Medicine> = medicines.values().filter(|m| m.prn_policy().is_none()).collect();
+    medicines.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let mut message = format!(
+        "{}\n🌍 {}：{}（{} {}）\n📅 {}：{}",
+        text.travel_plan_title,
+        if is_chinese { "目的地" } else { "Destination" },
+        plan.destination,
+        if is_chinese { "时差" } else { "time difference" },
+        format_time_difference(plan.time_difference_minutes()),
+        if is_chinese { "出发" } else { "Departure" },
+        plan.depart_date.format("%Y-%m-%d")
+    );
+    if let Some(return_date) = plan.return_date {
+        message.push_str(&format!(
+            "\n🏠 {}：{}",
+            if is_chinese { "返回" } else { "Return" },
+            return_date.format("%Y-%m-%d")
+        ));
+    }
+    message.push_str(&if is_chinese {
+        format!("\n⏱ 每天调整{}分钟，{} 起与当地时间一致\n", plan.step_minutes, plan.adjusted_on().format("%Y-%m-%d"))
+    } else {
+        format!(
+            "\n⏱ Shifting {} minutes per day, matching local time from {}\n",
+            plan.step_minutes,
+            plan.adjusted_on().format("%Y-%m-%d")
+        )
+    });
+
+    let last_day = plan.return_date.map_or(plan.adjusted_on(), |date| date.min(plan.adjusted_on()));
+    let days = (last_day - plan.depart_date).num_days();
+    for day in 0..=days.min(MAX_LISTED_TRAVEL_DAYS - 1) {
+        let date = plan.depart_date + Duration::days(day);
+        message.push_str(&format!("\n📅 {}", date.format("%m-%d")));
+        for medicine in &medicines {
+            message.push_str(&format!("\n  💊 {} {}", medicine.name, format_times(&plan.times_on(medicine, date))));
+        }
+    }
+    if days >= MAX_LISTED_TRAVEL_DAYS {
+        message.push_str("\n……");
+    }
+    message
+}
+
+/// 旅行计划开始、调整或结束时发送给用户的消息
+pub fn format_travel_event(language: &Language, event: TravelEvent, user: &UserData, now: DateTime<Local>) -> String {
+    let text = get_text(language);
+    let title = match event {
+        TravelEvent::Started => text.travel_started,
+        TravelEvent::Adjusted => text.travel_adjusted,
+        TravelEvent::Returned => return text.travel_returned.to_string(),
+    };
+
+    let mut message = title.to_string();
+    let mut medicines: Vec<&Medicine> = user.medicines.values().filter(|m| m.prn_policy().is_none()).collect();
+    medicines.sort_by(|a, b| a.name.cmp(&b.name));
+    for medicine in medicines {
+        message.push_str(&format!("\n💊 {}：{}", medicine.name, format_times(&medicine.reminder_times)));
+    }
+    if let Some(plan) = &user.user_settings.travel {
+        if plan.adjustment_on(plan.today(now)) == 0 {
+            message.push_str(if matches!(language, Language::Chinese) {
+                "\n\n✅ 已与当地时间一致"
+            } else {
+                "\n\n✅ Now matching local time"
+            });
+        }
+    }
+    message
+}
+
+pub fn format_stock_alert_settings(language: &Language, alert: &StockAlert) -> String {
+    let is_chinese = matches!(language, Language::Chinese);
+    let threshold = match alert.low_stock_threshold {
+        Some(threshold) if is_chinese => format!("剩余不超过{}个", threshold),
+        Some(threshold) => format!("{} or fewer left", threshold),
+        None => "-".to_string(),
+    };
+    if is_chinese {
+        format!("{}，不足{}天", threshold, alert.low_supply_days)
+    } else {
+        format!("{}, under {} days", threshold, alert.low_supply_days)
+    }
+}
+
+pub fn format_stock_alert(
+    language: &Language,
+    medicine_name: &str,
+    quantity: u32,
+    level: StockLevel,
+    forecast: &SupplyForecast,
+) -> String {
+    let text = get_text(language);
+    let title = if level == StockLevel::Empty { text.out_of_stock_title } else { text.low_stock_title };
+    format!(
+        "{}\n💊 {}\n📦 {}：{}\n📉 {}",
+        title,
+        medicine_name,
+        if matches!(language, Language::Chinese) { "剩余数量" } else { "Remaining" },
+        quantity,
+        format_supply_forecast(language, forecast)
+    )
+}
+
+/// 报告中最多列出的错过服药次数
+const MAX_LISTED_MISSED_DOSES: usize = 20;
+
+pub fn format_missed_doses(language: &Language, tz: Tz, title: &str, missed: &[DoseEvent]) -> String {
+    let mut message = title.to_string();
+    for event in missed.iter().take(MAX_LISTED_MISSED_DOSES) {
+        let scheduled = event.scheduled_time.unwrap_or(event.actual_time).with_timezone(&tz);
+        message.push_str(&format!("\n💊 {} - {}", event.medicine_name, scheduled.format("%m-%d %H:%M")));
+    }
+    if missed.len() > MAX_LISTED_MISSED_DOSES {
+        message.push_str(&if matches!(language, Language::Chinese) {
+            format!("\n……共 {} 次", missed.len())
+        } else {
+            format!("\n... {} in total", missed.len())
+        });
+    }
+    message
+}
+
+pub fn format_dose_status(language: &Language, status: DoseStatus) -> &'static str {
+    let is_chinese = matches!(language, Language::Chinese);
+    match status {
+        DoseStatus::Taken => if is_chinese { "✅ 已服用" } else { "✅ Taken" },
+        DoseStatus::Skipped => if is_chinese { "⏭️ 已跳过" } else { "⏭️ Skipped" },
+        DoseStatus::Missed => if is_chinese { "❌ 已错过" } else { "❌ Missed" },
+        DoseStatus::Snoozed => if is_chinese { "⏰ 已延迟" } else { "⏰ Snoozed" },
+    }
+}
+
+/// 重复确认同一提醒时的回复，`taken` 为该提醒的服药记录
+pub fn format_already_confirmed(language: &Language, tz: Tz, taken: Option<&DoseEvent>) -> String {
+    match taken {
+        Some(event) => {
+            let time = event.actual_time.with_timezone(&tz).format("%H:%M");
+            if matches!(language, Language::Chinese) {
+                format!("该提醒已于 {} 确认服药（{}片），不会重复扣减库存", time, event.amount)
+            } else {
+                format!("Already confirmed at {} ({} taken), stock was not reduced again", time, event.amount)
+            }
+        }
+        None => get_text(language).reminder_already_closed.to_string(),
+    }
+}
+
+/// 无法撤销确认时的回复
+pub fn format_undo_unavailable(language: &Language) -> String {
+    if matches!(language, Language::Chinese) {
+        format!("❌ 无法撤销：只能撤销{}分钟内确认的服药", UNDO_WINDOW_MINUTES)
+    } else {
+        format!("❌ Cannot undo: only doses confirmed in the last {} minutes can be undone", UNDO_WINDOW_MINUTES)
+    }
+}
+
+pub fn format_skip_reason(language: &Language, reason: SkipReason) -> &'static str {
+    let is_chinese = matches!(language, Language::Chinese);
+    match reason {
+        SkipReason::Forgot => if is_chinese { "忘记了" } else { "Forgot" },
+        SkipReason::SideEffects => if is_chinese { "副作用" } else { "Side effects" },
+        SkipReason::DoctorAdvice => if is_chinese { "医嘱" } else { "Doctor's advice" },
+        SkipReason::OutOfStock => if is_chinese { "缺药" } else { "Out of stock" },
+    }
+}
+
+pub fn format_dose_event(language: &Language, tz: Tz, event: &DoseEvent) -> String {
+    let text = get_text(language);
+    let mut line = format!(
+        "{} {} {}",
+        event.actual_time.with_timezone(&tz).format("%m-%d %H:%M"),
+        format_dose_status(language, event.status),
+        event.medicine_name
+    );
+    if event.amount > 0 {
+        line.push_str(&format!(" ×{}", event.amount));
+    }
+    match event.scheduled_time {
+        Some(scheduled) => line.push_str(&format!(
+            " ({} {})",
+            if matches!(language, Language::Chinese) { "计划" } else { "scheduled" },
+            scheduled.with_timezone(&tz).format("%m-%d %H:%M")
+        )),
+        None => line.push_str(&format!(" ({})", text.as_needed)),
+    }
+    if let Some(reason) = event.skip_reason {
+        line.push_str(&format!(" - {}", format_skip_reason(language, reason)));
+    }
+    line
+}
+
+pub fn format_adherence_report(language: &Language, title: &str, report: &AdherenceReport) -> String {
+    let is_chinese = matches!(language, Language::Chinese);
+    // 统计区间不包含结束时间
+    let last_day = report.to - Duration::seconds(1);
+    let mut message = format!(
+        "{}\n{} – {}",
+        title,
+        report.from.with_timezone(&report.timezone).format("%Y-%m-%d"),
+        last_day.with_timezone(&report.timezone).format("%Y-%m-%d")
+    );
+
+    if report.is_empty() {
+        message.push_str(&format!("\n\n{}", get_text(language).no_stats));
+        return message;
+    }
+
+    for stats in &report.medicines {
+        message.push_str(&format!("\n\n💊 {}", stats.medicine_name));
+        if let Some(percent) = stats.adherence_percent() {
+            message.push_str(&format!(
+                "\n  {}：{:.0}%（{}/{}）",
+                if is_chinese { "依从率" } else { "Adherence" },
+                percent,
+                stats.taken(),
+                stats.total()
+            ));
+        }
+        message.push_str(&if is_chinese {
+            format!(
+                "\n  按时 {} · 迟服 {} · 跳过 {} · 错过 {}",
+                stats.on_time, stats.late, stats.skipped, stats.missed
+            )
+        } else {
+            format!(
+                "\n  On time {} · Late {} · Skipped {} · Missed {}",
+                stats.on_time, stats.late, stats.skipped, stats.missed
+            )
+        });
+        if let Some(minutes) = stats.average_lateness_minutes {
+            message.push_str(&if is_chinese {
+                format!("\n  平均延迟：{} 分钟", minutes)
+            } else {
+                format!("\n  Average delay: {} min", minutes)
+            });
+        }
+        message.push_str(&if is_chinese {
+            format!("\n  连续服药：{} 次（最长 {} 次）", stats.current_streak, stats.best_streak)
+        } else {
+            format!("\n  Streak: {} (best {})", stats.current_streak, stats.best_streak)
+        });
+    }
+
+    if !report.most_missed_slots.is_empty() {
+        message.push_str(if is_chinese { "\n\n⏰ 最常错过的时间：" } else { "\n\n⏰ Most missed times:" });
+        for (time, count) in &report.most_missed_slots {
+            message.push_str(&format!("\n  {} ×{}", time.format("%H:%M"), count));
+        }
+    }
+
+    message
+}
+
+fn format_weekday(language: &Language, day: Weekday) -> &'static str {
+    let is_chinese = matches!(language, Language::Chinese);
+    match day {
+        Weekday::Mon => if is_chinese { "一" } else { "Mon" },
+        Weekday::Tue => if is_chinese { "二" } else { "Tue" },
+        Weekday::Wed => if is_chinese { "三" } else { "Wed" },
+        Weekday::Thu => if is_chinese { "四" } else { "Thu" },
+        Weekday::Fri => if is_chinese { "五" } else { "Fri" },
+        Weekday::Sat => if is_chinese { "六" } else { "Sat" },
+        Weekday::Sun => if is_chinese { "日" } else { "Sun" },
+    }
+}
+
 pub fn format_medicine_list(language: &Language, medicines: &[(String, u32, String)]) -> String {
     let text = get_text(language);
     let mut result = format!("{}\n\n", text.medicines_list);
@@ -1,4 +1,4 @@
-use medicine_reminder::{bot, ReminderService, Storage};
+use medicine_reminder::{access::AccessConfig, bot, dialogue_storage::DialogueStorage, JsonStorage, ReminderService, SqliteStorage, StorageBackend};
 use std::env;
 use std::sync::Arc;
 use std::time::Duration;
@@ -22,23 +22,72 @@ async fn main() -> anyhow::Result<()> {
     let bot_token = env::var("TELOXIDE_TOKEN")
         .expect("请设置 TELOXIDE_TOKEN 环境变量");
 
-    let chat_id_str = env::var("CHAT_ID")
-        .expect("请设置 CHAT_ID 环境变量");
+    // CHAT_ID 可选：用于接收旧版数据和启动消息
+    let chat_id = env::var("CHAT_ID").ok().map(|chat_id_str| {
+        ChatId(chat_id_str.parse::<i64>()
+            .expect("CHAT_ID 必须是有效的数字"))
+    });
+
+    // OWNER_ID 未设置时，私聊的 CHAT_ID 即为所有者的用户ID
+    let owner_id = env::var("OWNER_ID")
+        .ok()
+        .map(|id| id.parse::<u64>().expect("OWNER_ID 必须是有效的数字"))
+        .or_else(|| chat_id.filter(|id| id.is_user()).map(|id| id.0 as u64));
 
-    let chat_id = ChatId(chat_id_str.parse::<i64>()
-        .expect("CHAT_ID 必须是有效的数字"));
+    let allowed_users: Vec<u64> = env::var("ALLOWED_USERS")
+        .map(|ids| {
+            ids.split(',')
+                .map(|s| s.trim())
+                .filter(|s| !s.is_empty())
+                .map(|s| s.parse::<u64>().expect("ALLOWED_USERS 必须是逗号分隔的数字"))
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let access_config = AccessConfig {
+        owner_id,
+        report_rejections: env::var("REPORT_UNAUTHORIZED")
+            .map(|v| matches!(v.to_lowercase().as_str(), "1" | "true" | "yes"))
+            .unwrap_or(false),
+    };
 
     // 创建Bot实例
     let bot = Bot::new(bot_token);
 
-    // 创建存储和提醒服务
-    let storage = Storage::new("medicine_data.json");
+    // 创建存储和提醒服务，STORAGE_BACKEND=sqlite 时使用 SQLite，默认使用 JSON 文件
+    let json_storage = JsonStorage::new("medicine_data.json");
+    let storage: Box<dyn StorageBackend> = match env::var("STORAGE_BACKEND")
+        .unwrap_or_default()
+        .to_lowercase()
+        .as_str()
+    {
+        "sqlite" => {
+            let database_path = env::var("DATABASE_PATH").unwrap_or_else(|_| "medicine_data.db".to_string());
+            let sqlite_storage = SqliteStorage::open(&database_path)
+                .map_err(|e| anyhow::anyhow!("打开数据库 {} 失败: {}", database_path, e))?;
+            // 首次使用 SQLite 时导入原有的 JSON 数据
+            sqlite_storage
+                .migrate_from_json(&json_storage)
+                .map_err(|e| anyhow::anyhow!("导入 JSON 数据失败: {}", e))?;
+            log::info!("使用 SQLite 存储: {}", database_path);
+            Box::new(sqlite_storage)
+        }
+        "" | "json" => Box::new(json_storage),
+        other => panic!("不支持的 STORAGE_BACKEND: {}（可选 json 或 sqlite）", other),
+    };
     let reminder_service = Arc::new(ReminderService::new(
         storage,
         bot.clone(),
         chat_id,
+        access_config,
     ));
 
+    if !allowed_users.is_empty() {
+        if let Err(e) = reminder_service.allow_users(&allowed_users).await {
+            log::error!("保存白名单失败: {}", e);
+        }
+    }
+
     // 启动提醒循环（在后台运行）
     let reminder_service_clone = reminder_service.clone();
     let reminder_handle = tokio::spawn(async move {
@@ -48,22 +97,24 @@ async fn main() -> anyhow::Result<()> {
     });
 
     // 发送启动消息（带超时）
-    let startup_data = reminder_service.get_data().await;
-    let startup_language = &startup_data.user_settings.language;
-    let startup_text = medicine_reminder::localization::get_text(startup_language);
-
-    let send_startup_msg = async {
-        if let Err(e) = bot
-            .send_message(chat_id, startup_text.startup_message)
-            .await
-        {
-            log::error!("发送启动消息失败: {}", e);
-        }
-    };
+    if let Some(chat_id) = chat_id {
+        let startup_data = reminder_service.get_user_data(chat_id).await;
+        let startup_language = &startup_data.user_settings.language;
+        let startup_text = medicine_reminder::localization::get_text(startup_language);
+
+        let send_startup_msg = async {
+            if let Err(e) = bot
+                .send_message(chat_id, startup_text.startup_message)
+                .await
+            {
+                log::error!("发送启动消息失败: {}", e);
+            }
+        };
 
-    // 5秒超时发送启动消息
-    if let Err(_) = tokio::time::timeout(Duration::from_secs(5), send_startup_msg).await {
-        log::warn!("发送启动消息超时");
+        // 5秒超时发送启动消息
+        if tokio::time::timeout(Duration::from_secs(5), send_startup_msg).await.is_err() {
+            log::warn!("发送启动消息超时");
+        }
     }
 
     log::info!("机器人已启动，等待消息...");
@@ -71,7 +122,7 @@ async fn main() -> anyhow::Result<()> {
     // 创建调度器
     let mut dispatcher = Dispatcher::builder(bot, bot::schema())
         .dependencies(dptree::deps![
-            teloxide::dispatching::dialogue::InMemStorage::<bot::State>::new(),
+            DialogueStorage::new(reminder_service.clone()),
             reminder_service
         ])
         .build();
@@ -1,8 +1,267 @@
-use chrono::{DateTime, Local, NaiveTime};
+use crate::{timezone, Schedule, StockAlert};
+use chrono::{DateTime, Duration, Local, NaiveDate, NaiveDateTime, NaiveTime};
+use chrono_tz::Tz;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use uuid::Uuid;
 
+/// 默认在多少次未确认的后续提醒之后通知照护人
+pub const DEFAULT_ESCALATE_AFTER: u32 = 3;
+
+/// 默认宽限时间（分钟）：停机期间到期的服药在此时间内仍会补发提醒，超过则记为错过
+pub const DEFAULT_GRACE_MINUTES: u32 = 60;
+
+fn default_grace_minutes() -> u32 {
+    DEFAULT_GRACE_MINUTES
+}
+
+fn default_dose_amount() -> u32 {
+    1
+}
+
+/// 默认的后续提醒间隔（分钟）：第1次后5分钟，第2次后10分钟，之后每15分钟
+pub const DEFAULT_FOLLOW_UP_INTERVALS: [u32; 3] = [5, 10, 15];
+
+/// 后续提醒间隔最长一天
+pub const MAX_FOLLOW_UP_INTERVAL_MINUTES: u32 = 24 * 60;
+
+/// 最多可以设置的后续提醒间隔个数
+pub const MAX_FOLLOW_UP_INTERVALS: usize = 10;
+
+/// 查找下一次服药作为提醒过期时间时，向后查找的天数
+const EXPIRY_SEARCH_DAYS: i64 = 8;
+
+/// 找不到下一次服药（例如疗程已结束）时，提醒在计划时间之后多久过期
+pub const FALLBACK_EXPIRY_HOURS: i64 = 24;
+
+/// 夏令时切换最多使时间偏移的小时数
+const DST_MARGIN_HOURS: i64 = 3;
+
+/// 照护人：提醒长时间未确认时接收通知的聊天
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Caregiver {
+    pub chat_id: i64,
+    /// 后续提醒达到该次数仍未确认时通知照护人
+    pub escalate_after: u32,
+    /// 通知中显示的患者名称
+    #[serde(default)]
+    pub patient_name: Option<String>,
+}
+
+/// 后续提醒达到最多次数后的处理方式
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum EscalationAction {
+    /// 记为错过并关闭提醒
+    #[default]
+    MarkMissed,
+    /// 通知照护人，之后不再提醒
+    NotifyCaregiver,
+    /// 不再提醒，提醒仍可确认
+    Stop,
+}
+
+/// 未确认提醒的后续提醒策略
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct EscalationPolicy {
+    /// 依次使用的后续提醒间隔（分钟），用完后重复最后一个
+    pub intervals: Vec<u32>,
+    /// 最多后续提醒次数，`None` 表示一直提醒到确认为止
+    pub max_follow_ups: Option<u32>,
+    /// 达到最多次数后的处理
+    pub after: EscalationAction,
+}
+
+impl Default for EscalationPolicy {
+    fn default() -> Self {
+        Self {
+            intervals: DEFAULT_FOLLOW_UP_INTERVALS.to_vec(),
+            max_follow_ups: None,
+            after: EscalationAction::default(),
+        }
+    }
+}
+
+impl EscalationPolicy {
+    /// 第 `reminder_count` 次提醒之后到下一次提醒的间隔
+    pub fn interval(&self, reminder_count: u32) -> Duration {
+        let index = (reminder_count.max(1) - 1) as usize;
+        let minutes = self
+            .intervals
+            .get(index)
+            .or(self.intervals.last())
+            .copied()
+            .unwrap_or(DEFAULT_FOLLOW_UP_INTERVALS[DEFAULT_FOLLOW_UP_INTERVALS.len() - 1]);
+        Duration::minutes(i64::from(minutes))
+    }
+
+    /// 解析 `5,10,15 [最多次数] [missed|caregiver|stop]` 格式，例如 `5,10,15 6 caregiver`。
+    /// 不指定次数时一直提醒到确认为止，指定次数但不指定处理方式时记为错过
+    pub fn parse(input: &str) -> Option<Self> {
+        let input = input.trim().to_lowercase();
+        let tokens: Vec<&str> = input.split_whitespace().collect();
+        let (intervals, max_follow_ups, after) = match tokens.as_slice() {
+            [intervals] => (*intervals, None, EscalationAction::default()),
+            [intervals, max] => (*intervals, Some(max.parse().ok()?), EscalationAction::default()),
+            [intervals, max, after] => (*intervals, Some(max.parse().ok()?), Self::parse_action(after)?),
+            _ => return None,
+        };
+
+        let intervals = intervals
+            .split(',')
+            .map(|minutes| minutes.trim().parse::<u32>().ok())
+            .collect::<Option<Vec<u32>>>()?;
+        let valid = !intervals.is_empty()
+            && intervals.len() <= MAX_FOLLOW_UP_INTERVALS
+            && intervals.iter().all(|minutes| (1..=MAX_FOLLOW_UP_INTERVAL_MINUTES).contains(minutes));
+        valid.then_some(Self { intervals, max_follow_ups, after })
+    }
+
+    fn parse_action(input: &str) -> Option<EscalationAction> {
+        match input {
+            "missed" | "错过" => Some(EscalationAction::MarkMissed),
+            "caregiver" | "照护人" => Some(EscalationAction::NotifyCaregiver),
+            "stop" | "停止" => Some(EscalationAction::Stop),
+            _ => None,
+        }
+    }
+}
+
+/// 按需服用（PRN）药品的限制
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct PrnPolicy {
+    /// 两次服药之间的最短间隔（小时）
+    pub min_interval_hours: u32,
+    /// 24小时内最多服用次数
+    pub max_daily_doses: u32,
+}
+
+/// 检查按需服药是否允许的结果
+#[derive(Debug, Clone, PartialEq)]
+pub enum PrnCheck {
+    Allowed,
+    /// 距上次服药未达到最短间隔
+    TooSoon { next_allowed: DateTime<Local> },
+    /// 24小时内已达到最多次数
+    DailyLimitReached { next_allowed: DateTime<Local> },
+}
+
+impl PrnPolicy {
+    /// 解析 `prn 4h 6` 格式：至少间隔4小时，每天最多6次
+    pub fn parse(input: &str) -> Option<Self> {
+        let input = input.trim().to_lowercase();
+        match input.split_whitespace().collect::<Vec<_>>().as_slice() {
+            ["prn", interval, max_daily] => {
+                let interval = interval.strip_suffix('h').unwrap_or(interval);
+                Some(Self {
+                    min_interval_hours: interval.parse().ok()?,
+                    max_daily_doses: max_daily.parse().ok().filter(|&n| n > 0)?,
+                })
+            }
+            _ => None,
+        }
+    }
+
+    /// 根据过去的服药时间检查现在能否服药
+    pub fn check(&self, doses: &[DateTime<Local>], now: DateTime<Local>) -> PrnCheck {
+        let window_start = now - Duration::hours(24);
+        let mut recent: Vec<DateTime<Local>> = doses
+            .iter()
+            .copied()
+            .filter(|&dose| dose > window_start && dose <= now)
+            .collect();
+        recent.sort();
+
+        let interval_end = recent
+            .last()
+            .map(|&last| last + Duration::hours(i64::from(self.min_interval_hours)));
+
+        if recent.len() >= self.max_daily_doses as usize {
+            // 等到窗口内足够多的剂量过期
+            let index = recent.len() - self.max_daily_doses as usize;
+            let limit_end = recent[index] + Duration::hours(24);
+            let next_allowed = interval_end.map_or(limit_end, |end| end.max(limit_end));
+            return PrnCheck::DailyLimitReached { next_allowed };
+        }
+
+        match interval_end {
+            Some(next_allowed) if next_allowed > now => PrnCheck::TooSoon { next_allowed },
+            _ => PrnCheck::Allowed,
+        }
+    }
+
+    /// 下一次允许服药的时间（仅考虑间隔和次数限制）
+    pub fn next_allowed(&self, doses: &[DateTime<Local>], now: DateTime<Local>) -> DateTime<Local> {
+        match self.check(doses, now) {
+            PrnCheck::Allowed => now,
+            PrnCheck::TooSoon { next_allowed } | PrnCheck::DailyLimitReached { next_allowed } => next_allowed,
+        }
+    }
+}
+
+/// 解析逗号分隔的提醒时间（HH:MM），至少一个
+pub fn parse_reminder_times(input: &str) -> Option<Vec<NaiveTime>> {
+    let times = input
+        .split(',')
+        .map(|s| NaiveTime::parse_from_str(s.trim(), "%H:%M"))
+        .collect::<Result<Vec<_>, _>>()
+        .ok()?;
+    (!times.is_empty()).then_some(times)
+}
+
+/// `/edit` 可以修改的药品字段
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EditField {
+    Name,
+    Stock,
+    Times,
+    DoseAmount,
+    Notes,
+    Schedule,
+}
+
+impl EditField {
+    pub const ALL: [EditField; 6] = [
+        EditField::Name,
+        EditField::Stock,
+        EditField::Times,
+        EditField::DoseAmount,
+        EditField::Notes,
+        EditField::Schedule,
+    ];
+
+    /// 回调数据中使用的短代码
+    pub fn code(self) -> &'static str {
+        match self {
+            EditField::Name => "n",
+            EditField::Stock => "q",
+            EditField::Times => "t",
+            EditField::DoseAmount => "a",
+            EditField::Notes => "o",
+            EditField::Schedule => "s",
+        }
+    }
+
+    pub fn from_code(code: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|field| field.code() == code)
+    }
+
+    /// 按需服用的药品没有提醒时间和服药计划
+    pub fn applies_to(self, medicine: &Medicine) -> bool {
+        medicine.prn_policy().is_none() || !matches!(self, EditField::Times | EditField::Schedule)
+    }
+}
+
+/// 药品类型：按计划定时服用，或按需服用
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum MedicineKind {
+    #[default]
+    Scheduled,
+    AsNeeded(PrnPolicy),
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Medicine {
     pub id: Uuid,
@@ -11,6 +270,40 @@ pub struct Medicine {
     pub reminder_times: Vec<NaiveTime>,
     pub created_at: DateTime<Local>,
     pub is_active: bool,
+    /// 仅对该药品生效的照护人，优先于用户设置中的照护人
+    #[serde(default)]
+    pub caregiver: Option<Caregiver>,
+    /// 服药计划，旧数据默认为每天
+    #[serde(default)]
+    pub schedule: Schedule,
+    #[serde(default)]
+    pub kind: MedicineKind,
+    /// 按需服药最近24小时内的服药时间
+    #[serde(default)]
+    pub prn_doses: Vec<DateTime<Local>>,
+    #[serde(default)]
+    pub stock_alert: StockAlert,
+    /// 本次库存不足是否已经提醒过，补充药品后重置
+    #[serde(default)]
+    pub low_stock_notified: bool,
+    /// 错过提醒后仍补发提醒的宽限时间（分钟）
+    #[serde(default = "default_grace_minutes")]
+    pub grace_minutes: u32,
+    /// 未确认时的后续提醒策略
+    #[serde(default)]
+    pub escalation: EscalationPolicy,
+    /// 未确认的提醒在计划时间之后多少分钟过期并记为错过，`None` 表示到下一次计划服药为止
+    #[serde(default)]
+    pub expire_after_minutes: Option<u32>,
+    /// 每次服用的默认数量，用于“全部已服药”
+    #[serde(default = "default_dose_amount")]
+    pub dose_amount: u32,
+    /// 用户备注，例如“饭后服用”
+    #[serde(default)]
+    pub notes: Option<String>,
+    /// 暂停中的药品在这一天（用户时区）开始时自动恢复，`None` 表示需要手动恢复
+    #[serde(default)]
+    pub paused_until: Option<NaiveDate>,
 }
 
 impl Medicine {
@@ -22,9 +315,89 @@ impl Medicine {
             reminder_times,
             created_at: Local::now(),
             is_active: true,
+            caregiver: None,
+            schedule: Schedule::default(),
+            kind: MedicineKind::Scheduled,
+            prn_doses: Vec::new(),
+            stock_alert: StockAlert::default(),
+            low_stock_notified: false,
+            grace_minutes: DEFAULT_GRACE_MINUTES,
+            escalation: EscalationPolicy::default(),
+            expire_after_minutes: None,
+            dose_amount: default_dose_amount(),
+            notes: None,
+            paused_until: None,
+        }
+    }
+
+    /// 创建按需服用的药品（没有提醒时间）
+    pub fn new_as_needed(name: String, quantity: u32, policy: PrnPolicy) -> Self {
+        let mut medicine = Self::new(name, quantity, Vec::new());
+        medicine.kind = MedicineKind::AsNeeded(policy);
+        medicine
+    }
+
+    pub fn prn_policy(&self) -> Option<&PrnPolicy> {
+        match &self.kind {
+            MedicineKind::AsNeeded(policy) => Some(policy),
+            MedicineKind::Scheduled => None,
         }
     }
 
+    /// 记录一次按需服药并减少库存，库存不足时返回 `false`
+    pub fn take_prn_dose(&mut self, amount: u32, now: DateTime<Local>) -> bool {
+        if !self.take_dose(amount) {
+            return false;
+        }
+        self.prn_doses.retain(|&dose| dose > now - Duration::hours(24));
+        self.prn_doses.push(now);
+        true
+    }
+
+    /// 计算 `[from, to]` 区间内按服药计划应服药的时间（当地时间）
+    pub fn occurrences_between(&self, from: NaiveDateTime, to: NaiveDateTime) -> Vec<NaiveDateTime> {
+        self.schedule
+            .occurrences_between(&self.reminder_times, self.created_at.date_naive(), from, to)
+    }
+
+    /// 计划在 `scheduled` 的提醒未确认时的过期时间
+    pub fn reminder_expiry(&self, tz: Tz, scheduled: DateTime<Local>) -> DateTime<Local> {
+        if let Some(minutes) = self.expire_after_minutes {
+            return scheduled + Duration::minutes(i64::from(minutes));
+        }
+        self.occurrences_in(tz, scheduled, scheduled + Duration::days(EXPIRY_SEARCH_DAYS))
+            .into_iter()
+            .find(|&next| next > scheduled)
+            .unwrap_or(scheduled + Duration::hours(FALLBACK_EXPIRY_HOURS))
+    }
+
+    /// 按 `tz` 时区的服药计划，计算 `[from, to]` 区间内应服药的时间点，按时间排序。
+    ///
+    /// 夏令时切换时的处理见 [`timezone::resolve`]：重复的时间只服一次，不存在的时间顺延。
+    pub fn occurrences_in(&self, tz: Tz, from: DateTime<Local>, to: DateTime<Local>) -> Vec<DateTime<Local>> {
+        if from > to {
+            return Vec::new();
+        }
+
+        // 多查几个小时，避免夏令时切换附近顺延或提前的时间被漏掉
+        let margin = Duration::hours(DST_MARGIN_HOURS);
+        let mut occurrences: Vec<DateTime<Local>> = self
+            .schedule
+            .occurrences_between(
+                &self.reminder_times,
+                self.created_at.with_timezone(&tz).date_naive(),
+                from.with_timezone(&tz).naive_local() - margin,
+                to.with_timezone(&tz).naive_local() + margin,
+            )
+            .into_iter()
+            .map(|time| timezone::resolve(tz, time))
+            .filter(|&time| time >= from && time <= to)
+            .collect();
+        occurrences.sort();
+        occurrences.dedup();
+        occurrences
+    }
+
     pub fn take_dose(&mut self, amount: u32) -> bool {
         if self.quantity >= amount {
             self.quantity -= amount;
@@ -36,11 +409,58 @@ impl Medicine {
 
     pub fn add_quantity(&mut self, amount: u32) {
         self.quantity += amount;
+        self.low_stock_notified = false;
     }
 
     pub fn set_active(&mut self, active: bool) {
         self.is_active = active;
     }
+
+    /// 暂停提醒，`until` 为自动恢复的日期
+    pub fn pause(&mut self, until: Option<NaiveDate>) {
+        self.is_active = false;
+        self.paused_until = until;
+    }
+
+    pub fn resume(&mut self) {
+        self.is_active = true;
+        self.paused_until = None;
+    }
+
+    /// 按用户输入修改一个字段，输入无效时返回 `false` 且不做修改。
+    ///
+    /// 备注输入 `-` 表示清除。
+    pub fn apply_edit(&mut self, field: EditField, input: &str) -> bool {
+        let input = input.trim();
+        match field {
+            EditField::Name if !input.is_empty() => self.name = input.to_string(),
+            EditField::Stock => match input.parse() {
+                Ok(quantity) => {
+                    self.quantity = quantity;
+                    // 库存重新设置后重新判断是否需要提醒
+                    self.low_stock_notified = false;
+                }
+                Err(_) => return false,
+            },
+            EditField::Times => match parse_reminder_times(input) {
+                Some(times) => self.reminder_times = times,
+                None => return false,
+            },
+            EditField::DoseAmount => match input.parse() {
+                Ok(amount) if amount > 0 => self.dose_amount = amount,
+                _ => return false,
+            },
+            EditField::Notes if !input.is_empty() => {
+                self.notes = (input != "-").then(|| input.to_string());
+            }
+            EditField::Schedule => match Schedule::parse(input) {
+                Some(schedule) => self.schedule = schedule,
+                None => return false,
+            },
+            EditField::Name | EditField::Notes => return false,
+        }
+        true
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -52,30 +472,131 @@ pub struct PendingReminder {
     pub last_reminder_time: DateTime<Local>,
     pub reminder_count: u32,
     pub is_confirmed: bool,
+    /// 是否已通知照护人
+    #[serde(default)]
+    pub escalated: bool,
+    /// 已知晓该提醒的照护人聊天ID
+    #[serde(default)]
+    pub acknowledged_by: Option<i64>,
+    /// 将该提醒标记为已处理的照护人聊天ID
+    #[serde(default)]
+    pub handled_by: Option<i64>,
+    /// 后续提醒已达到最多次数并按策略处理，不再提醒
+    #[serde(default)]
+    pub follow_ups_finished: bool,
+    /// 用户选择延迟时，下一次提醒的准确时间
+    #[serde(default)]
+    pub snoozed_until: Option<DateTime<Local>>,
+    /// 用户选择跳过本次服药，提醒已关闭但没有服药
+    #[serde(default)]
+    pub skipped: bool,
+    /// 提醒未确认而记为错过：超过过期时间或达到后续提醒的最多次数
+    #[serde(default)]
+    pub missed: bool,
+    /// 发送给用户的提醒消息ID，后续提醒和处理结果会原地更新这条消息
+    #[serde(default)]
+    pub message_id: Option<i32>,
+    /// 撤销确认后重新打开提醒的时间，过期时间至少从这时起计算
+    #[serde(default)]
+    pub reopened_at: Option<DateTime<Local>>,
 }
 
 impl PendingReminder {
     pub fn new(medicine_id: Uuid, medicine_name: String, scheduled_time: DateTime<Local>) -> Self {
         Self {
-            id: Uuid::new_v4(),
+            id: Self::slot_id(medicine_id, scheduled_time),
             medicine_id,
             medicine_name,
             scheduled_time,
             last_reminder_time: scheduled_time,
             reminder_count: 1,
             is_confirmed: false,
+            escalated: false,
+            acknowledged_by: None,
+            handled_by: None,
+            follow_ups_finished: false,
+            snoozed_until: None,
+            skipped: false,
+            missed: false,
+            message_id: None,
+            reopened_at: None,
         }
     }
 
+    /// 服药时段（药品 + 计划时间）对应的提醒ID，同一时段只有一个提醒
+    pub fn slot_id(medicine_id: Uuid, scheduled_time: DateTime<Local>) -> Uuid {
+        Uuid::new_v5(&medicine_id, &scheduled_time.timestamp().to_be_bytes())
+    }
+
     pub fn increment_reminder(&mut self) {
         self.reminder_count += 1;
         self.last_reminder_time = Local::now();
+        self.snoozed_until = None;
+    }
+
+    /// 延迟到 `until` 再提醒，不受后续提醒策略的间隔和次数限制
+    pub fn snooze(&mut self, until: DateTime<Local>) {
+        self.snoozed_until = Some(until);
     }
 
     pub fn confirm(&mut self) {
         self.is_confirmed = true;
     }
+
+    /// 撤销确认后重新打开提醒，后续提醒、延迟和照护人通知都从 `now` 重新开始
+    pub fn reopen(&mut self, now: DateTime<Local>) {
+        self.is_confirmed = false;
+        self.last_reminder_time = now;
+        self.reminder_count = 1;
+        self.escalated = false;
+        self.acknowledged_by = None;
+        self.follow_ups_finished = false;
+        self.snoozed_until = None;
+        self.reopened_at = Some(now);
+    }
+
+    /// 记为错过：关闭提醒，不再后续提醒
+    pub fn mark_missed(&mut self) {
+        self.missed = true;
+        self.confirm();
+    }
+
+    /// 跳过本次服药：关闭提醒，不再后续提醒
+    pub fn skip(&mut self) {
+        self.skipped = true;
+        self.confirm();
+    }
+
+    /// 后续提醒的次数（不含首次提醒）
+    pub fn follow_up_count(&self) -> u32 {
+        self.reminder_count.saturating_sub(1)
+    }
+
+    /// 下一次后续提醒的时间：延迟时为延迟到的时间，已确认或已停止提醒时为 `None`
+    pub fn next_follow_up(&self, policy: &EscalationPolicy) -> Option<DateTime<Local>> {
+        if self.is_confirmed {
+            return None;
+        }
+        if let Some(until) = self.snoozed_until {
+            return Some(until);
+        }
+        if self.follow_ups_finished {
+            return None;
+        }
+        Some(self.last_reminder_time + policy.interval(self.reminder_count))
+    }
+
+    /// 后续提醒是否已达到策略的最多次数
+    pub fn follow_ups_exhausted(&self, policy: &EscalationPolicy) -> bool {
+        policy.max_follow_ups.is_some_and(|max| self.follow_up_count() >= max)
+    }
+
+    /// 是否应该通知照护人
+    pub fn should_escalate(&self, caregiver: &Caregiver) -> bool {
+        !self.is_confirmed && !self.escalated && self.follow_up_count() >= caregiver.escalate_after
+    }
 }
 
 pub type MedicineStore = HashMap<Uuid, Medicine>;
+/// 按服药时段索引的提醒，键为 [`PendingReminder::slot_id`]
 pub type PendingReminders = HashMap<Uuid, PendingReminder>;
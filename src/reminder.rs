@@ -1,56 +1,227 @@
-use crate::{localization, AppData, PendingReminder, Storage};
-use chrono::{Duration, Local, NaiveTime};
+use crate::access::{AccessConfig, InviteError};
+use crate::{
+    localization,
+    scheduler::{self, DueKind, DueQueue},
+    stats, storage::Language, AppData, Caregiver, DoseEvent, DoseStatus, EscalationAction, EscalationPolicy,
+    PendingReminder, PrnCheck, SkipReason,
+    StorageBackend, UserData, CLOSED_REMINDER_RETENTION_DAYS,
+};
+use chrono::{DateTime, Duration, Local, NaiveDate};
+use std::collections::{BTreeMap, HashMap};
 use std::sync::Arc;
 use std::time::Duration as StdDuration;
-use teloxide::{prelude::*, types::ChatId};
+use crate::CallbackData;
+use teloxide::{prelude::*, types::{ChatId, MessageId}, ApiError, RequestError};
 use tokio::sync::Mutex;
-use tokio::time::{interval, timeout};
+use tokio::sync::Notify;
+use tokio::time::timeout;
 use uuid::Uuid;
 
+/// 按需服药的结果
+#[derive(Debug, Clone, PartialEq)]
+pub enum PrnTakeResult {
+    /// 已记录服药，附带回复内容
+    Taken(String),
+    /// 超出间隔或次数限制而拒绝，附带原因；可以选择仍然服用
+    Refused(String),
+}
+
 pub struct ReminderService {
-    storage: Storage,
+    storage: Box<dyn StorageBackend>,
     data: Arc<Mutex<AppData>>,
     bot: Bot,
-    chat_id: ChatId,
+    access_config: AccessConfig,
+    // 每个被拒绝用户最近一次报告给所有者的时间，避免刷屏
+    rejection_reports: Mutex<HashMap<u64, DateTime<Local>>>,
+    // 数据变化时唤醒提醒循环重新计划
+    replan: Notify,
 }
 
 impl ReminderService {
-    pub fn new(storage: Storage, bot: Bot, chat_id: ChatId) -> Self {
-        let data = Arc::new(Mutex::new(storage.load().unwrap_or_default()));
+    /// `legacy_chat_id` 用于接收旧版单用户数据文件中的药品和设置
+    pub fn new(storage: Box<dyn StorageBackend>, bot: Bot, legacy_chat_id: Option<ChatId>, access_config: AccessConfig) -> Self {
+        let mut app_data = storage.load().unwrap_or_default();
+
+        if !access_config.is_enabled() {
+            log::warn!("未设置 OWNER_ID，访问控制未启用，任何人都可以使用机器人");
+        }
+
+        if app_data.has_legacy_data() {
+            match legacy_chat_id {
+                Some(chat_id) => {
+                    app_data.claim_legacy_data(chat_id.0);
+                    log::info!("已将旧版数据迁移到聊天 {}", chat_id);
+                    if let Err(e) = storage.save(&app_data) {
+                        log::error!("Failed to save data: {}", e);
+                    }
+                }
+                None => {
+                    log::warn!("检测到旧版单用户数据，但未设置 CHAT_ID，暂不迁移");
+                }
+            }
+        }
+
+        // 旧版本的提醒按药品记录，改为按服药时段记录
+        let migrated = app_data.migrate_reminder_slots();
+        if migrated > 0 {
+            log::info!("已将 {} 个提醒迁移为按服药时段记录", migrated);
+            if let Err(e) = storage.save(&app_data) {
+                log::error!("Failed to save data: {}", e);
+            }
+        }
+
+        let data = Arc::new(Mutex::new(app_data));
         Self {
             storage,
             data,
             bot,
-            chat_id,
+            access_config,
+            rejection_reports: Mutex::new(HashMap::new()),
+            replan: Notify::new(),
+        }
+    }
+
+    pub fn access_config(&self) -> &AccessConfig {
+        &self.access_config
+    }
+
+    pub async fn is_authorized(&self, user_id: UserId) -> bool {
+        let data = self.data.lock().await;
+        data.access.is_allowed(&self.access_config, user_id.0)
+    }
+
+    /// 将用户加入白名单（用于从环境变量预置用户）
+    pub async fn allow_users(&self, user_ids: &[u64]) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        self.update_data(|data| data.access.allowed_users.extend(user_ids.iter().copied()))
+            .await
+    }
+
+    pub async fn create_invite(&self, created_by: UserId) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+        let mut code = String::new();
+        self.update_data(|data| code = data.access.create_invite(created_by.0, Local::now()))
+            .await?;
+        Ok(code)
+    }
+
+    pub async fn redeem_invite(&self, code: &str, user_id: UserId) -> Result<(), InviteError> {
+        let mut data = self.data.lock().await;
+        data.access.redeem_invite(code, user_id.0, Local::now())?;
+        if let Err(e) = self.storage.save(&data) {
+            log::error!("Failed to save data: {}", e);
+        }
+        Ok(())
+    }
+
+    pub async fn revoke_user(&self, user_id: u64) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+        let mut removed = false;
+        self.update_data(|data| removed = data.access.revoke(user_id)).await?;
+        Ok(removed)
+    }
+
+    /// 记录被拒绝的访问，并按配置报告给所有者（每个用户每小时最多一次）
+    pub async fn report_rejection(&self, user: &teloxide::types::User, action: &str) {
+        log::warn!(
+            "拒绝未授权的访问：用户 {} ({}) 尝试 {}",
+            user.id,
+            user.full_name(),
+            action
+        );
+
+        let Some(owner_id) = self.access_config.owner_id else {
+            return;
+        };
+        if !self.access_config.report_rejections {
+            return;
         }
+
+        let now = Local::now();
+        {
+            let mut reports = self.rejection_reports.lock().await;
+            if let Some(last) = reports.get(&user.id.0) {
+                if now.signed_duration_since(*last) < Duration::hours(1) {
+                    return;
+                }
+            }
+            reports.insert(user.id.0, now);
+        }
+
+        let username = user
+            .username
+            .as_ref()
+            .map(|name| format!(" @{}", name))
+            .unwrap_or_default();
+        let message = format!(
+            "🚫 未授权的访问 / Unauthorized access
+
+👤 {}{} ({})
+📝 {}",
+            user.full_name(),
+            username,
+            user.id,
+            action
+        );
+        self.send_message_with_retry(ChatId(owner_id as i64), message, None, 1).await;
     }
 
     pub async fn start_reminder_loop(&self) -> anyhow::Result<()> {
-        let mut interval = interval(tokio::time::Duration::from_secs(60)); // 每分钟检查一次
         let mut error_count = 0;
         const MAX_ERRORS: u32 = 10;
+        // 启动时执行所有检查，补上停机期间到期的服药
+        let mut due = DueKind::ALL.to_vec();
 
         loop {
-            interval.tick().await;
+            if due.contains(&DueKind::Travel) {
+                if let Err(e) = timeout(
+                    StdDuration::from_secs(30),
+                    self.update_travel_plans()
+                ).await {
+                    log::error!("更新旅行计划超时: {:?}", e);
+                    error_count += 1;
+                }
+            }
+
+            if due.contains(&DueKind::Resume) {
+                if let Err(e) = timeout(
+                    StdDuration::from_secs(30),
+                    self.resume_paused_medicines()
+                ).await {
+                    log::error!("恢复暂停的药品超时: {:?}", e);
+                    error_count += 1;
+                }
+            }
 
             // 检查和发送提醒，带超时和错误处理
-            if let Err(e) = timeout(
-                StdDuration::from_secs(30),
-                self.check_and_send_reminders()
-            ).await {
-                log::error!("检查提醒超时: {:?}", e);
-                error_count += 1;
-            } else {
-                error_count = 0; // 重置错误计数
+            if due.contains(&DueKind::Dose) {
+                if let Err(e) = timeout(
+                    StdDuration::from_secs(30),
+                    self.check_and_send_reminders()
+                ).await {
+                    log::error!("检查提醒超时: {:?}", e);
+                    error_count += 1;
+                } else {
+                    error_count = 0; // 重置错误计数
+                }
             }
 
             // 检查待确认提醒，带超时和错误处理
-            if let Err(e) = timeout(
-                StdDuration::from_secs(30),
-                self.check_pending_reminders()
-            ).await {
-                log::error!("检查待确认提醒超时: {:?}", e);
-                error_count += 1;
+            if due.contains(&DueKind::FollowUp) {
+                if let Err(e) = timeout(
+                    StdDuration::from_secs(30),
+                    self.check_pending_reminders()
+                ).await {
+                    log::error!("检查待确认提醒超时: {:?}", e);
+                    error_count += 1;
+                }
+            }
+
+            if due.contains(&DueKind::WeeklyReport) {
+                if let Err(e) = timeout(
+                    StdDuration::from_secs(30),
+                    self.send_weekly_reports()
+                ).await {
+                    log::error!("发送每周报告超时: {:?}", e);
+                    error_count += 1;
+                }
             }
 
             // 如果连续错误太多，暂停一段时间
@@ -59,134 +230,488 @@ impl ReminderService {
                 tokio::time::sleep(StdDuration::from_secs(300)).await;
                 error_count = 0;
             }
+
+            due = self.wait_until_due().await;
+        }
+    }
+
+    /// 计划下一次到期的事件并睡眠到那时，返回需要执行的检查；
+    /// 数据通过 `update_data` 变化时重新计划
+    async fn wait_until_due(&self) -> Vec<DueKind> {
+        let max_sleep = Duration::minutes(scheduler::MAX_SLEEP_MINUTES);
+
+        loop {
+            let now = Local::now();
+            let mut queue = DueQueue::plan(&*self.data.lock().await, now);
+            let due = queue.pop_due(now);
+            if !due.is_empty() {
+                return due;
+            }
+
+            let next = queue.peek().map(|item| item.at).filter(|&at| at - now < max_sleep);
+            let sleep = next.map_or(max_sleep, |at| at - now);
+            log::debug!("已计划 {} 个事件，{} 秒后检查", queue.len(), sleep.num_seconds());
+
+            tokio::select! {
+                _ = tokio::time::sleep(sleep.to_std().unwrap_or_default()) => {
+                    // 长时间没有事件时全面检查一次
+                    if next.is_none() {
+                        return DueKind::ALL.to_vec();
+                    }
+                }
+                _ = self.replan.notified() => {}
+            }
         }
     }
 
     async fn check_and_send_reminders(&self) {
         let now = Local::now();
-        let current_time = now.time();
-        let mut new_reminders = Vec::new();
+        let mut plans = Vec::new();
+        let mut expirations = Vec::new();
 
-        // 首先收集需要创建的提醒
+        // 首先找出上次处理之后到期的服药，停机或循环停顿期间到期的也会补上
         {
-            let data = self.data.lock().await;
-            for medicine in data.medicines.values() {
-                if !medicine.is_active || medicine.quantity == 0 {
-                    continue;
+            let mut data = self.data.lock().await;
+            let (from, to) = scheduler::due_window(data.last_processed, now);
+            log::debug!("检查 {} 至 {} 之间到期的服药", from.format("%m-%d %H:%M"), to.format("%m-%d %H:%M"));
+
+            for (&chat_id, user) in data.users.iter_mut() {
+                // 先让过期的提醒记为错过，不再阻止新的提醒
+                let expired = user.expire_reminders(now);
+                if !expired.is_empty() {
+                    expirations.push((ChatId(chat_id), expired));
                 }
 
-                for &reminder_time in &medicine.reminder_times {
-                    // 检查是否到了提醒时间（允许1分钟的误差）
-                    if self.is_time_to_remind(current_time, reminder_time) {
-                        // 检查是否已经有待确认的提醒
-                        let has_pending = data
-                            .pending_reminders
-                            .values()
-                            .any(|r| r.medicine_id == medicine.id && !r.is_confirmed);
-
-                        if !has_pending {
-                            let scheduled_time = now
-                                .date_naive()
-                                .and_time(reminder_time)
-                                .and_local_timezone(Local)
-                                .unwrap();
-
-                            let reminder = PendingReminder::new(
-                                medicine.id,
-                                medicine.name.clone(),
-                                scheduled_time,
-                            );
-
-                            new_reminders.push(reminder);
-                        }
-                    }
+                let plan = scheduler::plan_due_doses(user, from, to);
+                if plan.is_empty() {
+                    continue;
+                }
+                for event in &plan.missed {
+                    user.record_dose_event(event.clone());
                 }
+                plans.push((ChatId(chat_id), plan));
+            }
+
+            data.last_processed = Some(now);
+            if let Err(e) = self.storage.save(&data) {
+                log::error!("Failed to save data: {}", e);
+            }
+        }
+
+        self.send_expired_reminders(expirations).await;
+
+        for (chat_id, plan) in plans {
+            for (medicine_id, medicine_name) in &plan.out_of_stock {
+                self.send_out_of_stock_warning(chat_id, *medicine_id, medicine_name).await;
+            }
+            if !plan.missed.is_empty() {
+                log::warn!("聊天 {} 有 {} 次服药超过宽限时间，记为错过", chat_id, plan.missed.len());
+                let (language, tz) = {
+                    let data = self.data.lock().await;
+                    (data.language_for(chat_id.0), data.timezone_for(chat_id.0))
+                };
+                let message = localization::format_missed_doses(
+                    &language,
+                    tz,
+                    localization::get_text(&language).missed_doses_title,
+                    &plan.missed,
+                );
+                self.send_message_with_retry(chat_id, message, None, 3).await;
             }
+            self.send_new_reminders(chat_id, plan.reminders).await;
         }
+    }
+
+    // 通知用户哪些提醒已过期并记为错过
+    async fn send_expired_reminders(&self, expirations: Vec<(ChatId, Vec<DoseEvent>)>) {
+        for (chat_id, expired) in expirations {
+            log::info!("聊天 {} 有 {} 个提醒过期未确认，记为错过", chat_id, expired.len());
+            let (language, tz) = {
+                let data = self.data.lock().await;
+                (data.language_for(chat_id.0), data.timezone_for(chat_id.0))
+            };
+            let title = localization::get_text(&language).expired_reminders_title;
+            let message = localization::format_missed_doses(&language, tz, title, &expired);
+            self.send_message_with_retry(chat_id, message, None, 3).await;
+            for reminder_id in expired.iter().filter_map(|event| event.reminder_id) {
+                self.refresh_reminder(chat_id, reminder_id).await;
+            }
+        }
+    }
+
+    // 发送提醒并保存，同一时间的提醒合并为一条消息
+    async fn send_new_reminders(&self, chat_id: ChatId, reminders: Vec<PendingReminder>) {
+        let mut slots: BTreeMap<DateTime<Local>, Vec<PendingReminder>> = BTreeMap::new();
+        for reminder in reminders {
+            slots.entry(reminder.scheduled_time).or_default().push(reminder);
+        }
+
+        for (_, mut group) in slots {
+            let message_id = if let [reminder] = group.as_slice() {
+                self.send_reminder_message(chat_id, reminder).await
+            } else {
+                group.sort_by(|a, b| a.medicine_name.cmp(&b.medicine_name));
+                self.send_grouped_reminder_message(chat_id, &group).await
+            };
 
-        // 然后发送提醒并保存
-        for reminder in new_reminders {
-            self.send_reminder_message(&reminder).await;
+            // 记录消息ID，之后原地更新这条消息
             let mut data = self.data.lock().await;
-            data.pending_reminders.insert(reminder.id, reminder);
+            let user = data.user_mut(chat_id.0);
+            for mut reminder in group {
+                reminder.message_id = message_id.map(|id| id.0);
+                user.pending_reminders.insert(reminder.id, reminder);
+            }
             if let Err(e) = self.storage.save(&data) {
                 log::error!("Failed to save data: {}", e);
             }
         }
     }
 
+    async fn update_travel_plans(&self) {
+        let now = Local::now();
+        let mut messages = Vec::new();
+
+        {
+            let mut data = self.data.lock().await;
+            for (&chat_id, user) in data.users.iter_mut() {
+                if let Some(event) = user.update_travel(now) {
+                    log::info!("聊天 {} 的旅行计划：{:?}", chat_id, event);
+                    messages.push((ChatId(chat_id), localization::format_travel_event(&user.user_settings.language, event, user, now)));
+                }
+            }
+
+            if !messages.is_empty() {
+                if let Err(e) = self.storage.save(&data) {
+                    log::error!("Failed to save data: {}", e);
+                }
+            }
+        }
+
+        for (chat_id, message) in messages {
+            self.send_message_with_retry(chat_id, message, None, 3).await;
+        }
+    }
+
+    // 恢复已到恢复日期的暂停药品并通知用户
+    async fn resume_paused_medicines(&self) {
+        let now = Local::now();
+        let mut messages = Vec::new();
+
+        {
+            let mut data = self.data.lock().await;
+            for (&chat_id, user) in data.users.iter_mut() {
+                let resumed = user.resume_due_medicines(now);
+                if !resumed.is_empty() {
+                    log::info!("聊天 {} 恢复了 {} 个暂停的药品", chat_id, resumed.len());
+                    let text = localization::get_text(&user.user_settings.language);
+                    messages.push((ChatId(chat_id), format!("{}\n💊 {}", text.medicines_resumed, resumed.join(", "))));
+                }
+            }
+
+            if !messages.is_empty() {
+                if let Err(e) = self.storage.save(&data) {
+                    log::error!("Failed to save data: {}", e);
+                }
+            }
+        }
+
+        for (chat_id, message) in messages {
+            self.send_message_with_retry(chat_id, message, None, 3).await;
+        }
+    }
+
+    async fn send_weekly_reports(&self) {
+        let now = Local::now();
+        let mut reports = Vec::new();
+
+        {
+            let mut data = self.data.lock().await;
+            let mut changed = false;
+            for (&chat_id, user) in data.users.iter_mut() {
+                let tz = user.timezone();
+                let settings = &user.user_settings;
+                if !settings.weekly_report || !stats::weekly_report_due(now, tz, settings.last_weekly_report) {
+                    continue;
+                }
+
+                let today = now.with_timezone(&tz).date_naive();
+                let (from, to) = stats::weekly_report_period(today, tz);
+                let report = user.adherence_report(from, to, now);
+                user.user_settings.last_weekly_report = Some(today);
+                changed = true;
+
+                // 上周没有任何计划服药时不打扰用户
+                if !report.is_empty() {
+                    let language = &user.user_settings.language;
+                    let title = localization::get_text(language).weekly_report_title;
+                    reports.push((ChatId(chat_id), localization::format_adherence_report(language, title, &report)));
+                }
+            }
+
+            if changed {
+                if let Err(e) = self.storage.save(&data) {
+                    log::error!("Failed to save data: {}", e);
+                }
+            }
+        }
+
+        for (chat_id, message) in reports {
+            self.send_message_with_retry(chat_id, message, None, 3).await;
+        }
+    }
+
     async fn check_pending_reminders(&self) {
-        let mut data = self.data.lock().await;
         let now = Local::now();
         let mut to_remind = Vec::new();
+        let mut escalations = Vec::new();
+        let mut finished = Vec::new();
+        let mut expirations = Vec::new();
+        let default_policy = EscalationPolicy::default();
+
+        {
+            let mut data = self.data.lock().await;
+            let prune_before = now - Duration::days(CLOSED_REMINDER_RETENTION_DAYS);
+            for (&chat_id, user) in data.users.iter_mut() {
+                user.prune_closed_reminders(prune_before);
+                let expired = user.expire_reminders(now);
+                if !expired.is_empty() {
+                    expirations.push((ChatId(chat_id), expired));
+                }
+                let language = user.user_settings.language.clone();
+                let UserData {
+                    medicines,
+                    pending_reminders,
+                    user_settings,
+                    dose_history,
+                    ..
+                } = user;
 
-        for reminder in data.pending_reminders.values_mut() {
-            if !reminder.is_confirmed {
-                let time_since_last = now.signed_duration_since(reminder.last_reminder_time);
+                for reminder in pending_reminders.values_mut() {
+                    if !reminder.is_confirmed {
+                        let medicine = medicines.get(&reminder.medicine_id);
+                        let policy = medicine.map_or(&default_policy, |medicine| &medicine.escalation);
+                        let caregiver = medicine
+                            .and_then(|medicine| medicine.caregiver.as_ref())
+                            .or(user_settings.caregiver.as_ref());
 
-                // 根据提醒次数调整间隔：第1次后5分钟，第2次后10分钟，之后每15分钟
-                let interval_minutes = match reminder.reminder_count {
-                    1 => 5,
-                    2 => 10,
-                    _ => 15,
-                };
+                        if reminder.next_follow_up(policy).is_some_and(|next| next <= now) {
+                            // 用户主动延迟的提醒照常发送
+                            if reminder.snoozed_until.is_none() && reminder.follow_ups_exhausted(policy) {
+                                // 达到最多次数后按策略处理，不再提醒
+                                reminder.follow_ups_finished = true;
+                                match policy.after {
+                                    EscalationAction::MarkMissed => {
+                                        reminder.mark_missed();
+                                        dose_history.push(
+                                            DoseEvent::new(
+                                                reminder.medicine_id,
+                                                reminder.medicine_name.clone(),
+                                                DoseStatus::Missed,
+                                                Some(reminder.scheduled_time),
+                                                0,
+                                            )
+                                            .with_reminder(reminder.id),
+                                        );
+                                    }
+                                    EscalationAction::NotifyCaregiver => match caregiver {
+                                        Some(caregiver) if !reminder.escalated => {
+                                            reminder.escalated = true;
+                                            escalations.push((caregiver.clone(), ChatId(chat_id), reminder.clone()));
+                                        }
+                                        Some(_) => {}
+                                        None => log::warn!("提醒 {} 需要通知照护人，但没有设置照护人", reminder.id),
+                                    },
+                                    EscalationAction::Stop => {}
+                                }
+                                finished.push((ChatId(chat_id), language.clone(), reminder.clone(), policy.after));
+                            } else {
+                                reminder.increment_reminder();
+                                to_remind.push((ChatId(chat_id), language.clone(), reminder.clone()));
+                            }
+                        }
 
-                if time_since_last >= Duration::minutes(interval_minutes) {
-                    reminder.increment_reminder();
-                    to_remind.push(reminder.clone());
+                        // 未确认的后续提醒达到设定次数时通知照护人
+                        if let Some(caregiver) = caregiver {
+                            if reminder.should_escalate(caregiver) {
+                                reminder.escalated = true;
+                                escalations.push((caregiver.clone(), ChatId(chat_id), reminder.clone()));
+                            }
+                        }
+                    }
                 }
             }
+
+            if let Err(e) = self.storage.save(&data) {
+                log::error!("Failed to save data: {}", e);
+            }
         }
 
-        for reminder in to_remind {
-            self.send_follow_up_reminder(&reminder).await;
+        // 发送时不持有数据锁
+        self.send_expired_reminders(expirations).await;
+
+        for (chat_id, language, reminder) in to_remind {
+            self.send_follow_up_reminder(chat_id, &language, &reminder).await;
         }
 
-        if let Err(e) = self.storage.save(&data) {
-            log::error!("Failed to save data: {}", e);
+        for (caregiver, patient_chat_id, reminder) in escalations {
+            self.send_escalation(&caregiver, patient_chat_id, &reminder).await;
+        }
+
+        for (chat_id, language, reminder, action) in finished {
+            self.refresh_reminder(chat_id, reminder.id).await;
+            let text = localization::get_text(&language);
+            let notice = match action {
+                EscalationAction::MarkMissed => text.follow_ups_marked_missed,
+                EscalationAction::NotifyCaregiver => text.follow_ups_caregiver_notified,
+                EscalationAction::Stop => text.follow_ups_stopped,
+            };
+            self.send_message_with_retry(chat_id, format!("{}\n💊 {}", notice, reminder.medicine_name), None, 3)
+                .await;
         }
     }
 
-    fn is_time_to_remind(&self, current_time: NaiveTime, reminder_time: NaiveTime) -> bool {
-        let diff = if current_time >= reminder_time {
-            current_time - reminder_time
-        } else {
-            reminder_time - current_time
+    async fn send_reminder_message(&self, chat_id: ChatId, reminder: &PendingReminder) -> Option<MessageId> {
+        let (language, tz) = {
+            let data = self.data.lock().await;
+            (data.language_for(chat_id.0), data.timezone_for(chat_id.0))
         };
+        let text = localization::get_text(&language);
 
-        diff <= chrono::Duration::minutes(1)
+        let mut message = localization::format_reminder_message(
+            &language,
+            &reminder.medicine_name,
+            &reminder.scheduled_time.with_timezone(&tz).format("%H:%M").to_string()
+        );
+        // 停机后补发的提醒
+        if Local::now() - reminder.scheduled_time > Duration::minutes(2) {
+            message = format!("{}\n\n{}", text.late_reminder_notice, message);
+        }
+
+        let keyboard = Self::reminder_keyboard(&language, reminder.id);
+
+        // 带超时和重试的发送消息
+        self.send_message_with_retry(chat_id, message, Some(keyboard), 3).await
     }
 
-    async fn send_reminder_message(&self, reminder: &PendingReminder) {
-        let data = self.data.lock().await;
-        let language = &data.user_settings.language;
-        let text = localization::get_text(language);
+    // 同一时间的多个提醒：列出每种药品和数量，每种药品一行按钮，最后是“全部已服药”
+    async fn send_grouped_reminder_message(&self, chat_id: ChatId, reminders: &[PendingReminder]) -> Option<MessageId> {
+        let (language, tz, doses) = {
+            let data = self.data.lock().await;
+            let user = data.user(chat_id.0);
+            let doses: Vec<(String, u32)> = reminders
+                .iter()
+                .map(|reminder| {
+                    let amount = user
+                        .and_then(|user| user.medicines.get(&reminder.medicine_id))
+                        .map_or(1, |medicine| medicine.dose_amount);
+                    (reminder.medicine_name.clone(), amount)
+                })
+                .collect();
+            (data.language_for(chat_id.0), data.timezone_for(chat_id.0), doses)
+        };
+        let text = localization::get_text(&language);
+        let scheduled_time = reminders[0].scheduled_time;
 
-        let message = localization::format_reminder_message(
-            language,
-            &reminder.medicine_name,
-            &reminder.scheduled_time.format("%H:%M").to_string()
+        let mut message = localization::format_grouped_reminder_message(
+            &language,
+            &doses,
+            &scheduled_time.with_timezone(&tz).format("%H:%M").to_string(),
         );
+        if Local::now() - scheduled_time > Duration::minutes(2) {
+            message = format!("{}\n\n{}", text.late_reminder_notice, message);
+        }
 
-        let keyboard = teloxide::types::InlineKeyboardMarkup::new(vec![vec![
-            teloxide::types::InlineKeyboardButton::callback(
-                text.taken_button,
-                format!("confirm_{}", reminder.id),
-            ),
-            teloxide::types::InlineKeyboardButton::callback(
-                text.snooze_button,
-                format!("snooze_{}", reminder.id),
-            ),
-        ]]);
+        let open: Vec<&PendingReminder> = reminders.iter().collect();
+        let keyboard = Self::reminder_view_keyboard(&language, &open);
+        self.send_message_with_retry(chat_id, message, keyboard, 3).await
+    }
 
-        // 带超时和重试的发送消息
-        self.send_message_with_retry(message, Some(keyboard), 3).await;
+    async fn send_out_of_stock_warning(&self, chat_id: ChatId, medicine_id: Uuid, medicine_name: &str) {
+        let language = self.data.lock().await.language_for(chat_id.0);
+        let text = localization::get_text(&language);
+        log::warn!("药品 {} 已用完，跳过提醒", medicine_id);
+
+        self.send_message_with_retry(
+            chat_id,
+            format!("{}\n💊 {}", text.out_of_stock_reminder, medicine_name),
+            Some(Self::refill_keyboard(&language, medicine_id)),
+            3,
+        )
+        .await;
     }
 
-    async fn send_follow_up_reminder(&self, reminder: &PendingReminder) {
-        let data = self.data.lock().await;
-        let language = &data.user_settings.language;
+    // 服药后库存首次低于提醒条件时发送提醒
+    async fn send_stock_alert(&self, chat_id: ChatId, medicine_id: Uuid, message: String) {
+        let language = self.data.lock().await.language_for(chat_id.0);
+        self.send_message_with_retry(chat_id, message, Some(Self::refill_keyboard(&language, medicine_id)), 3)
+            .await;
+    }
+
+    // 库存不足时检查并生成提醒内容，需要在持有数据锁时调用
+    fn low_stock_message(user: &mut UserData, medicine_id: Uuid, language: &Language) -> Option<String> {
+        let (level, forecast) = user.take_low_stock_alert(medicine_id, Local::now())?;
+        let medicine = user.medicines.get(&medicine_id)?;
+        Some(localization::format_stock_alert(language, &medicine.name, medicine.quantity, level, &forecast))
+    }
+
+    fn reminder_keyboard(language: &Language, reminder_id: Uuid) -> teloxide::types::InlineKeyboardMarkup {
         let text = localization::get_text(language);
+        teloxide::types::InlineKeyboardMarkup::new(vec![vec![
+            teloxide::types::InlineKeyboardButton::callback(text.taken_button, CallbackData::Confirm(reminder_id).encode()),
+            teloxide::types::InlineKeyboardButton::callback(text.snooze_button, CallbackData::Snooze(reminder_id).encode()),
+            teloxide::types::InlineKeyboardButton::callback(text.skip_button, CallbackData::Skip(reminder_id).encode()),
+        ]])
+    }
+
+    /// 提醒消息中未处理提醒的按钮：只有一个时使用完整按钮，多个时每种药品一行，最后是“全部已服药”
+    fn reminder_view_keyboard(
+        language: &Language,
+        open: &[&PendingReminder],
+    ) -> Option<teloxide::types::InlineKeyboardMarkup> {
+        let text = localization::get_text(language);
+        match open {
+            [] => None,
+            [reminder] => Some(Self::reminder_keyboard(language, reminder.id)),
+            _ => {
+                let mut keyboard: Vec<Vec<teloxide::types::InlineKeyboardButton>> = open
+                    .iter()
+                    .map(|reminder| {
+                        vec![
+                            teloxide::types::InlineKeyboardButton::callback(
+                                format!("✅ {}", reminder.medicine_name),
+                                CallbackData::Confirm(reminder.id).encode(),
+                            ),
+                            teloxide::types::InlineKeyboardButton::callback("⏰", CallbackData::Snooze(reminder.id).encode()),
+                            teloxide::types::InlineKeyboardButton::callback("⏭️", CallbackData::Skip(reminder.id).encode()),
+                        ]
+                    })
+                    .collect();
+                keyboard.push(vec![teloxide::types::InlineKeyboardButton::callback(
+                    text.take_all_button,
+                    CallbackData::TakeAll(open[0].scheduled_time.timestamp()).encode(),
+                )]);
+                Some(teloxide::types::InlineKeyboardMarkup::new(keyboard))
+            }
+        }
+    }
+
+    fn refill_keyboard(language: &Language, medicine_id: Uuid) -> teloxide::types::InlineKeyboardMarkup {
+        teloxide::types::InlineKeyboardMarkup::new(vec![vec![
+            teloxide::types::InlineKeyboardButton::callback(
+                localization::get_text(language).refill_button,
+                CallbackData::Refill(medicine_id).encode(),
+            ),
+        ]])
+    }
+
+    async fn send_follow_up_reminder(&self, chat_id: ChatId, language: &Language, reminder: &PendingReminder) {
+        // 优先更新原来的提醒消息，消息已无法编辑时再发送新消息
+        if let Some(message_id) = reminder.message_id {
+            if self.refresh_reminder_message(chat_id, message_id).await {
+                return;
+            }
+        }
+        let tz = self.data.lock().await.timezone_for(chat_id.0);
 
         let message = format!(
             "🔔 {}！\n\n💊 {}：{}\n⏰ {}：{}\n📊 {}：{}\n\n{}：",
@@ -194,37 +719,79 @@ impl ReminderService {
             if matches!(language, crate::storage::Language::Chinese) { "药品" } else { "Medicine" },
             reminder.medicine_name,
             if matches!(language, crate::storage::Language::Chinese) { "原定时间" } else { "Scheduled time" },
-            reminder.scheduled_time.format("%H:%M"),
+            reminder.scheduled_time.with_timezone(&tz).format("%H:%M"),
             if matches!(language, crate::storage::Language::Chinese) { "提醒次数" } else { "Reminder count" },
             reminder.reminder_count,
             if matches!(language, crate::storage::Language::Chinese) { "请确认是否已服药" } else { "Please confirm if you have taken the medicine" }
         );
 
+        let keyboard = Self::reminder_keyboard(language, reminder.id);
+
+        // 带超时和重试的发送消息
+        let message_id = self.send_message_with_retry(chat_id, message, Some(keyboard), 3).await;
+        if let Some(message_id) = message_id {
+            let mut data = self.data.lock().await;
+            if let Some(reminder) = data.user_mut(chat_id.0).pending_reminders.get_mut(&reminder.id) {
+                reminder.message_id = Some(message_id.0);
+            }
+            if let Err(e) = self.storage.save(&data) {
+                log::error!("Failed to save data: {}", e);
+            }
+        }
+    }
+
+    async fn send_escalation(&self, caregiver: &Caregiver, patient_chat_id: ChatId, reminder: &PendingReminder) {
+        let (language, tz) = {
+            let data = self.data.lock().await;
+            // 按患者的时区显示原定时间
+            (data.language_for(caregiver.chat_id), data.timezone_for(patient_chat_id.0))
+        };
+        let text = localization::get_text(&language);
+
+        log::info!(
+            "提醒 {} 已有 {} 次后续提醒未确认，通知照护人 {}",
+            reminder.id,
+            reminder.follow_up_count(),
+            caregiver.chat_id
+        );
+
+        let patient = caregiver
+            .patient_name
+            .clone()
+            .unwrap_or_else(|| patient_chat_id.to_string());
+        let message = localization::format_escalation_message(
+            &language,
+            &patient,
+            &reminder.medicine_name,
+            &reminder.scheduled_time.with_timezone(&tz).format("%H:%M").to_string(),
+            reminder.reminder_count,
+        );
+
         let keyboard = teloxide::types::InlineKeyboardMarkup::new(vec![vec![
             teloxide::types::InlineKeyboardButton::callback(
-                text.taken_button,
-                format!("confirm_{}", reminder.id),
+                text.acknowledge_button,
+                CallbackData::CaregiverAcknowledge { patient_chat_id: patient_chat_id.0, reminder_id: reminder.id }.encode(),
             ),
             teloxide::types::InlineKeyboardButton::callback(
-                text.snooze_button,
-                format!("snooze_{}", reminder.id),
+                text.mark_handled_button,
+                CallbackData::CaregiverHandled { patient_chat_id: patient_chat_id.0, reminder_id: reminder.id }.encode(),
             ),
         ]]);
 
-        // 带超时和重试的发送消息
-        self.send_message_with_retry(message, Some(keyboard), 3).await;
+        self.send_message_with_retry(ChatId(caregiver.chat_id), message, Some(keyboard), 3).await;
     }
 
     // 新增：带重试机制的消息发送方法
     async fn send_message_with_retry(
         &self,
+        chat_id: ChatId,
         message: String,
         keyboard: Option<teloxide::types::InlineKeyboardMarkup>,
         max_retries: u32,
-    ) {
+    ) -> Option<MessageId> {
         for attempt in 1..=max_retries {
             let send_future = async {
-                let mut request = self.bot.send_message(self.chat_id, &message);
+                let mut request = self.bot.send_message(chat_id, &message);
                 if let Some(ref kb) = keyboard {
                     request = request.reply_markup(kb.clone());
                 }
@@ -232,9 +799,9 @@ impl ReminderService {
             };
 
             match timeout(StdDuration::from_secs(10), send_future).await {
-                Ok(Ok(_)) => {
+                Ok(Ok(sent)) => {
                     log::debug!("消息发送成功");
-                    return;
+                    return Some(sent.id);
                 }
                 Ok(Err(e)) => {
                     log::warn!("发送消息失败 (尝试 {}/{}): {}", attempt, max_retries, e);
@@ -252,59 +819,127 @@ impl ReminderService {
         }
 
         log::error!("发送消息最终失败，已重试 {} 次", max_retries);
+        None
     }
 
-    pub async fn confirm_medicine(&self, reminder_id: Uuid) -> Result<String, String> {
-        let mut data = self.data.lock().await;
+    /// 按提醒的最新状态更新提醒消息，已处理的提醒不再显示按钮。返回是否更新成功
+    async fn refresh_reminder_message(&self, chat_id: ChatId, message_id: i32) -> bool {
+        let (message, keyboard) = {
+            let data = self.data.lock().await;
+            let Some(user) = data.user(chat_id.0) else {
+                return false;
+            };
+            let language = &user.user_settings.language;
+            let tz = user.timezone();
 
-        if let Some(reminder) = data.pending_reminders.get_mut(&reminder_id) {
-            reminder.confirm();
-            let medicine_id = reminder.medicine_id;
-            let medicine_name = reminder.medicine_name.clone();
-
-            // 减少药品数量（默认1个）
-            if let Some(medicine) = data.medicines.get_mut(&medicine_id) {
-                if medicine.take_dose(1) {
-                    let response = format!(
-                        "✅ 已确认服药：{}\n💊 服用数量：1\n📦 剩余数量：{}",
-                        medicine_name,
-                        medicine.quantity
-                    );
-
-                    if let Err(e) = self.storage.save(&data) {
-                        log::error!("Failed to save data: {}", e);
-                    }
+            let mut reminders: Vec<&PendingReminder> = user
+                .pending_reminders
+                .values()
+                .filter(|r| r.message_id == Some(message_id))
+                .collect();
+            if reminders.is_empty() {
+                return false;
+            }
+            reminders.sort_by(|a, b| a.medicine_name.cmp(&b.medicine_name));
 
-                    Ok(response)
-                } else {
-                    Err(format!("药品数量不足，当前剩余：{}", medicine.quantity))
-                }
-            } else {
-                Err("药品信息未找到".to_string())
+            let entries: Vec<(&PendingReminder, u32, Option<String>)> = reminders
+                .iter()
+                .map(|&reminder| {
+                    let amount = user.medicines.get(&reminder.medicine_id).map_or(1, |medicine| medicine.dose_amount);
+                    let outcome = user
+                        .dose_history
+                        .iter()
+                        .rev()
+                        .find(|event| event.reminder_id == Some(reminder.id) && event.status != DoseStatus::Snoozed);
+                    (reminder, amount, localization::format_reminder_status(language, tz, reminder, outcome))
+                })
+                .collect();
+            let open: Vec<&PendingReminder> = reminders.iter().copied().filter(|r| !r.is_confirmed).collect();
+            (
+                localization::format_reminder_view(language, tz, &entries),
+                Self::reminder_view_keyboard(language, &open),
+            )
+        };
+
+        // 不带按钮编辑时 Telegram 会移除原有的按钮
+        let mut request = self.bot.edit_message_text(chat_id, MessageId(message_id), message);
+        if let Some(keyboard) = keyboard {
+            request = request.reply_markup(keyboard);
+        }
+        match timeout(StdDuration::from_secs(10), request.send()).await {
+            Ok(Ok(_)) | Ok(Err(RequestError::Api(ApiError::MessageNotModified))) => true,
+            Ok(Err(e)) => {
+                log::warn!("更新提醒消息失败: {}", e);
+                false
+            }
+            Err(_) => {
+                log::warn!("更新提醒消息超时");
+                false
             }
-        } else {
-            Err("提醒信息未找到".to_string())
         }
     }
 
-    pub async fn snooze_reminder(&self, reminder_id: Uuid) -> Result<String, String> {
+    // 更新提醒所在的消息
+    async fn refresh_reminder(&self, chat_id: ChatId, reminder_id: Uuid) {
+        let message_id = self
+            .data
+            .lock()
+            .await
+            .user(chat_id.0)
+            .and_then(|user| user.pending_reminders.get(&reminder_id))
+            .and_then(|reminder| reminder.message_id);
+        if let Some(message_id) = message_id {
+            self.refresh_reminder_message(chat_id, message_id).await;
+        }
+    }
+
+    pub async fn confirm_medicine(&self, chat_id: ChatId, reminder_id: Uuid) -> Result<String, String> {
+        self.confirm_medicine_with_amount(chat_id, reminder_id, 1).await
+    }
+
+    /// 延迟提醒，到 `until` 时再次提醒
+    pub async fn snooze_reminder(
+        &self,
+        chat_id: ChatId,
+        reminder_id: Uuid,
+        until: DateTime<Local>,
+    ) -> Result<String, String> {
         let mut data = self.data.lock().await;
-        let language = data.user_settings.language.clone();
+        let language = data.language_for(chat_id.0);
+        let text = localization::get_text(&language);
+        let tz = data.timezone_for(chat_id.0);
 
-        if let Some(reminder) = data.pending_reminders.get_mut(&reminder_id) {
-            // 重置最后提醒时间，延迟5分钟后再次提醒
-            reminder.last_reminder_time = Local::now();
+        let user = data.user_mut(chat_id.0);
+        if let Some(reminder) = user.pending_reminders.get_mut(&reminder_id) {
+            if reminder.is_confirmed {
+                return Err(text.reminder_already_closed.to_string());
+            }
+            reminder.snooze(until);
+            let event = DoseEvent::new(
+                reminder.medicine_id,
+                reminder.medicine_name.clone(),
+                DoseStatus::Snoozed,
+                Some(reminder.scheduled_time),
+                0,
+            )
+            .with_reminder(reminder_id);
+            user.record_dose_event(event);
 
             if let Err(e) = self.storage.save(&data) {
                 log::error!("Failed to save data: {}", e);
             }
+            // 延迟时间可能早于原定的下一次提醒
+            self.replan.notify_one();
+            drop(data);
+            self.refresh_reminder(chat_id, reminder_id).await;
 
-            let response = if matches!(language, crate::storage::Language::Chinese) {
-                "⏰ 已延迟提醒，5分钟后将再次提醒"
+            // 跨天时显示日期
+            let format = if until.with_timezone(&tz).date_naive() == Local::now().with_timezone(&tz).date_naive() {
+                "%H:%M"
             } else {
-                "⏰ Reminder snoozed, will remind again in 5 minutes"
+                "%Y-%m-%d %H:%M"
             };
-            Ok(response.to_string())
+            Ok(format!("{}{}", text.reminder_snoozed, until.with_timezone(&tz).format(format)))
         } else {
             let error_msg = if matches!(language, crate::storage::Language::Chinese) {
                 "提醒信息未找到"
@@ -315,72 +950,463 @@ impl ReminderService {
         }
     }
 
+    /// 按默认数量确认计划在同一时间（`scheduled_timestamp`）的所有未确认提醒，返回每种药品的结果
+    pub async fn confirm_all(&self, chat_id: ChatId, scheduled_timestamp: i64) -> Result<String, String> {
+        let (text, doses) = {
+            let data = self.data.lock().await;
+            let text = localization::get_text(&data.language_for(chat_id.0));
+            let Some(user) = data.user(chat_id.0) else {
+                return Err(text.reminder_not_found.to_string());
+            };
+            let mut doses: Vec<(Uuid, String, u32)> = user
+                .pending_reminders
+                .values()
+                .filter(|r| !r.is_confirmed && r.scheduled_time.timestamp() == scheduled_timestamp)
+                .map(|r| {
+                    let amount = user.medicines.get(&r.medicine_id).map_or(1, |medicine| medicine.dose_amount);
+                    (r.id, r.medicine_name.clone(), amount)
+                })
+                .collect();
+            doses.sort_by(|a, b| a.1.cmp(&b.1));
+            (text, doses)
+        };
+        if doses.is_empty() {
+            return Err(text.reminder_already_closed.to_string());
+        }
+
+        let mut results = Vec::new();
+        for (reminder_id, medicine_name, amount) in doses {
+            match self.confirm_medicine_with_amount(chat_id, reminder_id, amount).await {
+                Ok(response) => results.push(response),
+                Err(error) => results.push(format!("❌ {}: {}", medicine_name, error)),
+            }
+        }
+        Ok(results.join("\n\n"))
+    }
+
+    /// 跳过本次服药：关闭提醒并记录为跳过，不改变药品库存
+    pub async fn skip_reminder(
+        &self,
+        chat_id: ChatId,
+        reminder_id: Uuid,
+        reason: Option<SkipReason>,
+    ) -> Result<String, String> {
+        let mut data = self.data.lock().await;
+        let language = data.language_for(chat_id.0);
+        let text = localization::get_text(&language);
+
+        let user = data.user_mut(chat_id.0);
+        let Some(reminder) = user.pending_reminders.get_mut(&reminder_id) else {
+            return Err(text.reminder_not_found.to_string());
+        };
+        if reminder.is_confirmed {
+            return Err(text.reminder_already_closed.to_string());
+        }
+        reminder.skip();
+        let event = DoseEvent::new(
+            reminder.medicine_id,
+            reminder.medicine_name.clone(),
+            DoseStatus::Skipped,
+            Some(reminder.scheduled_time),
+            0,
+        )
+        .with_reminder(reminder_id)
+        .with_skip_reason(reason);
+        let mut response = format!("{}\n💊 {}", text.dose_skipped, reminder.medicine_name);
+        if let Some(reason) = reason {
+            response.push_str(&format!("\n📝 {}", localization::format_skip_reason(&language, reason)));
+        }
+        user.record_dose_event(event);
+
+        if let Err(e) = self.storage.save(&data) {
+            log::error!("Failed to save data: {}", e);
+        }
+        drop(data);
+        self.refresh_reminder(chat_id, reminder_id).await;
+        Ok(response)
+    }
+
+    /// 照护人知晓未确认的提醒，并通知患者
+    pub async fn caregiver_acknowledge(
+        &self,
+        caregiver_chat_id: ChatId,
+        patient_chat_id: ChatId,
+        reminder_id: Uuid,
+    ) -> Result<String, String> {
+        self.update_escalated_reminder(caregiver_chat_id, patient_chat_id, reminder_id, |reminder| {
+            reminder.acknowledged_by = Some(caregiver_chat_id.0);
+        })
+        .await?;
+
+        let patient_language = self.data.lock().await.language_for(patient_chat_id.0);
+        self.send_message_with_retry(
+            patient_chat_id,
+            localization::get_text(&patient_language).caregiver_acknowledged.to_string(),
+            None,
+            3,
+        )
+        .await;
+
+        let language = self.data.lock().await.language_for(caregiver_chat_id.0);
+        Ok(localization::get_text(&language).escalation_acknowledged.to_string())
+    }
+
+    /// 照护人将提醒标记为已处理：提醒不再重复，但不改变药品库存
+    pub async fn caregiver_mark_handled(
+        &self,
+        caregiver_chat_id: ChatId,
+        patient_chat_id: ChatId,
+        reminder_id: Uuid,
+    ) -> Result<String, String> {
+        let medicine_name = self
+            .update_escalated_reminder(caregiver_chat_id, patient_chat_id, reminder_id, |reminder| {
+                reminder.handled_by = Some(caregiver_chat_id.0);
+                reminder.confirm();
+            })
+            .await?;
+        self.refresh_reminder(patient_chat_id, reminder_id).await;
+
+        let patient_language = self.data.lock().await.language_for(patient_chat_id.0);
+        self.send_message_with_retry(
+            patient_chat_id,
+            format!(
+                "{}\n💊 {}",
+                localization::get_text(&patient_language).caregiver_handled,
+                medicine_name
+            ),
+            None,
+            3,
+        )
+        .await;
+
+        let language = self.data.lock().await.language_for(caregiver_chat_id.0);
+        Ok(localization::get_text(&language).escalation_handled.to_string())
+    }
+
+    // 校验照护人身份后修改已通知照护人的提醒，返回药品名称
+    async fn update_escalated_reminder<F>(
+        &self,
+        caregiver_chat_id: ChatId,
+        patient_chat_id: ChatId,
+        reminder_id: Uuid,
+        updater: F,
+    ) -> Result<String, String>
+    where
+        F: FnOnce(&mut PendingReminder),
+    {
+        let mut data = self.data.lock().await;
+        let text = localization::get_text(&data.language_for(caregiver_chat_id.0));
+
+        let Some(user) = data.users.get_mut(&patient_chat_id.0) else {
+            return Err(text.reminder_not_found.to_string());
+        };
+        let Some(reminder) = user.pending_reminders.get(&reminder_id) else {
+            return Err(text.reminder_not_found.to_string());
+        };
+
+        let is_caregiver = user
+            .medicines
+            .get(&reminder.medicine_id)
+            .and_then(|medicine| user.caregiver_for(medicine))
+            .or(user.user_settings.caregiver.as_ref())
+            .is_some_and(|caregiver| caregiver.chat_id == caregiver_chat_id.0);
+        if !is_caregiver {
+            return Err(text.not_caregiver.to_string());
+        }
+
+        let reminder = user.pending_reminders.get_mut(&reminder_id).unwrap();
+        if reminder.is_confirmed {
+            return Err(text.reminder_already_closed.to_string());
+        }
+        updater(reminder);
+        let medicine_name = reminder.medicine_name.clone();
+
+        if let Err(e) = self.storage.save(&data) {
+            log::error!("Failed to save data: {}", e);
+        }
+        Ok(medicine_name)
+    }
+
+    /// 记录一次按需服药。`force` 为 `true` 时即使超出限制也记录，并附带警告
+    pub async fn take_as_needed(&self, chat_id: ChatId, medicine_id: Uuid, force: bool) -> Result<PrnTakeResult, String> {
+        let mut data = self.data.lock().await;
+        let language = data.language_for(chat_id.0);
+        let text = localization::get_text(&language);
+        let is_chinese = matches!(language, Language::Chinese);
+        let tz = data.timezone_for(chat_id.0);
+        let now = Local::now();
+
+        let Some(medicine) = data.user_mut(chat_id.0).medicines.get_mut(&medicine_id) else {
+            return Err(text.medicine_not_found.to_string());
+        };
+        let Some(policy) = medicine.prn_policy().cloned() else {
+            return Err(text.medicine_not_found.to_string());
+        };
+
+        let check = policy.check(&medicine.prn_doses, now);
+        let warning = match check {
+            PrnCheck::Allowed => None,
+            PrnCheck::TooSoon { next_allowed } => Some(format!(
+                "{}\n⏰ {}：{}",
+                if is_chinese {
+                    format!("距上次服药不足{}小时", policy.min_interval_hours)
+                } else {
+                    format!("Less than {} hours since the last dose", policy.min_interval_hours)
+                },
+                text.next_dose_allowed,
+                next_allowed.with_timezone(&tz).format("%m-%d %H:%M")
+            )),
+            PrnCheck::DailyLimitReached { next_allowed } => Some(format!(
+                "{}\n⏰ {}：{}",
+                if is_chinese {
+                    format!("24小时内已服用{}次，已达上限", policy.max_daily_doses)
+                } else {
+                    format!("Already taken {} times in 24 hours, limit reached", policy.max_daily_doses)
+                },
+                text.next_dose_allowed,
+                next_allowed.with_timezone(&tz).format("%m-%d %H:%M")
+            )),
+        };
+
+        if let (Some(warning), false) = (&warning, force) {
+            return Ok(PrnTakeResult::Refused(format!("❌ {}", warning)));
+        }
+
+        if !medicine.take_prn_dose(1, now) {
+            return Err(if is_chinese {
+                format!("药品数量不足，当前剩余：{}", medicine.quantity)
+            } else {
+                format!("Insufficient quantity, remaining: {}", medicine.quantity)
+            });
+        }
+        let event = DoseEvent::new(medicine_id, medicine.name.clone(), DoseStatus::Taken, None, 1);
+
+        let next_allowed = policy.next_allowed(&medicine.prn_doses, now);
+        let mut response = format!(
+            "{}：{}\n📦 {}：{}\n⏰ {}：{}",
+            text.prn_dose_recorded,
+            medicine.name,
+            if is_chinese { "剩余数量" } else { "Remaining" },
+            medicine.quantity,
+            text.next_dose_allowed,
+            next_allowed.with_timezone(&tz).format("%m-%d %H:%M")
+        );
+        if let Some(warning) = warning {
+            log::warn!("按需药品 {} 超出限制仍然服用", medicine_id);
+            response = format!("⚠️ {}\n\n{}", warning, response);
+        }
+        let user = data.user_mut(chat_id.0);
+        user.record_dose_event(event);
+        let stock_alert = Self::low_stock_message(user, medicine_id, &language);
+
+        if let Err(e) = self.storage.save(&data) {
+            log::error!("Failed to save data: {}", e);
+        }
+        drop(data);
+
+        if let Some(message) = stock_alert {
+            self.send_stock_alert(chat_id, medicine_id, message).await;
+        }
+
+        Ok(PrnTakeResult::Taken(response))
+    }
+
     pub async fn get_data(&self) -> AppData {
         let data = self.data.lock().await;
         data.clone()
     }
 
+    /// 获取指定聊天的数据，不存在时返回默认值
+    pub async fn get_user_data(&self, chat_id: ChatId) -> UserData {
+        let data = self.data.lock().await;
+        data.user(chat_id.0).cloned().unwrap_or_default()
+    }
+
     pub async fn update_data<F>(&self, updater: F) -> Result<(), Box<dyn std::error::Error + Send + Sync>>
     where
         F: FnOnce(&mut AppData),
     {
         let mut data = self.data.lock().await;
         updater(&mut data);
+        self.replan.notify_one();
+        self.storage.save(&data)?;
+        Ok(())
+    }
+
+    pub async fn update_user_data<F>(&self, chat_id: ChatId, updater: F) -> Result<(), Box<dyn std::error::Error + Send + Sync>>
+    where
+        F: FnOnce(&mut UserData),
+    {
+        self.update_data(|data| updater(data.user_mut(chat_id.0))).await
+    }
+
+    pub async fn get_dialogue_state(&self, chat_id: ChatId) -> Option<serde_json::Value> {
+        let data = self.data.lock().await;
+        data.user(chat_id.0).and_then(|user| user.dialogue_state.clone())
+    }
+
+    /// 保存对话状态，状态未变化时不写入存储
+    pub async fn set_dialogue_state(
+        &self,
+        chat_id: ChatId,
+        state: Option<serde_json::Value>,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let mut data = self.data.lock().await;
+        let current = data.user(chat_id.0).and_then(|user| user.dialogue_state.as_ref());
+        if current == state.as_ref() {
+            return Ok(());
+        }
+
+        data.user_mut(chat_id.0).dialogue_state = state;
         self.storage.save(&data)?;
         Ok(())
     }
 
-    pub async fn confirm_medicine_with_amount(&self, reminder_id: Uuid, amount: u32) -> Result<String, String> {
+    /// 确认服药并扣减库存。同一提醒只能确认一次，重复确认时返回已确认的时间而不再扣减库存
+    pub async fn confirm_medicine_with_amount(&self, chat_id: ChatId, reminder_id: Uuid, amount: u32) -> Result<String, String> {
         let mut data = self.data.lock().await;
-        let language = data.user_settings.language.clone();
+        let language = data.language_for(chat_id.0);
         let text = localization::get_text(&language);
+        let is_chinese = matches!(language, crate::storage::Language::Chinese);
+        let user = data.user_mut(chat_id.0);
 
-        if let Some(reminder) = data.pending_reminders.get_mut(&reminder_id) {
-            reminder.confirm();
-            let medicine_id = reminder.medicine_id;
-            let medicine_name = reminder.medicine_name.clone();
-
-            // 减少药品数量
-            if let Some(medicine) = data.medicines.get_mut(&medicine_id) {
-                if medicine.take_dose(amount) {
-                    let response = format!(
-                        "{}: {}\n💊 {}: {}\n📦 {}: {}",
-                        text.dose_confirmed.trim_end_matches("✅ "),
-                        medicine_name,
-                        if matches!(language, crate::storage::Language::Chinese) { "服用数量" } else { "Dose amount" },
-                        amount,
-                        if matches!(language, crate::storage::Language::Chinese) { "剩余数量" } else { "Remaining" },
-                        medicine.quantity
-                    );
-
-                    if let Err(e) = self.storage.save(&data) {
-                        log::error!("Failed to save data: {}", e);
-                    }
+        let Some(reminder) = user.pending_reminders.get(&reminder_id) else {
+            return Err(text.reminder_not_found.to_string());
+        };
+        if reminder.is_confirmed {
+            return Err(localization::format_already_confirmed(
+                &language,
+                user.timezone(),
+                user.taken_event(reminder_id),
+            ));
+        }
+        let medicine_id = reminder.medicine_id;
+        let medicine_name = reminder.medicine_name.clone();
+        let event = DoseEvent::new(
+            medicine_id,
+            medicine_name.clone(),
+            DoseStatus::Taken,
+            Some(reminder.scheduled_time),
+            amount,
+        )
+        .with_reminder(reminder_id);
 
-                    Ok(response)
-                } else {
-                    let error_msg = if matches!(language, crate::storage::Language::Chinese) {
-                        format!("药品数量不足，当前剩余：{}", medicine.quantity)
-                    } else {
-                        format!("Insufficient quantity, remaining: {}", medicine.quantity)
-                    };
-                    Err(error_msg)
-                }
-            } else {
-                let error_msg = if matches!(language, crate::storage::Language::Chinese) {
-                    "药品信息未找到"
-                } else {
-                    "Medicine information not found"
-                };
-                Err(error_msg.to_string())
-            }
-        } else {
-            let error_msg = if matches!(language, crate::storage::Language::Chinese) {
-                "提醒信息未找到"
+        // 减少药品数量，库存不足时提醒保持未确认
+        let Some(medicine) = user.medicines.get_mut(&medicine_id) else {
+            let error_msg = if is_chinese { "药品信息未找到" } else { "Medicine information not found" };
+            return Err(error_msg.to_string());
+        };
+        if !medicine.take_dose(amount) {
+            let error_msg = if is_chinese {
+                format!("药品数量不足，当前剩余：{}", medicine.quantity)
             } else {
-                "Reminder information not found"
+                format!("Insufficient quantity, remaining: {}", medicine.quantity)
             };
-            Err(error_msg.to_string())
+            return Err(error_msg);
+        }
+        let response = format!(
+            "{}: {}\n💊 {}: {}\n📦 {}: {}",
+            text.dose_confirmed.trim_end_matches("✅ "),
+            medicine_name,
+            if is_chinese { "服用数量" } else { "Dose amount" },
+            amount,
+            if is_chinese { "剩余数量" } else { "Remaining" },
+            medicine.quantity
+        );
+        if let Some(reminder) = user.pending_reminders.get_mut(&reminder_id) {
+            reminder.confirm();
+        }
+        user.record_dose_event(event);
+        let stock_alert = Self::low_stock_message(user, medicine_id, &language);
+
+        if let Err(e) = self.storage.save(&data) {
+            log::error!("Failed to save data: {}", e);
         }
+        drop(data);
+
+        self.refresh_reminder(chat_id, reminder_id).await;
+        if let Some(message) = stock_alert {
+            self.send_stock_alert(chat_id, medicine_id, message).await;
+        }
+        Ok(response)
+    }
+
+    /// 暂停药品的提醒，`until` 为自动恢复的日期；该药品未处理的提醒记为跳过
+    pub async fn pause_medicine(
+        &self,
+        chat_id: ChatId,
+        medicine_id: Uuid,
+        until: Option<NaiveDate>,
+    ) -> Result<String, String> {
+        let mut data = self.data.lock().await;
+        let language = data.language_for(chat_id.0);
+        let text = localization::get_text(&language);
+        let user = data.user_mut(chat_id.0);
+
+        let Some(closed) = user.pause_medicine(medicine_id, until) else {
+            return Err(text.medicine_not_found.to_string());
+        };
+        let name = user.medicines.get(&medicine_id).map(|medicine| medicine.name.clone()).unwrap_or_default();
+
+        if let Err(e) = self.storage.save(&data) {
+            log::error!("Failed to save data: {}", e);
+        }
+        // 需要计划自动恢复的时间
+        self.replan.notify_one();
+        drop(data);
+
+        for reminder_id in closed {
+            self.refresh_reminder(chat_id, reminder_id).await;
+        }
+        Ok(format!("💊 {}\n{}", name, localization::format_pause_status(&language, until)))
+    }
+
+    pub async fn resume_medicine(&self, chat_id: ChatId, medicine_id: Uuid) -> Result<String, String> {
+        let mut data = self.data.lock().await;
+        let text = localization::get_text(&data.language_for(chat_id.0));
+
+        let Some(medicine) = data.user_mut(chat_id.0).medicines.get_mut(&medicine_id) else {
+            return Err(text.medicine_not_found.to_string());
+        };
+        if medicine.is_active {
+            return Err(text.medicine_not_paused.to_string());
+        }
+        medicine.resume();
+        let response = format!("{}\n💊 {}", text.medicines_resumed, medicine.name);
+
+        if let Err(e) = self.storage.save(&data) {
+            log::error!("Failed to save data: {}", e);
+        }
+        self.replan.notify_one();
+        Ok(response)
+    }
+
+    /// 撤销最近确认的服药：恢复库存并重新打开提醒
+    pub async fn undo_confirmation(&self, chat_id: ChatId, reminder_id: Uuid) -> Result<String, String> {
+        let mut data = self.data.lock().await;
+        let language = data.language_for(chat_id.0);
+        let user = data.user_mut(chat_id.0);
+
+        let Some(event) = user.undo_taken(reminder_id, Local::now()) else {
+            return Err(localization::format_undo_unavailable(&language));
+        };
+        let remaining = user.medicines.get(&event.medicine_id).map_or(0, |medicine| medicine.quantity);
+
+        if let Err(e) = self.storage.save(&data) {
+            log::error!("Failed to save data: {}", e);
+        }
+        // 重新打开的提醒需要重新计划后续提醒
+        self.replan.notify_one();
+        drop(data);
+        self.refresh_reminder(chat_id, reminder_id).await;
+
+        let remaining_label = if matches!(language, crate::storage::Language::Chinese) { "剩余数量" } else { "Remaining" };
+        Ok(format!(
+            "{}\n💊 {}\n📦 {}: {}",
+            localization::get_text(&language).dose_undone,
+            event.medicine_name,
+            remaining_label,
+            remaining
+        ))
     }
 }
@@ -0,0 +1,203 @@
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime, NaiveTime, Weekday};
+use serde::{Deserialize, Serialize};
+
+/// 每隔 N 小时的计划允许的最大间隔（一年）
+pub const MAX_INTERVAL_HOURS: u32 = 24 * 365;
+/// 每隔 N 天和周期疗程中每段允许的最大天数（十年）
+pub const MAX_INTERVAL_DAYS: u32 = 3650;
+
+/// 服药的重复规则
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Recurrence {
+    /// 每天
+    #[default]
+    Daily,
+    /// 每周的指定几天
+    Weekdays { days: Vec<Weekday> },
+    /// 每隔 N 天（从开始日期算起）
+    EveryNDays { days: u32 },
+    /// 每隔 N 小时（从开始日期的第一个提醒时间算起）
+    EveryNHours { hours: u32 },
+    /// 周期性疗程，例如服用21天、停药7天
+    Cycle { days_on: u32, days_off: u32 },
+}
+
+/// 药品的服药计划：重复规则加上可选的疗程起止日期
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+pub struct Schedule {
+    #[serde(default)]
+    pub recurrence: Recurrence,
+    /// 疗程开始日期，未设置时使用药品的创建日期
+    #[serde(default)]
+    pub start_date: Option<NaiveDate>,
+    /// 疗程结束日期（包含当天）
+    #[serde(default)]
+    pub end_date: Option<NaiveDate>,
+}
+
+impl Schedule {
+    /// 指定日期是否在疗程范围内并且是服药日
+    pub fn is_active_on(&self, date: NaiveDate, anchor: NaiveDate) -> bool {
+        let start = self.start_date.unwrap_or(anchor);
+        if date < start || self.end_date.is_some_and(|end| date > end) {
+            return false;
+        }
+
+        let days_since_start = (date - start).num_days();
+        match &self.recurrence {
+            Recurrence::Daily | Recurrence::EveryNHours { .. } => true,
+            Recurrence::Weekdays { days } => days.contains(&date.weekday()),
+            Recurrence::EveryNDays { days } => days_since_start % i64::from((*days).max(1)) == 0,
+            Recurrence::Cycle { days_on, days_off } => {
+                let cycle_length = (i64::from(*days_on) + i64::from(*days_off)).max(1);
+                days_since_start % cycle_length < i64::from(*days_on)
+            }
+        }
+    }
+
+    /// 计算 `[from, to]` 区间内的所有服药时间，按时间排序
+    ///
+    /// `times` 为每天的提醒时间；对于每隔 N 小时的计划，只使用最早的时间作为起点。
+    /// `anchor` 为未设置开始日期时使用的日期。
+    pub fn occurrences_between(
+        &self,
+        times: &[NaiveTime],
+        anchor: NaiveDate,
+        from: NaiveDateTime,
+        to: NaiveDateTime,
+    ) -> Vec<NaiveDateTime> {
+        if from > to || times.is_empty() {
+            return Vec::new();
+        }
+
+        let mut occurrences = Vec::new();
+
+        if let Recurrence::EveryNHours { hours } = self.recurrence {
+            let start = self.start_date.unwrap_or(anchor);
+            let first_time = times.iter().min().copied().unwrap_or(NaiveTime::MIN);
+            let first = start.and_time(first_time);
+            let step = Duration::hours(i64::from(hours.max(1)));
+
+            // 跳过区间开始之前的次数
+            let skipped = if from > first {
+                let elapsed = (from - first).num_seconds();
+                (elapsed + step.num_seconds() - 1) / step.num_seconds()
+            } else {
+                0
+            };
+
+            // 超出日期范围时停止，避免溢出
+            let mut current = first.checked_add_signed(Duration::seconds(skipped * step.num_seconds()));
+            while let Some(time) = current.filter(|&time| time <= to) {
+                if self.end_date.is_some_and(|end| time.date() > end) {
+                    break;
+                }
+                occurrences.push(time);
+                current = time.checked_add_signed(step);
+            }
+            return occurrences;
+        }
+
+        let mut sorted_times = times.to_vec();
+        sorted_times.sort();
+
+        let mut date = from.date();
+        while date <= to.date() {
+            if self.is_active_on(date, anchor) {
+                for &time in &sorted_times {
+                    let occurrence = date.and_time(time);
+                    if occurrence >= from && occurrence <= to {
+                        occurrences.push(occurrence);
+                    }
+                }
+            }
+            date = match date.succ_opt() {
+                Some(next) => next,
+                None => break,
+            };
+        }
+
+        occurrences
+    }
+
+    /// 解析用户输入的服药计划，例如：
+    ///
+    /// - `daily`
+    /// - `mon,wed,fri`
+    /// - `every 2d`
+    /// - `every 8h`
+    /// - `cycle 21/7`
+    ///
+    /// 后面可以加上 `from YYYY-MM-DD` 和/或 `to YYYY-MM-DD` 指定疗程起止日期。
+    pub fn parse(input: &str) -> Option<Self> {
+        let input = input.trim().to_lowercase();
+        let parse_date = |s: Option<&str>| NaiveDate::parse_from_str(s?, "%Y-%m-%d").ok();
+
+        let mut tokens = Vec::new();
+        let mut start_date = None;
+        let mut end_date = None;
+        let mut iter = input.split_whitespace();
+        while let Some(token) = iter.next() {
+            match token {
+                "from" if start_date.is_none() => start_date = Some(parse_date(iter.next())?),
+                "to" if end_date.is_none() => end_date = Some(parse_date(iter.next())?),
+                // 日期必须写在重复规则之后
+                _ if start_date.is_none() && end_date.is_none() => tokens.push(token),
+                _ => return None,
+            }
+        }
+
+        if let (Some(start), Some(end)) = (start_date, end_date) {
+            if end < start {
+                return None;
+            }
+        }
+
+        let recurrence = match tokens.as_slice() {
+            [] | ["daily"] => Recurrence::Daily,
+            ["every", interval] => {
+                if let Some(days) = interval.strip_suffix('d') {
+                    Recurrence::EveryNDays {
+                        days: days.parse().ok().filter(|&n| n > 0 && n <= MAX_INTERVAL_DAYS)?,
+                    }
+                } else if let Some(hours) = interval.strip_suffix('h') {
+                    Recurrence::EveryNHours {
+                        hours: hours.parse().ok().filter(|&n| n > 0 && n <= MAX_INTERVAL_HOURS)?,
+                    }
+                } else {
+                    return None;
+                }
+            }
+            ["cycle", pattern] => {
+                let (on, off) = pattern.split_once('/')?;
+                Recurrence::Cycle {
+                    days_on: on.parse().ok().filter(|&n| n > 0 && n <= MAX_INTERVAL_DAYS)?,
+                    days_off: off.parse().ok().filter(|&n| n <= MAX_INTERVAL_DAYS)?,
+                }
+            }
+            days => {
+                // 允许 "mon,wed" 和 "mon, wed" 两种写法
+                let days = days.join(",");
+                let mut weekdays = Vec::new();
+                for day in days.split(',').map(|s| s.trim()).filter(|s| !s.is_empty()) {
+                    let weekday = day.parse::<Weekday>().ok()?;
+                    if !weekdays.contains(&weekday) {
+                        weekdays.push(weekday);
+                    }
+                }
+                if weekdays.is_empty() {
+                    return None;
+                }
+                weekdays.sort_by_key(|d| d.num_days_from_monday());
+                Recurrence::Weekdays { days: weekdays }
+            }
+        };
+
+        Some(Self {
+            recurrence,
+            start_date,
+            end_date,
+        })
+    }
+}
@@ -0,0 +1,223 @@
+use crate::{stats, timezone, AppData, DoseEvent, DoseStatus, EscalationPolicy, PendingReminder, UserData};
+use chrono::{DateTime, Duration, Local};
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use uuid::Uuid;
+
+/// 停机后最多补查多少天内到期的服药
+pub const MAX_CATCH_UP_DAYS: i64 = 7;
+
+/// 计划下一次服药时向后查找的天数，更远的服药由定期的全面检查处理
+const LOOKAHEAD_DAYS: i64 = 8;
+
+/// 即使没有到期的事件，也至少每隔这么久全面检查一次，防止系统时间调整后错过提醒
+pub const MAX_SLEEP_MINUTES: i64 = 60;
+
+/// 到期事件的类型，对应提醒循环中的一项检查
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum DueKind {
+    /// 旅行模式每天调整提醒时间，需要在当天的提醒之前进行
+    Travel,
+    /// 暂停的药品到期自动恢复，同样需要在当天的提醒之前进行
+    Resume,
+    /// 按服药计划的首次提醒
+    Dose,
+    /// 未确认提醒的后续提醒和过期
+    FollowUp,
+    /// 每周报告
+    WeeklyReport,
+}
+
+impl DueKind {
+    pub const ALL: [DueKind; 5] = [
+        DueKind::Travel,
+        DueKind::Resume,
+        DueKind::Dose,
+        DueKind::FollowUp,
+        DueKind::WeeklyReport,
+    ];
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct DueItem {
+    pub at: DateTime<Local>,
+    pub kind: DueKind,
+}
+
+/// 按时间排序的到期事件队列，提醒循环睡眠到队首事件到期
+#[derive(Debug, Default)]
+pub struct DueQueue {
+    heap: BinaryHeap<Reverse<DueItem>>,
+}
+
+impl DueQueue {
+    /// 根据当前数据计划 `now` 之后（含已经到期）的所有事件
+    pub fn plan(data: &AppData, now: DateTime<Local>) -> Self {
+        let mut queue = Self::default();
+        let lookahead = now + Duration::days(LOOKAHEAD_DAYS);
+        let default_policy = EscalationPolicy::default();
+        // 上次处理之后到期但还没处理的服药也要计划，它们已经到期
+        let (processed_until, _) = due_window(data.last_processed, now);
+
+        for user in data.users.values() {
+            let tz = user.timezone();
+            for medicine in user.medicines.values() {
+                if let (false, Some(until)) = (medicine.is_active, medicine.paused_until) {
+                    queue.push(DueItem {
+                        at: timezone::start_of_day(tz, until),
+                        kind: DueKind::Resume,
+                    });
+                }
+                if !medicine.is_active || medicine.prn_policy().is_some() {
+                    continue;
+                }
+                let next_dose = medicine
+                    .occurrences_in(tz, processed_until, lookahead)
+                    .into_iter()
+                    .find(|&scheduled| scheduled > processed_until);
+                if let Some(at) = next_dose {
+                    queue.push(DueItem { at, kind: DueKind::Dose });
+                }
+            }
+
+            for reminder in user.pending_reminders.values() {
+                let policy = user
+                    .medicines
+                    .get(&reminder.medicine_id)
+                    .map_or(&default_policy, |medicine| &medicine.escalation);
+                if let Some(at) = reminder.next_follow_up(policy) {
+                    queue.push(DueItem { at, kind: DueKind::FollowUp });
+                }
+                if !reminder.is_confirmed && reminder.snoozed_until.is_none() {
+                    queue.push(DueItem {
+                        at: user.reminder_expiry(reminder),
+                        kind: DueKind::FollowUp,
+                    });
+                }
+            }
+
+            let settings = &user.user_settings;
+            if let Some(travel) = &settings.travel {
+                queue.push(DueItem {
+                    at: travel.next_update(now),
+                    kind: DueKind::Travel,
+                });
+            }
+            if settings.weekly_report {
+                queue.push(DueItem {
+                    at: stats::next_weekly_report(now, tz, settings.last_weekly_report),
+                    kind: DueKind::WeeklyReport,
+                });
+            }
+        }
+
+        queue
+    }
+
+    pub fn push(&mut self, item: DueItem) {
+        self.heap.push(Reverse(item));
+    }
+
+    pub fn peek(&self) -> Option<&DueItem> {
+        self.heap.peek().map(|Reverse(item)| item)
+    }
+
+    pub fn len(&self) -> usize {
+        self.heap.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.heap.is_empty()
+    }
+
+    /// 取出所有不晚于 `now` 的事件，返回需要执行的检查类型（已去重）
+    pub fn pop_due(&mut self, now: DateTime<Local>) -> Vec<DueKind> {
+        let mut kinds = Vec::new();
+        while self.peek().is_some_and(|item| item.at <= now) {
+            let Some(Reverse(item)) = self.heap.pop() else { break };
+            if !kinds.contains(&item.kind) {
+                kinds.push(item.kind);
+            }
+        }
+        kinds.sort();
+        kinds
+    }
+}
+
+/// 本次需要检查的时间区间 `(from, to]`。
+///
+/// 首次运行时只检查最近一分钟；停机太久时最多补查 `MAX_CATCH_UP_DAYS` 天。
+pub fn due_window(last_processed: Option<DateTime<Local>>, now: DateTime<Local>) -> (DateTime<Local>, DateTime<Local>) {
+    let earliest = now - Duration::days(MAX_CATCH_UP_DAYS);
+    let from = match last_processed {
+        Some(last) if last < now => last.max(earliest),
+        Some(_) => now,
+        None => now - Duration::minutes(1),
+    };
+    (from, now)
+}
+
+/// 一个聊天在检查区间内到期的服药
+#[derive(Debug, Default)]
+pub struct DuePlan {
+    /// 需要发送的提醒（包括宽限时间内的迟到提醒）
+    pub reminders: Vec<PendingReminder>,
+    /// 超过宽限时间、记为错过的服药
+    pub missed: Vec<DoseEvent>,
+    /// 到期但药品已用完的药品
+    pub out_of_stock: Vec<(Uuid, String)>,
+}
+
+impl DuePlan {
+    pub fn is_empty(&self) -> bool {
+        self.reminders.is_empty() && self.missed.is_empty() && self.out_of_stock.is_empty()
+    }
+}
+
+/// 找出计划时间在 `(from, to]` 内的服药，按药品的宽限时间决定发送提醒还是记为错过。
+///
+/// 同一药品在区间内有多次服药时，只提醒最近的一次，其余记为错过；
+/// 每个服药时段只创建一次提醒，同一药品的其他时段互不影响。
+pub fn plan_due_doses(user: &UserData, from: DateTime<Local>, to: DateTime<Local>) -> DuePlan {
+    let mut plan = DuePlan::default();
+    let tz = user.timezone();
+
+    for medicine in user.medicines.values() {
+        if !medicine.is_active || medicine.prn_policy().is_some() {
+            continue;
+        }
+
+        let due: Vec<DateTime<Local>> = medicine
+            .occurrences_in(tz, from, to)
+            .into_iter()
+            .filter(|&scheduled| scheduled > from)
+            .collect();
+        let grace = Duration::minutes(i64::from(medicine.grace_minutes));
+        let latest_in_grace = due.iter().rev().find(|&&scheduled| to - scheduled <= grace).copied();
+
+        for scheduled in due {
+            if Some(scheduled) != latest_in_grace {
+                plan.missed.push(DoseEvent::new(
+                    medicine.id,
+                    medicine.name.clone(),
+                    DoseStatus::Missed,
+                    Some(scheduled),
+                    0,
+                ));
+                continue;
+            }
+
+            // 药品已用完时不创建提醒，改为发送缺药警告
+            if medicine.quantity == 0 {
+                plan.out_of_stock.push((medicine.id, medicine.name.clone()));
+                continue;
+            }
+
+            if !user.pending_reminders.contains_key(&PendingReminder::slot_id(medicine.id, scheduled)) {
+                plan.reminders.push(PendingReminder::new(medicine.id, medicine.name.clone(), scheduled));
+            }
+        }
+    }
+
+    plan
+}
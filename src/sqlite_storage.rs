@@ -0,0 +1,409 @@
+use crate::storage::{JsonStorage, StorageBackend, UserSettings};
+use crate::{access::Invite, AppData, DoseEvent, Medicine, PendingReminder, UserData};
+use rusqlite::types::Value;
+use rusqlite::{params, Connection, OptionalExtension, Transaction};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::Mutex;
+
+type StorageResult<T> = Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+/// 按顺序执行的数据库迁移，`PRAGMA user_version` 记录已执行的数量
+const MIGRATIONS: &[&str] = &[
+    // 1: 初始表结构。除了用于查询的列，其余字段以 JSON 保存在 data 列中
+    "CREATE TABLE users (
+        chat_id INTEGER PRIMARY KEY,
+        settings TEXT NOT NULL
+    );
+    CREATE TABLE medicines (
+        id TEXT PRIMARY KEY,
+        chat_id INTEGER NOT NULL,
+        name TEXT NOT NULL,
+        quantity INTEGER NOT NULL,
+        data TEXT NOT NULL
+    );
+    CREATE INDEX idx_medicines_chat ON medicines (chat_id);
+    CREATE TABLE pending_reminders (
+        id TEXT PRIMARY KEY,
+        chat_id INTEGER NOT NULL,
+        medicine_id TEXT NOT NULL,
+        scheduled_time TEXT NOT NULL,
+        is_confirmed INTEGER NOT NULL,
+        data TEXT NOT NULL
+    );
+    CREATE INDEX idx_pending_reminders_chat ON pending_reminders (chat_id);
+    CREATE TABLE dose_events (
+        id TEXT PRIMARY KEY,
+        chat_id INTEGER NOT NULL,
+        medicine_id TEXT NOT NULL,
+        status TEXT NOT NULL,
+        actual_time TEXT NOT NULL,
+        data TEXT NOT NULL
+    );
+    CREATE INDEX idx_dose_events_chat_time ON dose_events (chat_id, actual_time);
+    CREATE TABLE allowed_users (
+        user_id INTEGER PRIMARY KEY
+    );
+    CREATE TABLE invites (
+        code TEXT PRIMARY KEY,
+        data TEXT NOT NULL
+    );
+    CREATE TABLE meta (
+        key TEXT PRIMARY KEY,
+        value TEXT NOT NULL
+    );",
+    // 2: 保存未完成的对话状态
+    "ALTER TABLE users ADD COLUMN dialogue_state TEXT;",
+];
+
+/// 保存旧版单用户数据的 meta 键
+const LEGACY_DATA_KEY: &str = "legacy_data";
+/// 保存提醒循环最后处理时间的 meta 键
+const LAST_PROCESSED_KEY: &str = "last_processed";
+
+/// 表名和列名，第一列为主键
+struct Table {
+    name: &'static str,
+    columns: &'static [&'static str],
+}
+
+const USERS: Table = Table {
+    name: "users",
+    columns: &["chat_id", "settings", "dialogue_state"],
+};
+const MEDICINES: Table = Table {
+    name: "medicines",
+    columns: &["id", "chat_id", "name", "quantity", "data"],
+};
+const PENDING_REMINDERS: Table = Table {
+    name: "pending_reminders",
+    columns: &["id", "chat_id", "medicine_id", "scheduled_time", "is_confirmed", "data"],
+};
+const DOSE_EVENTS: Table = Table {
+    name: "dose_events",
+    columns: &["id", "chat_id", "medicine_id", "status", "actual_time", "data"],
+};
+const ALLOWED_USERS: Table = Table {
+    name: "allowed_users",
+    columns: &["user_id"],
+};
+const INVITES: Table = Table {
+    name: "invites",
+    columns: &["code", "data"],
+};
+const META: Table = Table {
+    name: "meta",
+    columns: &["key", "value"],
+};
+
+const TABLES: [&Table; 7] = [&USERS, &MEDICINES, &PENDING_REMINDERS, &DOSE_EVENTS, &ALLOWED_USERS, &INVITES, &META];
+
+/// 每张表的行，键为主键的文本形式
+type TableRows = HashMap<String, Vec<Value>>;
+
+/// 使用 SQLite 保存数据。
+///
+/// 每次保存只写入与上次保存相比发生变化的行，并在同一个事务中完成。
+pub struct SqliteStorage {
+    connection: Mutex<Connection>,
+    // 上次保存（或加载）时各表的内容，用于计算需要写入的行
+    saved_rows: Mutex<HashMap<&'static str, TableRows>>,
+}
+
+impl SqliteStorage {
+    pub fn open<P: AsRef<Path>>(path: P) -> StorageResult<Self> {
+        let connection = Connection::open(path)?;
+        Self::with_connection(connection)
+    }
+
+    pub fn open_in_memory() -> StorageResult<Self> {
+        Self::with_connection(Connection::open_in_memory()?)
+    }
+
+    fn with_connection(mut connection: Connection) -> StorageResult<Self> {
+        connection.pragma_update(None, "journal_mode", "WAL")?;
+        run_migrations(&mut connection)?;
+
+        Ok(Self {
+            connection: Mutex::new(connection),
+            saved_rows: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// 数据库是否还没有任何数据
+    pub fn is_empty(&self) -> StorageResult<bool> {
+        let connection = self.connection.lock().map_err(|_| "数据库连接锁已损坏")?;
+        for table in TABLES {
+            let has_rows = connection
+                .query_row(&format!("SELECT 1 FROM {} LIMIT 1", table.name), [], |_| Ok(()))
+                .optional()?
+                .is_some();
+            if has_rows {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// 数据库为空且 JSON 数据文件存在时，将其中的数据导入数据库，
+    /// 并将 JSON 文件重命名为 `*.migrated`，避免重复导入。返回是否进行了导入。
+    pub fn migrate_from_json(&self, json: &JsonStorage) -> StorageResult<bool> {
+        if !Path::new(json.file_path()).exists() || !self.is_empty()? {
+            return Ok(false);
+        }
+
+        let data = json.load()?;
+        self.save(&data)?;
+        let migrated_path = format!("{}.migrated", json.file_path());
+        fs::rename(json.file_path(), &migrated_path)?;
+        log::info!("已将 {} 中的数据导入 SQLite，原文件已重命名为 {}", json.file_path(), migrated_path);
+        Ok(true)
+    }
+}
+
+impl StorageBackend for SqliteStorage {
+    fn load(&self) -> StorageResult<AppData> {
+        let connection = self.connection.lock().map_err(|_| "数据库连接锁已损坏")?;
+        let mut data = AppData::default();
+
+        let mut statement = connection.prepare("SELECT chat_id, settings, dialogue_state FROM users")?;
+        let rows = statement.query_map([], |row| {
+            Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?, row.get::<_, Option<String>>(2)?))
+        })?;
+        for row in rows {
+            let (chat_id, settings, dialogue_state) = row?;
+            let user = data.user_mut(chat_id);
+            user.user_settings = serde_json::from_str::<UserSettings>(&settings)?;
+            user.dialogue_state = dialogue_state.as_deref().map(serde_json::from_str).transpose()?;
+        }
+
+        for (chat_id, json) in query_data(&connection, "SELECT chat_id, data FROM medicines")? {
+            let medicine: Medicine = serde_json::from_str(&json)?;
+            data.user_mut(chat_id).medicines.insert(medicine.id, medicine);
+        }
+
+        for (chat_id, json) in query_data(&connection, "SELECT chat_id, data FROM pending_reminders")? {
+            let reminder: PendingReminder = serde_json::from_str(&json)?;
+            data.user_mut(chat_id).pending_reminders.insert(reminder.id, reminder);
+        }
+
+        // 按 rowid 读取，保持追加的顺序
+        for (chat_id, json) in query_data(&connection, "SELECT chat_id, data FROM dose_events ORDER BY rowid")? {
+            let event: DoseEvent = serde_json::from_str(&json)?;
+            data.user_mut(chat_id).dose_history.push(event);
+        }
+
+        let mut statement = connection.prepare("SELECT user_id FROM allowed_users")?;
+        for user_id in statement.query_map([], |row| row.get::<_, i64>(0))? {
+            data.access.allowed_users.insert(user_id? as u64);
+        }
+
+        let mut statement = connection.prepare("SELECT code, data FROM invites")?;
+        let rows = statement.query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))?;
+        for row in rows {
+            let (code, json) = row?;
+            data.access.invites.insert(code, serde_json::from_str::<Invite>(&json)?);
+        }
+
+        let legacy: Option<String> = connection
+            .query_row("SELECT value FROM meta WHERE key = ?1", [LEGACY_DATA_KEY], |row| row.get(0))
+            .optional()?;
+        if let Some(legacy) = legacy {
+            data.restore_legacy_json(&legacy)?;
+        }
+
+        let last_processed: Option<String> = connection
+            .query_row("SELECT value FROM meta WHERE key = ?1", [LAST_PROCESSED_KEY], |row| row.get(0))
+            .optional()?;
+        data.last_processed = last_processed.as_deref().map(serde_json::from_str).transpose()?;
+
+        // 记录当前内容，之后保存时只写入变化的行
+        let rows = table_rows(&data)?;
+        *self.saved_rows.lock().map_err(|_| "数据缓存锁已损坏")? = rows;
+
+        log::debug!("成功从 SQLite 加载数据");
+        Ok(data)
+    }
+
+    fn save(&self, data: &AppData) -> StorageResult<()> {
+        let mut connection = self.connection.lock().map_err(|_| "数据库连接锁已损坏")?;
+        let mut saved_rows = self.saved_rows.lock().map_err(|_| "数据缓存锁已损坏")?;
+
+        let rows = table_rows(data)?;
+        let transaction = connection.transaction()?;
+        for table in TABLES {
+            let empty = TableRows::new();
+            let saved = saved_rows.get(table.name).unwrap_or(&empty);
+            let current = rows.get(table.name).unwrap_or(&empty);
+            write_table_changes(&transaction, table, saved, current)?;
+        }
+        transaction.commit()?;
+
+        *saved_rows = rows;
+        log::debug!("成功保存数据到 SQLite");
+        Ok(())
+    }
+}
+
+fn run_migrations(connection: &mut Connection) -> StorageResult<()> {
+    let version: usize = connection.pragma_query_value(None, "user_version", |row| row.get(0))?;
+    if version > MIGRATIONS.len() {
+        return Err(format!("数据库版本 {} 高于程序支持的版本 {}", version, MIGRATIONS.len()).into());
+    }
+
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+        let transaction = connection.transaction()?;
+        transaction.execute_batch(migration)?;
+        transaction.pragma_update(None, "user_version", index + 1)?;
+        transaction.commit()?;
+        log::info!("已执行数据库迁移 {}", index + 1);
+    }
+    Ok(())
+}
+
+fn query_data(connection: &Connection, sql: &str) -> rusqlite::Result<Vec<(i64, String)>> {
+    let mut statement = connection.prepare(sql)?;
+    let rows = statement.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+    rows.collect()
+}
+
+fn write_table_changes(
+    transaction: &Transaction,
+    table: &Table,
+    saved: &TableRows,
+    current: &TableRows,
+) -> rusqlite::Result<()> {
+    let placeholders: Vec<String> = (1..=table.columns.len()).map(|i| format!("?{}", i)).collect();
+    let mut upsert = transaction.prepare_cached(&format!(
+        "INSERT OR REPLACE INTO {} ({}) VALUES ({})",
+        table.name,
+        table.columns.join(", "),
+        placeholders.join(", ")
+    ))?;
+    for (key, values) in current {
+        if saved.get(key) != Some(values) {
+            upsert.execute(rusqlite::params_from_iter(values.iter()))?;
+        }
+    }
+
+    let mut delete =
+        transaction.prepare_cached(&format!("DELETE FROM {} WHERE {} = ?1", table.name, table.columns[0]))?;
+    for (key, values) in saved {
+        if !current.contains_key(key) {
+            delete.execute(params![values[0]])?;
+        }
+    }
+    Ok(())
+}
+
+// 将数据转换为各表的行。服药记录也可能被修改（例如迁移提醒ID），因此与其他表一样按内容比较
+fn table_rows(data: &AppData) -> serde_json::Result<HashMap<&'static str, TableRows>> {
+    let mut rows: HashMap<&'static str, TableRows> = TABLES.iter().map(|table| (table.name, TableRows::new())).collect();
+
+    for (&chat_id, user) in &data.users {
+        let UserData {
+            medicines,
+            pending_reminders,
+            user_settings,
+            dose_history,
+            dialogue_state,
+        } = user;
+
+        insert_row(
+            &mut rows,
+            &USERS,
+            vec![
+                Value::Integer(chat_id),
+                Value::Text(serde_json::to_string(user_settings)?),
+                match dialogue_state {
+                    Some(state) => Value::Text(serde_json::to_string(state)?),
+                    None => Value::Null,
+                },
+            ],
+        );
+
+        for medicine in medicines.values() {
+            insert_row(
+                &mut rows,
+                &MEDICINES,
+                vec![
+                    Value::Text(medicine.id.to_string()),
+                    Value::Integer(chat_id),
+                    Value::Text(medicine.name.clone()),
+                    Value::Integer(i64::from(medicine.quantity)),
+                    Value::Text(serde_json::to_string(medicine)?),
+                ],
+            );
+        }
+
+        for reminder in pending_reminders.values() {
+            insert_row(
+                &mut rows,
+                &PENDING_REMINDERS,
+                vec![
+                    Value::Text(reminder.id.to_string()),
+                    Value::Integer(chat_id),
+                    Value::Text(reminder.medicine_id.to_string()),
+                    Value::Text(reminder.scheduled_time.to_rfc3339()),
+                    Value::Integer(i64::from(reminder.is_confirmed)),
+                    Value::Text(serde_json::to_string(reminder)?),
+                ],
+            );
+        }
+
+        for event in dose_history {
+            insert_row(
+                &mut rows,
+                &DOSE_EVENTS,
+                vec![
+                    Value::Text(event.id.to_string()),
+                    Value::Integer(chat_id),
+                    Value::Text(event.medicine_id.to_string()),
+                    Value::Text(serde_json::to_string(&event.status)?.trim_matches('"').to_string()),
+                    Value::Text(event.actual_time.to_rfc3339()),
+                    Value::Text(serde_json::to_string(event)?),
+                ],
+            );
+        }
+    }
+
+    for &user_id in &data.access.allowed_users {
+        insert_row(&mut rows, &ALLOWED_USERS, vec![Value::Integer(user_id as i64)]);
+    }
+    for (code, invite) in &data.access.invites {
+        insert_row(
+            &mut rows,
+            &INVITES,
+            vec![Value::Text(code.clone()), Value::Text(serde_json::to_string(invite)?)],
+        );
+    }
+    if let Some(last_processed) = &data.last_processed {
+        insert_row(
+            &mut rows,
+            &META,
+            vec![
+                Value::Text(LAST_PROCESSED_KEY.to_string()),
+                Value::Text(serde_json::to_string(last_processed)?),
+            ],
+        );
+    }
+    if let Some(legacy) = data.legacy_json() {
+        insert_row(
+            &mut rows,
+            &META,
+            vec![Value::Text(LEGACY_DATA_KEY.to_string()), Value::Text(legacy)],
+        );
+    }
+
+    Ok(rows)
+}
+
+fn insert_row(rows: &mut HashMap<&'static str, TableRows>, table: &Table, values: Vec<Value>) {
+    let key = match &values[0] {
+        Value::Integer(n) => n.to_string(),
+        Value::Text(s) => s.clone(),
+        other => format!("{:?}", other),
+    };
+    rows.entry(table.name).or_default().insert(key, values);
+}
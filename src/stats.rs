@@ -0,0 +1,264 @@
+use crate::{timezone, DoseEvent, DoseStatus, PendingReminders};
+use chrono::{DateTime, Datelike, Duration, Local, NaiveDate, NaiveTime, Timelike, Weekday};
+use chrono_tz::Tz;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+/// 超过计划时间多少分钟服药算作迟服
+pub const LATE_THRESHOLD_MINUTES: i64 = 30;
+
+/// 报告中显示的最常错过时间段数量
+const MOST_MISSED_SLOTS: usize = 3;
+
+/// 每周报告在周一的几点之后发送
+pub const WEEKLY_REPORT_HOUR: u32 = 9;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SlotOutcome {
+    OnTime,
+    Late,
+    Skipped,
+    Missed,
+}
+
+/// 一次计划服药（一个提醒）的最终结果
+#[derive(Debug, Clone)]
+pub struct DoseSlot {
+    pub medicine_id: Uuid,
+    pub medicine_name: String,
+    pub scheduled_time: DateTime<Local>,
+    pub outcome: SlotOutcome,
+    /// 服药时间相对计划时间的延迟，仅对已服药的时段有值
+    pub lateness: Option<Duration>,
+}
+
+impl DoseSlot {
+    pub fn is_taken(&self) -> bool {
+        matches!(self.outcome, SlotOutcome::OnTime | SlotOutcome::Late)
+    }
+}
+
+/// 从服药记录中整理出计划时间在 `[from, to)` 内且已有结果的时段，按计划时间排序。
+///
+/// 同一提醒的多条记录以最后一条非延迟记录为准；只有延迟记录的时段尚未结束，不计入统计。
+/// 超过迟服时间仍未确认的提醒按错过计算，之后确认时会变为迟服。
+pub fn collect_slots(
+    history: &[DoseEvent],
+    pending: &PendingReminders,
+    from: DateTime<Local>,
+    to: DateTime<Local>,
+    now: DateTime<Local>,
+) -> Vec<DoseSlot> {
+    let mut latest: HashMap<(Uuid, Option<Uuid>, DateTime<Local>), &DoseEvent> = HashMap::new();
+
+    for event in history {
+        let Some(scheduled_time) = event.scheduled_time else {
+            continue;
+        };
+        if event.status == DoseStatus::Snoozed || scheduled_time < from || scheduled_time >= to {
+            continue;
+        }
+
+        let key = (event.medicine_id, event.reminder_id, scheduled_time);
+        match latest.get(&key) {
+            Some(existing) if existing.actual_time > event.actual_time => {}
+            _ => {
+                latest.insert(key, event);
+            }
+        }
+    }
+
+    let mut slots: Vec<DoseSlot> = latest
+        .into_values()
+        .map(|event| {
+            let scheduled_time = event.scheduled_time.unwrap_or(event.actual_time);
+            let lateness = (event.actual_time - scheduled_time).max(Duration::zero());
+            let outcome = match event.status {
+                DoseStatus::Taken if lateness > Duration::minutes(LATE_THRESHOLD_MINUTES) => SlotOutcome::Late,
+                DoseStatus::Taken | DoseStatus::Snoozed => SlotOutcome::OnTime,
+                DoseStatus::Skipped => SlotOutcome::Skipped,
+                DoseStatus::Missed => SlotOutcome::Missed,
+            };
+            DoseSlot {
+                medicine_id: event.medicine_id,
+                medicine_name: event.medicine_name.clone(),
+                scheduled_time,
+                outcome,
+                lateness: (event.status == DoseStatus::Taken).then_some(lateness),
+            }
+        })
+        .collect();
+
+    let missed_before = now - Duration::minutes(LATE_THRESHOLD_MINUTES);
+    slots.extend(
+        pending
+            .values()
+            .filter(|reminder| !reminder.is_confirmed)
+            .filter(|reminder| reminder.scheduled_time >= from && reminder.scheduled_time < to)
+            .filter(|reminder| reminder.scheduled_time < missed_before)
+            .map(|reminder| DoseSlot {
+                medicine_id: reminder.medicine_id,
+                medicine_name: reminder.medicine_name.clone(),
+                scheduled_time: reminder.scheduled_time,
+                outcome: SlotOutcome::Missed,
+                lateness: None,
+            }),
+    );
+
+    slots.sort_by_key(|slot| slot.scheduled_time);
+    slots
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MedicineStats {
+    pub medicine_id: Uuid,
+    pub medicine_name: String,
+    pub on_time: u32,
+    pub late: u32,
+    pub skipped: u32,
+    pub missed: u32,
+    /// 已服药时段的平均延迟（分钟）
+    pub average_lateness_minutes: Option<i64>,
+    /// 截至最近一次的连续服药次数
+    pub current_streak: u32,
+    pub best_streak: u32,
+}
+
+impl MedicineStats {
+    pub fn total(&self) -> u32 {
+        self.on_time + self.late + self.skipped + self.missed
+    }
+
+    pub fn taken(&self) -> u32 {
+        self.on_time + self.late
+    }
+
+    /// 服药依从率（百分比），没有任何时段时为 `None`
+    pub fn adherence_percent(&self) -> Option<f64> {
+        match self.total() {
+            0 => None,
+            total => Some(f64::from(self.taken()) * 100.0 / f64::from(total)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AdherenceReport {
+    pub from: DateTime<Local>,
+    pub to: DateTime<Local>,
+    pub medicines: Vec<MedicineStats>,
+    /// 错过次数最多的计划时间（`timezone` 时区），按次数从多到少排列
+    pub most_missed_slots: Vec<(NaiveTime, u32)>,
+    /// 报告使用的时区
+    pub timezone: Tz,
+}
+
+impl AdherenceReport {
+    pub fn is_empty(&self) -> bool {
+        self.medicines.is_empty()
+    }
+}
+
+pub fn build_report(
+    history: &[DoseEvent],
+    pending: &PendingReminders,
+    from: DateTime<Local>,
+    to: DateTime<Local>,
+    now: DateTime<Local>,
+    tz: Tz,
+) -> AdherenceReport {
+    let slots = collect_slots(history, pending, from, to, now);
+
+    let mut by_medicine: HashMap<Uuid, Vec<&DoseSlot>> = HashMap::new();
+    for slot in &slots {
+        by_medicine.entry(slot.medicine_id).or_default().push(slot);
+    }
+
+    let mut medicines: Vec<MedicineStats> = by_medicine
+        .into_iter()
+        .map(|(medicine_id, slots)| medicine_stats(medicine_id, &slots))
+        .collect();
+    medicines.sort_by(|a, b| a.medicine_name.cmp(&b.medicine_name));
+
+    let mut missed_counts: HashMap<NaiveTime, u32> = HashMap::new();
+    for slot in slots.iter().filter(|slot| slot.outcome == SlotOutcome::Missed) {
+        *missed_counts.entry(slot.scheduled_time.with_timezone(&tz).time()).or_default() += 1;
+    }
+    let mut most_missed_slots: Vec<(NaiveTime, u32)> = missed_counts.into_iter().collect();
+    most_missed_slots.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    most_missed_slots.truncate(MOST_MISSED_SLOTS);
+
+    AdherenceReport {
+        from,
+        to,
+        medicines,
+        most_missed_slots,
+        timezone: tz,
+    }
+}
+
+/// 是否应该发送每周报告：`tz` 时区每周一 `WEEKLY_REPORT_HOUR` 点之后，且当天尚未发送
+pub fn weekly_report_due(now: DateTime<Local>, tz: Tz, last_sent: Option<NaiveDate>) -> bool {
+    let now = now.with_timezone(&tz);
+    now.weekday() == Weekday::Mon && now.hour() >= WEEKLY_REPORT_HOUR && last_sent != Some(now.date_naive())
+}
+
+/// 下一次发送每周报告的时间，已经到期时返回 `now`
+pub fn next_weekly_report(now: DateTime<Local>, tz: Tz, last_sent: Option<NaiveDate>) -> DateTime<Local> {
+    if weekly_report_due(now, tz, last_sent) {
+        return now;
+    }
+
+    let today = now.with_timezone(&tz).date_naive();
+    let mut monday = today + Duration::days(i64::from((7 - today.weekday().num_days_from_monday()) % 7));
+    loop {
+        let report_time = timezone::resolve(tz, monday.and_time(NaiveTime::MIN) + Duration::hours(i64::from(WEEKLY_REPORT_HOUR)));
+        if report_time > now {
+            return report_time;
+        }
+        monday += Duration::days(7);
+    }
+}
+
+/// 截至 `tz` 时区 `today` 零点的前7天
+pub fn weekly_report_period(today: NaiveDate, tz: Tz) -> (DateTime<Local>, DateTime<Local>) {
+    (timezone::start_of_day(tz, today - Duration::days(7)), timezone::start_of_day(tz, today))
+}
+
+// slots 已按计划时间排序
+fn medicine_stats(medicine_id: Uuid, slots: &[&DoseSlot]) -> MedicineStats {
+    let mut stats = MedicineStats {
+        medicine_id,
+        medicine_name: slots.last().map(|slot| slot.medicine_name.clone()).unwrap_or_default(),
+        ..Default::default()
+    };
+
+    let mut lateness_total = Duration::zero();
+    let mut streak = 0;
+    for slot in slots {
+        match slot.outcome {
+            SlotOutcome::OnTime => stats.on_time += 1,
+            SlotOutcome::Late => stats.late += 1,
+            SlotOutcome::Skipped => stats.skipped += 1,
+            SlotOutcome::Missed => stats.missed += 1,
+        }
+
+        if let Some(lateness) = slot.lateness {
+            lateness_total += lateness;
+        }
+
+        if slot.is_taken() {
+            streak += 1;
+            stats.best_streak = stats.best_streak.max(streak);
+        } else {
+            streak = 0;
+        }
+    }
+    stats.current_streak = streak;
+
+    if stats.taken() > 0 {
+        stats.average_lateness_minutes = Some(lateness_total.num_minutes() / i64::from(stats.taken()));
+    }
+
+    stats
+}
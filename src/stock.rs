@@ -0,0 +1,177 @@
+use crate::{DoseEvent, DoseStatus, Medicine};
+use chrono::{DateTime, Duration, Local, NaiveDate};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// 默认在剩余药量不足几天时提醒
+pub const DEFAULT_LOW_SUPPLY_DAYS: u32 = 3;
+
+/// 预测用完日期时最多向后计算的天数
+const FORECAST_HORIZON_DAYS: i64 = 365;
+
+/// 计算常用剂量时参考的最近服药次数
+const TYPICAL_DOSE_SAMPLES: usize = 10;
+
+/// 按需药品按最近几天的用量估算每日用量
+const PRN_USAGE_WINDOW_DAYS: i64 = 14;
+
+/// 药品的库存提醒设置
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct StockAlert {
+    /// 剩余数量不超过该值时提醒，`None` 表示不按数量提醒
+    #[serde(default)]
+    pub low_stock_threshold: Option<u32>,
+    /// 预计剩余天数少于该值时提醒，0 表示不按天数提醒
+    #[serde(default = "default_low_supply_days")]
+    pub low_supply_days: u32,
+}
+
+fn default_low_supply_days() -> u32 {
+    DEFAULT_LOW_SUPPLY_DAYS
+}
+
+impl Default for StockAlert {
+    fn default() -> Self {
+        Self {
+            low_stock_threshold: None,
+            low_supply_days: DEFAULT_LOW_SUPPLY_DAYS,
+        }
+    }
+}
+
+impl StockAlert {
+    /// 解析用户输入：`数量阈值 [天数]`，阈值为 0 表示不按数量提醒，例如 `10 5`；
+    /// `default` 恢复默认设置
+    pub fn parse(input: &str) -> Option<Self> {
+        let input = input.trim().to_lowercase();
+        if input == "default" {
+            return Some(Self::default());
+        }
+
+        let mut parts = input.split_whitespace();
+        let threshold: u32 = parts.next()?.parse().ok()?;
+        let low_supply_days = match parts.next() {
+            Some(days) => days.parse().ok()?,
+            None => DEFAULT_LOW_SUPPLY_DAYS,
+        };
+        if parts.next().is_some() {
+            return None;
+        }
+
+        Some(Self {
+            low_stock_threshold: (threshold > 0).then_some(threshold),
+            low_supply_days,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StockLevel {
+    Ok,
+    Low,
+    Empty,
+}
+
+/// 剩余药量的预测
+#[derive(Debug, Clone, PartialEq)]
+pub struct SupplyForecast {
+    /// 预计每次服用的数量
+    pub dose_amount: u32,
+    /// 预计无法足量服药的第一天，无法预测或一年内不会用完时为 `None`
+    pub runs_out_on: Option<NaiveDate>,
+    /// 距离用完还有几天
+    pub days_left: Option<i64>,
+}
+
+/// 最近几次按计划服药的剂量中位数，没有记录时为 1
+pub fn typical_dose(history: &[DoseEvent], medicine_id: Uuid) -> u32 {
+    let mut amounts: Vec<u32> = history
+        .iter()
+        .rev()
+        .filter(|event| {
+            event.medicine_id == medicine_id
+                && event.status == DoseStatus::Taken
+                && event.scheduled_time.is_some()
+                && event.amount > 0
+        })
+        .take(TYPICAL_DOSE_SAMPLES)
+        .map(|event| event.amount)
+        .collect();
+
+    if amounts.is_empty() {
+        return 1;
+    }
+    amounts.sort_unstable();
+    amounts[amounts.len() / 2]
+}
+
+/// 根据服药计划（按需药品根据最近用量）预测药品何时用完，日期按 `tz` 时区计算
+pub fn forecast_supply(medicine: &Medicine, history: &[DoseEvent], now: DateTime<Local>, tz: Tz) -> SupplyForecast {
+    let today = now.with_timezone(&tz).date_naive();
+
+    if medicine.prn_policy().is_some() {
+        let since = now - Duration::days(PRN_USAGE_WINDOW_DAYS);
+        let used: u32 = history
+            .iter()
+            .filter(|event| {
+                event.medicine_id == medicine.id
+                    && event.status == DoseStatus::Taken
+                    && event.scheduled_time.is_none()
+                    && event.actual_time >= since
+            })
+            .map(|event| event.amount)
+            .sum();
+        let dose_amount = 1;
+        if used == 0 {
+            return SupplyForecast {
+                dose_amount,
+                runs_out_on: None,
+                days_left: None,
+            };
+        }
+
+        let daily_usage = f64::from(used) / PRN_USAGE_WINDOW_DAYS as f64;
+        let days_left = (f64::from(medicine.quantity) / daily_usage).floor() as i64;
+        return SupplyForecast {
+            dose_amount,
+            runs_out_on: Some(today + Duration::days(days_left)),
+            days_left: Some(days_left),
+        };
+    }
+
+    let dose_amount = typical_dose(history, medicine.id);
+    let doses_left = (medicine.quantity / dose_amount) as usize;
+    let runs_out_on = medicine
+        .occurrences_in(tz, now, now + Duration::days(FORECAST_HORIZON_DAYS))
+        .get(doses_left)
+        .map(|occurrence| occurrence.with_timezone(&tz).date_naive());
+
+    SupplyForecast {
+        dose_amount,
+        runs_out_on,
+        days_left: runs_out_on.map(|date| (date - today).num_days()),
+    }
+}
+
+/// 根据药品的库存提醒设置判断当前库存状态
+pub fn stock_level(medicine: &Medicine, forecast: &SupplyForecast) -> StockLevel {
+    if medicine.quantity == 0 {
+        return StockLevel::Empty;
+    }
+
+    let alert = &medicine.stock_alert;
+    let below_threshold = alert
+        .low_stock_threshold
+        .is_some_and(|threshold| medicine.quantity <= threshold);
+    let running_out = alert.low_supply_days > 0
+        && forecast
+            .days_left
+            .is_some_and(|days| days < i64::from(alert.low_supply_days));
+
+    if below_threshold || running_out {
+        StockLevel::Low
+    } else {
+        StockLevel::Ok
+    }
+}
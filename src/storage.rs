@@ -1,63 +1,492 @@
-use crate::{MedicineStore, PendingReminders};
+use crate::stats::{self, AdherenceReport};
+use crate::timezone;
+use crate::travel::{TravelEvent, TravelPlan};
+use crate::{
+    access::AccessControl, forecast_supply, stock_level, Caregiver, DoseEvent, DoseHistory, DoseStatus, Medicine,
+    MedicineStore, PendingReminder, PendingReminders, StockLevel, SupplyForecast, FALLBACK_EXPIRY_HOURS,
+};
+use chrono::{DateTime, Duration, Local, NaiveDate};
+use chrono_tz::Tz;
 use serde::{Deserialize, Serialize};
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
 use std::fs;
 use std::path::Path;
+use uuid::Uuid;
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
 pub enum Language {
+    #[default]
     Chinese,
     English,
 }
 
-impl Default for Language {
-    fn default() -> Self {
-        Language::Chinese
-    }
-}
-
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct UserSettings {
     pub language: Language,
+    /// 所有药品默认的照护人
+    #[serde(default)]
+    pub caregiver: Option<Caregiver>,
+    /// 是否每周发送服药统计
+    #[serde(default = "default_weekly_report")]
+    pub weekly_report: bool,
+    /// 上次发送每周报告的日期
+    #[serde(default)]
+    pub last_weekly_report: Option<NaiveDate>,
+    /// IANA 时区，未设置时使用服务器时区
+    #[serde(default)]
+    pub timezone: Option<Tz>,
+    /// 旅行模式
+    #[serde(default)]
+    pub travel: Option<TravelPlan>,
+}
+
+fn default_weekly_report() -> bool {
+    true
 }
 
 impl Default for UserSettings {
     fn default() -> Self {
         Self {
             language: Language::Chinese,
+            caregiver: None,
+            weekly_report: default_weekly_report(),
+            last_weekly_report: None,
+            timezone: None,
+            travel: None,
         }
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct AppData {
+/// 已确认的提醒保留的天数，之后只保留服药记录
+pub const CLOSED_REMINDER_RETENTION_DAYS: i64 = 7;
+
+/// 确认服药后多少分钟内可以撤销
+pub const UNDO_WINDOW_MINUTES: i64 = 5;
+
+/// 撤销后重新打开的提醒至少在撤销后多少分钟才过期，留出后续提醒的时间
+pub const REOPENED_EXPIRY_MINUTES: i64 = 30;
+
+/// 单个聊天（用户或群组）的药箱数据
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UserData {
     pub medicines: MedicineStore,
     pub pending_reminders: PendingReminders,
     #[serde(default)]
     pub user_settings: UserSettings,
+    /// 服药记录，只追加，唯一的例外是撤销确认时删除对应的服药记录
+    #[serde(default)]
+    pub dose_history: DoseHistory,
+    /// 未完成的对话状态，重启后继续
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dialogue_state: Option<serde_json::Value>,
 }
 
-impl Default for AppData {
-    fn default() -> Self {
-        Self {
-            medicines: MedicineStore::new(),
-            pending_reminders: PendingReminders::new(),
-            user_settings: UserSettings::default(),
+impl UserData {
+    pub fn record_dose_event(&mut self, event: DoseEvent) {
+        self.dose_history.push(event);
+    }
+
+    /// 药品实际生效的照护人：药品单独设置的优先，否则使用用户设置
+    pub fn caregiver_for<'a>(&'a self, medicine: &'a Medicine) -> Option<&'a Caregiver> {
+        medicine
+            .caregiver
+            .as_ref()
+            .or(self.user_settings.caregiver.as_ref())
+    }
+
+    /// 服药计划和显示时间使用的时区
+    pub fn timezone(&self) -> Tz {
+        self.user_settings.timezone.unwrap_or_else(timezone::server_timezone)
+    }
+
+    pub fn supply_forecast(&self, medicine: &Medicine, now: DateTime<Local>) -> SupplyForecast {
+        forecast_supply(medicine, &self.dose_history, now, self.timezone())
+    }
+
+    /// 服药后检查库存，首次低于提醒条件时返回库存状态和预测，之后直到补充药品前不再返回
+    pub fn take_low_stock_alert(
+        &mut self,
+        medicine_id: Uuid,
+        now: DateTime<Local>,
+    ) -> Option<(StockLevel, SupplyForecast)> {
+        let medicine = self.medicines.get(&medicine_id)?;
+        let forecast = self.supply_forecast(medicine, now);
+        let level = stock_level(medicine, &forecast);
+        if level == StockLevel::Ok || medicine.low_stock_notified {
+            return None;
+        }
+
+        self.medicines.get_mut(&medicine_id)?.low_stock_notified = true;
+        Some((level, forecast))
+    }
+
+    /// 提醒最近一次的服药记录
+    pub fn taken_event(&self, reminder_id: Uuid) -> Option<&DoseEvent> {
+        self.dose_history
+            .iter()
+            .rev()
+            .find(|event| event.reminder_id == Some(reminder_id) && event.status == DoseStatus::Taken)
+    }
+
+    /// 撤销 `UNDO_WINDOW_MINUTES` 分钟内对提醒的确认：恢复库存、删除服药记录并重新打开提醒，返回被撤销的服药记录
+    pub fn undo_taken(&mut self, reminder_id: Uuid, now: DateTime<Local>) -> Option<DoseEvent> {
+        let reminder = self.pending_reminders.get(&reminder_id)?;
+        if !reminder.is_confirmed || reminder.skipped || reminder.missed || reminder.handled_by.is_some() {
+            return None;
+        }
+        let index = self
+            .dose_history
+            .iter()
+            .rposition(|event| event.reminder_id == Some(reminder_id) && event.status == DoseStatus::Taken)?;
+        if now - self.dose_history[index].actual_time > Duration::minutes(UNDO_WINDOW_MINUTES) {
+            return None;
+        }
+
+        let event = self.dose_history.remove(index);
+        if let Some(medicine) = self.medicines.get_mut(&event.medicine_id) {
+            medicine.quantity += event.amount;
+        }
+        // 恢复库存后不再低于提醒条件时，之后再次低于时重新提醒
+        if let Some(medicine) = self.medicines.get(&event.medicine_id) {
+            if stock_level(medicine, &self.supply_forecast(medicine, now)) == StockLevel::Ok {
+                self.medicines.get_mut(&event.medicine_id)?.low_stock_notified = false;
+            }
+        }
+        self.pending_reminders.get_mut(&reminder_id)?.reopen(now);
+        Some(event)
+    }
+
+    /// 暂停药品，`until` 为自动恢复的日期。该药品未处理的提醒记为跳过并关闭，返回这些提醒的ID；
+    /// 药品不存在时返回 `None`
+    pub fn pause_medicine(&mut self, medicine_id: Uuid, until: Option<NaiveDate>) -> Option<Vec<Uuid>> {
+        self.medicines.get_mut(&medicine_id)?.pause(until);
+
+        let mut closed = Vec::new();
+        for reminder in self.pending_reminders.values_mut() {
+            if reminder.medicine_id == medicine_id && !reminder.is_confirmed {
+                reminder.skip();
+                closed.push(reminder.id);
+                self.dose_history.push(
+                    DoseEvent::new(
+                        medicine_id,
+                        reminder.medicine_name.clone(),
+                        DoseStatus::Skipped,
+                        Some(reminder.scheduled_time),
+                        0,
+                    )
+                    .with_reminder(reminder.id),
+                );
+            }
+        }
+        Some(closed)
+    }
+
+    /// 恢复已到恢复日期的暂停药品，返回恢复的药品名称
+    pub fn resume_due_medicines(&mut self, now: DateTime<Local>) -> Vec<String> {
+        let today = now.with_timezone(&self.timezone()).date_naive();
+        let mut resumed = Vec::new();
+        for medicine in self.medicines.values_mut() {
+            if !medicine.is_active && medicine.paused_until.is_some_and(|until| until <= today) {
+                medicine.resume();
+                resumed.push(medicine.name.clone());
+            }
+        }
+        resumed.sort();
+        resumed
+    }
+
+    /// 删除计划时间早于 `before` 的已确认提醒，其结果已记录在服药记录中
+    pub fn prune_closed_reminders(&mut self, before: DateTime<Local>) -> usize {
+        let count = self.pending_reminders.len();
+        self.pending_reminders
+            .retain(|_, reminder| !reminder.is_confirmed || reminder.scheduled_time >= before);
+        count - self.pending_reminders.len()
+    }
+
+    /// 将旧数据中随机生成的提醒ID改为服药时段ID，并更新服药记录中的引用，返回修改的提醒数量。
+    ///
+    /// 同一时段有多个提醒时只保留一个：已关闭的优先，其次是提醒次数多的。
+    pub fn migrate_reminder_slots(&mut self) -> usize {
+        let mut renamed = HashMap::new();
+        let reminders: Vec<PendingReminder> = self.pending_reminders.drain().map(|(_, reminder)| reminder).collect();
+        for mut reminder in reminders {
+            let slot_id = PendingReminder::slot_id(reminder.medicine_id, reminder.scheduled_time);
+            if reminder.id != slot_id {
+                renamed.insert(reminder.id, slot_id);
+                reminder.id = slot_id;
+            }
+            match self.pending_reminders.entry(slot_id) {
+                Entry::Vacant(entry) => {
+                    entry.insert(reminder);
+                }
+                Entry::Occupied(mut entry) => {
+                    let existing = entry.get();
+                    if (reminder.is_confirmed, reminder.reminder_count) > (existing.is_confirmed, existing.reminder_count) {
+                        entry.insert(reminder);
+                    }
+                }
+            }
+        }
+
+        for event in &mut self.dose_history {
+            if let Some(slot_id) = event.reminder_id.and_then(|id| renamed.get(&id)) {
+                event.reminder_id = Some(*slot_id);
+            }
+        }
+        renamed.len()
+    }
+
+    /// 未确认提醒的过期时间，药品已删除时按计划时间之后一天计算
+    pub fn reminder_expiry(&self, reminder: &PendingReminder) -> DateTime<Local> {
+        let expiry = match self.medicines.get(&reminder.medicine_id) {
+            Some(medicine) => medicine.reminder_expiry(self.timezone(), reminder.scheduled_time),
+            None => reminder.scheduled_time + Duration::hours(FALLBACK_EXPIRY_HOURS),
+        };
+        match reminder.reopened_at {
+            Some(reopened_at) => expiry.max(reopened_at + Duration::minutes(REOPENED_EXPIRY_MINUTES)),
+            None => expiry,
+        }
+    }
+
+    /// 将到 `now` 已过期的未确认提醒记为错过，返回新增的错过记录。
+    ///
+    /// 延迟中的提醒等延迟的提醒发出后再过期。
+    pub fn expire_reminders(&mut self, now: DateTime<Local>) -> Vec<DoseEvent> {
+        let expired: Vec<Uuid> = self
+            .pending_reminders
+            .values()
+            .filter(|r| !r.is_confirmed && r.snoozed_until.is_none() && self.reminder_expiry(r) <= now)
+            .map(|r| r.id)
+            .collect();
+
+        let mut events = Vec::new();
+        for id in expired {
+            let reminder = self.pending_reminders.get_mut(&id).unwrap();
+            reminder.mark_missed();
+            events.push(
+                DoseEvent::new(
+                    reminder.medicine_id,
+                    reminder.medicine_name.clone(),
+                    DoseStatus::Missed,
+                    Some(reminder.scheduled_time),
+                    0,
+                )
+                .with_reminder(id),
+            );
+        }
+        self.dose_history.extend(events.iter().cloned());
+        events
+    }
+
+    /// 按旅行计划切换时区、调整当天的提醒时间或在返回时恢复，返回发生的变化
+    pub fn update_travel(&mut self, now: DateTime<Local>) -> Option<TravelEvent> {
+        let plan = self.user_settings.travel.as_mut()?;
+        if plan.has_returned(plan.today(now)) {
+            self.end_travel();
+            return Some(TravelEvent::Returned);
+        }
+        if plan.today(now) < plan.depart_date {
+            return None;
+        }
+
+        let starting = !plan.started;
+        if starting {
+            // 出发时记录在家的提醒时间，返回时恢复
+            plan.started = true;
+            plan.home_times = self
+                .medicines
+                .values()
+                .filter(|medicine| medicine.prn_policy().is_none())
+                .map(|medicine| (medicine.id, medicine.reminder_times.clone()))
+                .collect();
+            self.user_settings.timezone = Some(plan.destination);
+        }
+
+        let today = plan.today(now);
+        let mut changed = false;
+        for medicine in self.medicines.values_mut() {
+            if !plan.home_times.contains_key(&medicine.id) {
+                continue;
+            }
+            let times = plan.times_on(medicine, today);
+            if medicine.reminder_times != times {
+                medicine.reminder_times = times;
+                changed = true;
+            }
+        }
+
+        if starting {
+            Some(TravelEvent::Started)
+        } else if changed {
+            Some(TravelEvent::Adjusted)
+        } else {
+            None
+        }
+    }
+
+    /// 是否已经出发、提醒时间正按旅行计划调整
+    pub fn is_travelling(&self) -> bool {
+        self.user_settings.travel.as_ref().is_some_and(|plan| plan.started)
+    }
+
+    /// 结束旅行模式，恢复出发前的时区和提醒时间
+    pub fn end_travel(&mut self) -> bool {
+        let Some(plan) = self.user_settings.travel.take() else {
+            return false;
+        };
+        if plan.started {
+            for (medicine_id, times) in plan.home_times {
+                if let Some(medicine) = self.medicines.get_mut(&medicine_id) {
+                    medicine.reminder_times = times;
+                }
+            }
+            self.user_settings.timezone = plan.previous_timezone;
+        }
+        true
+    }
+
+    /// 计划时间在 `[from, to)` 内的服药统计
+    pub fn adherence_report(&self, from: DateTime<Local>, to: DateTime<Local>, now: DateTime<Local>) -> AdherenceReport {
+        stats::build_report(&self.dose_history, &self.pending_reminders, from, to, now, self.timezone())
+    }
+}
+
+/// 按 Telegram 聊天 ID 划分的用户数据
+pub type UserStore = HashMap<i64, UserData>;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AppData {
+    #[serde(default)]
+    pub users: UserStore,
+    #[serde(default)]
+    pub access: AccessControl,
+    /// 提醒循环最后处理到的时间，重启后从这里补查到期的服药
+    #[serde(default)]
+    pub last_processed: Option<DateTime<Local>>,
+    // 旧版单用户格式的字段，仅在尚未归属到某个聊天时保留
+    #[serde(rename = "medicines", default, skip_serializing_if = "Option::is_none")]
+    legacy_medicines: Option<MedicineStore>,
+    #[serde(rename = "pending_reminders", default, skip_serializing_if = "Option::is_none")]
+    legacy_pending_reminders: Option<PendingReminders>,
+    #[serde(rename = "user_settings", default, skip_serializing_if = "Option::is_none")]
+    legacy_user_settings: Option<UserSettings>,
+}
+
+impl AppData {
+    /// 获取指定聊天的数据，不存在时返回 `None`
+    pub fn user(&self, chat_id: i64) -> Option<&UserData> {
+        self.users.get(&chat_id)
+    }
+
+    /// 获取指定聊天的可变数据，不存在时自动创建
+    pub fn user_mut(&mut self, chat_id: i64) -> &mut UserData {
+        self.users.entry(chat_id).or_default()
+    }
+
+    /// 获取指定聊天的语言设置
+    pub fn language_for(&self, chat_id: i64) -> Language {
+        self.user(chat_id)
+            .map(|user| user.user_settings.language.clone())
+            .unwrap_or_default()
+    }
+
+    pub fn timezone_for(&self, chat_id: i64) -> Tz {
+        self.user(chat_id)
+            .map(UserData::timezone)
+            .unwrap_or_else(timezone::server_timezone)
+    }
+
+    /// 对所有聊天执行 [`UserData::migrate_reminder_slots`]
+    pub fn migrate_reminder_slots(&mut self) -> usize {
+        self.users.values_mut().map(UserData::migrate_reminder_slots).sum()
+    }
+
+    pub fn has_legacy_data(&self) -> bool {
+        self.legacy_medicines.is_some()
+            || self.legacy_pending_reminders.is_some()
+            || self.legacy_user_settings.is_some()
+    }
+
+    /// 将旧版单用户格式的数据归属到指定聊天。
+    ///
+    /// 如果该聊天已有数据，旧数据会合并进去（已存在的条目不会被覆盖）。
+    /// 返回是否迁移了任何数据。
+    pub fn claim_legacy_data(&mut self, chat_id: i64) -> bool {
+        if !self.has_legacy_data() {
+            return false;
+        }
+
+        let medicines = self.legacy_medicines.take().unwrap_or_default();
+        let pending_reminders = self.legacy_pending_reminders.take().unwrap_or_default();
+        let user_settings = self.legacy_user_settings.take();
+
+        let is_new_user = !self.users.contains_key(&chat_id);
+        let user = self.user_mut(chat_id);
+        for (id, medicine) in medicines {
+            user.medicines.entry(id).or_insert(medicine);
+        }
+        for (id, reminder) in pending_reminders {
+            user.pending_reminders.entry(id).or_insert(reminder);
+        }
+        if let Some(settings) = user_settings {
+            if is_new_user {
+                user.user_settings = settings;
+            }
         }
+        true
+    }
+
+    /// 尚未归属的旧版数据，序列化为 JSON，供不直接保存 `AppData` 的存储后端使用
+    pub(crate) fn legacy_json(&self) -> Option<String> {
+        if !self.has_legacy_data() {
+            return None;
+        }
+
+        let legacy = AppData {
+            legacy_medicines: self.legacy_medicines.clone(),
+            legacy_pending_reminders: self.legacy_pending_reminders.clone(),
+            legacy_user_settings: self.legacy_user_settings.clone(),
+            ..Default::default()
+        };
+        serde_json::to_string(&legacy).ok()
     }
+
+    pub(crate) fn restore_legacy_json(&mut self, json: &str) -> serde_json::Result<()> {
+        let legacy: AppData = serde_json::from_str(json)?;
+        self.legacy_medicines = legacy.legacy_medicines;
+        self.legacy_pending_reminders = legacy.legacy_pending_reminders;
+        self.legacy_user_settings = legacy.legacy_user_settings;
+        Ok(())
+    }
+}
+
+/// 数据的持久化方式
+pub trait StorageBackend: Send + Sync {
+    fn load(&self) -> Result<AppData, Box<dyn std::error::Error + Send + Sync>>;
+
+    fn save(&self, data: &AppData) -> Result<(), Box<dyn std::error::Error + Send + Sync>>;
 }
 
-pub struct Storage {
+/// 将全部数据保存在一个 JSON 文件中
+pub struct JsonStorage {
     file_path: String,
 }
 
-impl Storage {
+impl JsonStorage {
     pub fn new(file_path: &str) -> Self {
         Self {
             file_path: file_path.to_string(),
         }
     }
 
-    pub fn load(&self) -> Result<AppData, Box<dyn std::error::Error + Send + Sync>> {
+    pub fn file_path(&self) -> &str {
+        &self.file_path
+    }
+}
+
+impl StorageBackend for JsonStorage {
+    fn load(&self) -> Result<AppData, Box<dyn std::error::Error + Send + Sync>> {
         if !Path::new(&self.file_path).exists() {
             log::info!("数据文件不存在，创建默认数据");
             return Ok(AppData::default());
@@ -101,7 +530,7 @@ impl Storage {
         Ok(AppData::default())
     }
 
-    pub fn save(&self, data: &AppData) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    fn save(&self, data: &AppData) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         // 带重试的文件保存
         for attempt in 1..=3 {
             match serde_json::to_string_pretty(data) {
@@ -1,6 +1,6 @@
 #[cfg(test)]
 mod tests {
-    use crate::{localization, storage::{Language, UserSettings, AppData}};
+    use crate::{localization, storage::{Language, UserSettings, UserData}};
 
     #[test]
     fn test_chinese_localization() {
@@ -61,8 +61,8 @@ mod tests {
         let settings = UserSettings::default();
         assert_eq!(settings.language, Language::Chinese);
         
-        let app_data = AppData::default();
-        assert_eq!(app_data.user_settings.language, Language::Chinese);
+        let user_data = UserData::default();
+        assert_eq!(user_data.user_settings.language, Language::Chinese);
     }
 
     #[test]
@@ -77,4 +77,45 @@ mod tests {
         assert_eq!(chinese, Language::Chinese);
         assert_eq!(english, Language::English);
     }
+
+    #[test]
+    fn test_grouped_reminder_message_formatting() {
+        let doses = vec![("Aspirin".to_string(), 1), ("Vitamin C".to_string(), 2)];
+        let message = localization::format_grouped_reminder_message(&Language::English, &doses, "08:00");
+        assert!(message.contains("Time：08:00"));
+        assert!(message.contains("💊 Aspirin ×1"));
+        assert!(message.contains("💊 Vitamin C ×2"));
+        assert!(message.find("Aspirin") < message.find("Vitamin C"));
+    }
+
+    #[test]
+    fn test_reminder_view_shows_outcome() {
+        use crate::{DoseEvent, DoseStatus, PendingReminder};
+        use chrono::{Local, TimeZone};
+
+        let scheduled = Local.with_ymd_and_hms(2024, 1, 1, 8, 0, 0).unwrap();
+        let taken_at = Local.with_ymd_and_hms(2024, 1, 1, 8, 12, 0).unwrap();
+        let mut taken = PendingReminder::new(uuid::Uuid::new_v4(), "Aspirin".to_string(), scheduled);
+        let open = PendingReminder::new(uuid::Uuid::new_v4(), "Vitamin C".to_string(), scheduled);
+        taken.confirm();
+        let mut event = DoseEvent::new(taken.medicine_id, "Aspirin".to_string(), DoseStatus::Taken, Some(scheduled), 1)
+            .with_reminder(taken.id);
+        event.actual_time = taken_at;
+
+        let tz = chrono_tz::UTC;
+        let language = Language::English;
+        let status = localization::format_reminder_status(&language, tz, &taken, Some(&event));
+        let taken_at_utc = taken_at.with_timezone(&tz).format("%H:%M").to_string();
+        assert_eq!(status, Some(format!("✅ Taken at {}, 1 pill", taken_at_utc)));
+        assert_eq!(localization::format_reminder_status(&language, tz, &open, None), None);
+
+        let message = localization::format_reminder_view(&language, tz, &[(&taken, 1, status), (&open, 2, None)]);
+        assert!(message.contains("💊 Aspirin ×1\n✅ Taken at"));
+        assert!(message.contains("💊 Vitamin C ×2"));
+        assert!(message.contains("Please click the button"));
+
+        // 全部处理后不再提示点击按钮
+        let message = localization::format_reminder_view(&language, tz, &[(&taken, 1, Some("✅".to_string()))]);
+        assert!(!message.contains("Please click the button"));
+    }
 }
@@ -0,0 +1,61 @@
+use chrono::{DateTime, Duration, Local, LocalResult, NaiveDate, NaiveDateTime, NaiveTime, Offset, TimeZone};
+use chrono_tz::{Tz, TZ_VARIANTS};
+use std::sync::OnceLock;
+
+/// 服务器所在的时区，未设置时区的用户使用它；优先使用 `TZ` 环境变量，无法识别时使用 UTC
+pub fn server_timezone() -> Tz {
+    static SERVER_TIMEZONE: OnceLock<Tz> = OnceLock::new();
+    *SERVER_TIMEZONE.get_or_init(|| {
+        let name = std::env::var("TZ")
+            .ok()
+            .map(|name| name.trim_start_matches(':').to_string())
+            .or_else(|| iana_time_zone::get_timezone().ok());
+        match name.and_then(|name| name.parse().ok()) {
+            Some(tz) => tz,
+            None => {
+                log::warn!("无法识别服务器时区，使用 UTC");
+                Tz::UTC
+            }
+        }
+    })
+}
+
+/// 解析 IANA 时区名称，例如 `Asia/Shanghai`，不区分大小写
+pub fn parse_timezone(input: &str) -> Option<Tz> {
+    let input = input.trim();
+    TZ_VARIANTS.iter().copied().find(|tz| tz.name().eq_ignore_ascii_case(input))
+}
+
+/// 将用户时区的当地时间转换为时间点。
+///
+/// 夏令时切换时：
+/// - 时钟回拨导致出现两次的时间，只取第一次
+/// - 时钟拨快导致不存在的时间，按切换前的偏移换算，即顺延到切换之后（例如 02:30 变为 03:30）
+pub fn resolve(tz: Tz, time: NaiveDateTime) -> DateTime<Local> {
+    let resolved = match tz.from_local_datetime(&time) {
+        LocalResult::Single(resolved) => resolved,
+        LocalResult::Ambiguous(earliest, _) => earliest,
+        LocalResult::None => {
+            // 一天前的偏移即切换前的偏移
+            let offset = tz.offset_from_utc_datetime(&(time - Duration::days(1))).fix();
+            tz.from_utc_datetime(&(time - Duration::seconds(i64::from(offset.local_minus_utc()))))
+        }
+    };
+    resolved.with_timezone(&Local)
+}
+
+/// `now` 之后用户时区下一次到达 `time` 的时间：今天还没到就是今天，否则是明天
+pub fn next_time_of_day(tz: Tz, time: NaiveTime, now: DateTime<Local>) -> DateTime<Local> {
+    let today = now.with_timezone(&tz).date_naive();
+    let candidate = resolve(tz, today.and_time(time));
+    if candidate > now {
+        candidate
+    } else {
+        resolve(tz, (today + Duration::days(1)).and_time(time))
+    }
+}
+
+/// 用户时区某一天的开始时间
+pub fn start_of_day(tz: Tz, date: NaiveDate) -> DateTime<Local> {
+    resolve(tz, date.and_time(NaiveTime::MIN))
+}
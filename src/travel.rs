@@ -0,0 +1,168 @@
+use crate::{timezone, Medicine};
+use chrono::{DateTime, Duration, Local, NaiveDate, NaiveTime, Offset, TimeZone};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+/// 默认每天调整的分钟数
+pub const DEFAULT_TRAVEL_STEP_MINUTES: u32 = 60;
+
+/// 每天最多调整的分钟数
+pub const MAX_TRAVEL_STEP_MINUTES: u32 = 12 * 60;
+
+/// 旅行模式：出发后切换到目的地时区，并逐日把服药时间调整到目的地当地时间，返回时恢复
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct TravelPlan {
+    /// 出发地时区
+    pub home_timezone: Tz,
+    /// 出发前用户设置的时区（`None` 表示服务器时区），返回时恢复
+    pub previous_timezone: Option<Tz>,
+    pub destination: Tz,
+    /// 出发日期，当天开始调整
+    pub depart_date: NaiveDate,
+    /// 返回日期，当天恢复原来的服药时间
+    pub return_date: Option<NaiveDate>,
+    pub step_minutes: u32,
+    /// 出发时各药品的提醒时间，旅行期间以此为基础调整
+    #[serde(default)]
+    pub home_times: HashMap<Uuid, Vec<NaiveTime>>,
+    /// 是否已经出发
+    #[serde(default)]
+    pub started: bool,
+}
+
+/// 旅行计划带来的变化，用于通知用户
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TravelEvent {
+    Started,
+    Adjusted,
+    Returned,
+}
+
+impl TravelPlan {
+    pub fn new(
+        previous_timezone: Option<Tz>,
+        home_timezone: Tz,
+        destination: Tz,
+        depart_date: NaiveDate,
+        return_date: Option<NaiveDate>,
+        step_minutes: u32,
+    ) -> Self {
+        Self {
+            home_timezone,
+            previous_timezone,
+            destination,
+            depart_date,
+            return_date,
+            step_minutes: step_minutes.clamp(1, MAX_TRAVEL_STEP_MINUTES),
+            home_times: HashMap::new(),
+            started: false,
+        }
+    }
+
+    /// 解析“出发日期 [返回日期] [每天调整分钟数]”，例如 `2024-05-01 2024-05-20 60`
+    pub fn parse_dates(input: &str) -> Option<(NaiveDate, Option<NaiveDate>, u32)> {
+        let parse_date = |s: &str| NaiveDate::parse_from_str(s, "%Y-%m-%d").ok();
+        let tokens: Vec<&str> = input.split_whitespace().collect();
+        let (depart_date, return_date, step_minutes) = match tokens.as_slice() {
+            [depart] => (parse_date(depart)?, None, DEFAULT_TRAVEL_STEP_MINUTES),
+            [depart, second] => match parse_date(second) {
+                Some(return_date) => (parse_date(depart)?, Some(return_date), DEFAULT_TRAVEL_STEP_MINUTES),
+                None => (parse_date(depart)?, None, second.parse().ok()?),
+            },
+            [depart, return_date, step] => (parse_date(depart)?, Some(parse_date(return_date)?), step.parse().ok()?),
+            _ => return None,
+        };
+
+        let valid_return = return_date.is_none_or(|date| date > depart_date);
+        let valid_step = (1..=MAX_TRAVEL_STEP_MINUTES).contains(&step_minutes);
+        (valid_return && valid_step).then_some((depart_date, return_date, step_minutes))
+    }
+
+    /// 出发当天目的地与出发地的时差（分钟），取不超过12小时的方向
+    pub fn time_difference_minutes(&self) -> i64 {
+        let noon = self.depart_date.and_time(NaiveTime::MIN) + Duration::hours(12);
+        let offset = |tz: Tz| i64::from(tz.offset_from_utc_datetime(&noon).fix().local_minus_utc()) / 60;
+        let difference = offset(self.destination) - offset(self.home_timezone);
+        match difference {
+            d if d > 720 => d - 1440,
+            d if d <= -720 => d + 1440,
+            d => d,
+        }
+    }
+
+    /// `date`（目的地日期）当天的服药时间相对出发地时间的调整（分钟）。
+    ///
+    /// 出发当天与在家时的服药时刻相同，之后每天向目的地当地时间靠近 `step_minutes`，直到一致（为0）。
+    pub fn adjustment_on(&self, date: NaiveDate) -> i64 {
+        let total = self.time_difference_minutes();
+        let days = (date - self.depart_date).num_days().max(0);
+        let progress = (days * i64::from(self.step_minutes)).min(total.abs());
+        total - total.signum() * progress
+    }
+
+    /// 服药时间与目的地当地时间一致的第一天
+    pub fn adjusted_on(&self) -> NaiveDate {
+        let total = self.time_difference_minutes().abs();
+        let step = i64::from(self.step_minutes.max(1));
+        self.depart_date + Duration::days((total + step - 1) / step)
+    }
+
+    /// 药品在家时的提醒时间；出发前使用药品当前的设置
+    pub fn home_times_for<'a>(&'a self, medicine: &'a Medicine) -> &'a [NaiveTime] {
+        self.home_times.get(&medicine.id).unwrap_or(&medicine.reminder_times)
+    }
+
+    /// `date` 当天药品在目的地时区的提醒时间。
+    ///
+    /// 调整后跨过零点的时间不会绕到当天的另一端，而是限制在当天的 00:00 或 23:59，
+    /// 避免当天多服或漏服一次。多个时间限制到同一端时依次错开一分钟，不会合并。
+    pub fn times_on(&self, medicine: &Medicine, date: NaiveDate) -> Vec<NaiveTime> {
+        let adjustment = Duration::minutes(self.adjustment_on(date));
+        let minute = Duration::minutes(1);
+        let last_minute = NaiveTime::from_hms_opt(23, 59, 0).unwrap();
+
+        let mut home_times = self.home_times_for(medicine).to_vec();
+        home_times.sort();
+        home_times.dedup();
+        let mut times: Vec<NaiveTime> = home_times
+            .into_iter()
+            .map(|time| match time.overflowing_add_signed(adjustment) {
+                (_, carry) if carry > 0 => last_minute,
+                (_, carry) if carry < 0 => NaiveTime::MIN,
+                (time, _) => time,
+            })
+            .collect();
+        times.sort();
+
+        // 限制在 23:59 的时间向前错开，限制在 00:00 的时间向后错开
+        for i in (1..times.len()).rev() {
+            if times[i - 1] >= times[i] && times[i] > NaiveTime::MIN {
+                times[i - 1] = times[i] - minute;
+            }
+        }
+        for i in 1..times.len() {
+            if times[i] <= times[i - 1] && times[i - 1] < last_minute {
+                times[i] = times[i - 1] + minute;
+            }
+        }
+        times
+    }
+
+    /// 当前所在地的日期：出发后按目的地时区，出发前按出发地时区
+    pub fn today(&self, now: DateTime<Local>) -> NaiveDate {
+        let tz = if self.started { self.destination } else { self.home_timezone };
+        now.with_timezone(&tz).date_naive()
+    }
+
+    /// 下一次需要更新提醒时间的时间：当前所在地的下一个零点
+    pub fn next_update(&self, now: DateTime<Local>) -> DateTime<Local> {
+        let tz = if self.started { self.destination } else { self.home_timezone };
+        timezone::start_of_day(tz, self.today(now) + Duration::days(1))
+    }
+
+    pub fn has_returned(&self, date: NaiveDate) -> bool {
+        self.return_date.is_some_and(|return_date| date >= return_date)
+    }
+}
@@ -50,7 +50,7 @@ check_env() {
     if [ ! -f ".env" ]; then
         log_error ".env 文件不存在，请创建并配置以下变量："
         echo "TELOXIDE_TOKEN=your_bot_token"
-        echo "CHAT_ID=your_chat_id"
+        echo "CHAT_ID=your_chat_id  # 可选"
         exit 1
     fi
     
@@ -62,8 +62,7 @@ check_env() {
     fi
     
     if [ -z "$CHAT_ID" ]; then
-        log_error "CHAT_ID 未设置"
-        exit 1
+        log_warn "CHAT_ID 未设置，将不发送启动消息"
     fi
     
     log_info "环境变量检查通过"
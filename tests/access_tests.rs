@@ -0,0 +1,59 @@
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, Local};
+    use medicine_reminder::access::{AccessConfig, AccessControl, InviteError};
+
+    fn owner_config() -> AccessConfig {
+        AccessConfig {
+            owner_id: Some(1),
+            report_rejections: false,
+        }
+    }
+
+    #[test]
+    fn test_disabled_access_control_allows_everyone() {
+        let access = AccessControl::default();
+        assert!(access.is_allowed(&AccessConfig::default(), 12345));
+    }
+
+    #[test]
+    fn test_only_owner_and_allowlist_are_allowed() {
+        let config = owner_config();
+        let mut access = AccessControl::default();
+        access.allowed_users.insert(2);
+
+        assert!(access.is_allowed(&config, 1));
+        assert!(access.is_allowed(&config, 2));
+        assert!(!access.is_allowed(&config, 3));
+
+        assert!(access.revoke(2));
+        assert!(!access.is_allowed(&config, 2));
+        assert!(!access.revoke(2));
+    }
+
+    #[test]
+    fn test_invite_code_is_single_use() {
+        let config = owner_config();
+        let mut access = AccessControl::default();
+        let now = Local::now();
+
+        let code = access.create_invite(1, now);
+        assert_eq!(access.redeem_invite(&code.to_lowercase(), 3, now), Ok(()));
+        assert!(access.is_allowed(&config, 3));
+
+        assert_eq!(access.redeem_invite(&code, 4, now), Err(InviteError::NotFound));
+        assert!(!access.is_allowed(&config, 4));
+    }
+
+    #[test]
+    fn test_expired_invite_code_is_rejected() {
+        let config = owner_config();
+        let mut access = AccessControl::default();
+        let now = Local::now();
+
+        let code = access.create_invite(1, now);
+        let later = now + Duration::hours(25);
+        assert_eq!(access.redeem_invite(&code, 3, later), Err(InviteError::Expired));
+        assert!(!access.is_allowed(&config, 3));
+    }
+}
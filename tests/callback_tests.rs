@@ -0,0 +1,100 @@
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+    use medicine_reminder::{
+        storage::Language, CallbackData, CallbackError, EditField, HistoryFilter, SkipReason, MAX_CALLBACK_BYTES,
+    };
+    use uuid::Uuid;
+
+    // 机器人发送的每一种按钮，ID 和数字取最长的情况
+    fn all_callbacks() -> Vec<CallbackData> {
+        let id = Uuid::new_v4();
+        let mut callbacks = vec![
+            CallbackData::SetLanguage(Language::Chinese),
+            CallbackData::SetLanguage(Language::English),
+            CallbackData::Confirm(id),
+            CallbackData::DoseAmount { reminder_id: id, amount: u32::MAX },
+            CallbackData::DoseCustom(id),
+            CallbackData::Snooze(id),
+            CallbackData::SnoozeFor { reminder_id: id, minutes: 60 },
+            CallbackData::SnoozeCustom(id),
+            CallbackData::SnoozeUntil(id),
+            CallbackData::Skip(id),
+            CallbackData::SkipWithReason { reminder_id: id, reason: None },
+            CallbackData::TakeAll(i64::MIN),
+            CallbackData::Undo(id),
+            CallbackData::TakeAsNeeded { medicine_id: id, force: false },
+            CallbackData::TakeAsNeeded { medicine_id: id, force: true },
+            CallbackData::History { filter: HistoryFilter::default(), page: 0 },
+            CallbackData::History {
+                filter: HistoryFilter {
+                    medicine_id: Some(id),
+                    from: NaiveDate::from_ymd_opt(2024, 1, 1),
+                    to: NaiveDate::from_ymd_opt(2024, 12, 31),
+                },
+                page: 99_999,
+            },
+            CallbackData::Caregiver(None),
+            CallbackData::Caregiver(Some(id)),
+            CallbackData::CaregiverAcknowledge { patient_chat_id: i64::MIN, reminder_id: id },
+            CallbackData::CaregiverHandled { patient_chat_id: i64::MIN, reminder_id: id },
+            CallbackData::StockAlert(id),
+            CallbackData::Grace(id),
+            CallbackData::Expiry(id),
+            CallbackData::Escalation(id),
+            CallbackData::RevokeUser(u64::MAX),
+            CallbackData::DeleteMedicine(id),
+            CallbackData::Refill(id),
+            CallbackData::RefillAmount { medicine_id: id, amount: 30 },
+            CallbackData::RefillCustom(id),
+            CallbackData::EditMedicine(id),
+            CallbackData::Pause(id),
+            CallbackData::Resume(id),
+            CallbackData::TravelConfirm,
+            CallbackData::TravelDiscard,
+            CallbackData::TravelCancel,
+        ];
+        callbacks.extend(
+            EditField::ALL
+                .into_iter()
+                .map(|field| CallbackData::EditField { medicine_id: id, field }),
+        );
+        callbacks.extend(
+            SkipReason::ALL
+                .into_iter()
+                .map(|reason| CallbackData::SkipWithReason { reminder_id: id, reason: Some(reason) }),
+        );
+        callbacks
+    }
+
+    #[test]
+    fn test_every_callback_round_trips_within_limit() {
+        for callback in all_callbacks() {
+            let data = callback.encode();
+            assert!(data.len() <= MAX_CALLBACK_BYTES, "{} is {} bytes", data, data.len());
+            assert_eq!(CallbackData::decode(&data), Ok(callback), "{}", data);
+        }
+    }
+
+    #[test]
+    fn test_refill_amount_is_not_shadowed_by_refill() {
+        let medicine_id = Uuid::new_v4();
+        let data = CallbackData::RefillAmount { medicine_id, amount: 10 }.encode();
+        assert_eq!(CallbackData::decode(&data), Ok(CallbackData::RefillAmount { medicine_id, amount: 10 }));
+    }
+
+    #[test]
+    fn test_old_and_invalid_buttons_are_rejected() {
+        let id = Uuid::new_v4();
+        // 旧格式和其他版本的按钮
+        assert_eq!(CallbackData::decode(&format!("confirm_{}", id)), Err(CallbackError::Outdated));
+        assert_eq!(CallbackData::decode(&format!("refill_10_{}", id)), Err(CallbackError::Outdated));
+        assert_eq!(CallbackData::decode("lang_chinese"), Err(CallbackError::Outdated));
+        assert_eq!(CallbackData::decode(&format!("0:c:{}", id.simple())), Err(CallbackError::Outdated));
+        // 当前版本但内容错误
+        assert_eq!(CallbackData::decode("1:c:not-a-uuid"), Err(CallbackError::Invalid));
+        assert_eq!(CallbackData::decode(&format!("1:zz:{}", id.simple())), Err(CallbackError::Invalid));
+        assert_eq!(CallbackData::decode(&format!("1:kr:{}:unknown", id.simple())), Err(CallbackError::Invalid));
+        assert_eq!(CallbackData::decode("1:tc:extra"), Err(CallbackError::Invalid));
+    }
+}
@@ -0,0 +1,87 @@
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, Local, NaiveDate, TimeZone};
+    use medicine_reminder::timezone::server_timezone;
+    use medicine_reminder::{query_history, DoseEvent, DoseStatus, HistoryFilter, SkipReason};
+    use uuid::Uuid;
+
+    fn event_on(medicine_id: Uuid, day: u32, hour: u32) -> DoseEvent {
+        let mut event = DoseEvent::new(medicine_id, "Vitamin C".to_string(), DoseStatus::Taken, None, 1);
+        event.actual_time = Local.with_ymd_and_hms(2024, 1, day, hour, 0, 0).unwrap();
+        event
+    }
+
+    #[test]
+    fn test_history_is_newest_first_and_paginated() {
+        let medicine_id = Uuid::new_v4();
+        let history: Vec<DoseEvent> = (1..=5).map(|day| event_on(medicine_id, day, 8)).collect();
+
+        let (page, total_pages) = query_history(&history, &HistoryFilter::default(), server_timezone(), 0, 2);
+        assert_eq!(total_pages, 3);
+        assert_eq!(page.len(), 2);
+        assert_eq!(page[0].actual_time.date_naive(), NaiveDate::from_ymd_opt(2024, 1, 5).unwrap());
+        assert_eq!(page[1].actual_time.date_naive(), NaiveDate::from_ymd_opt(2024, 1, 4).unwrap());
+
+        let (last_page, _) = query_history(&history, &HistoryFilter::default(), server_timezone(), 2, 2);
+        assert_eq!(last_page.len(), 1);
+        assert_eq!(last_page[0].actual_time.date_naive(), NaiveDate::from_ymd_opt(2024, 1, 1).unwrap());
+
+        let (empty, _) = query_history(&history, &HistoryFilter::default(), server_timezone(), 3, 2);
+        assert!(empty.is_empty());
+    }
+
+    #[test]
+    fn test_history_filters_by_medicine_and_date_range() {
+        let vitamin = Uuid::new_v4();
+        let aspirin = Uuid::new_v4();
+        let history = vec![
+            event_on(vitamin, 1, 8),
+            event_on(aspirin, 2, 8),
+            event_on(vitamin, 3, 23),
+            event_on(vitamin, 4, 0),
+        ];
+
+        let filter = HistoryFilter {
+            medicine_id: Some(vitamin),
+            from: NaiveDate::from_ymd_opt(2024, 1, 2),
+            to: NaiveDate::from_ymd_opt(2024, 1, 3),
+        };
+        let (events, total_pages) = query_history(&history, &filter, server_timezone(), 0, 10);
+        assert_eq!(total_pages, 1);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].medicine_id, vitamin);
+        assert_eq!(events[0].actual_time, history[2].actual_time);
+    }
+
+    #[test]
+    fn test_dose_event_records_scheduled_and_actual_time() {
+        let scheduled = Local::now() - Duration::minutes(10);
+        let reminder_id = Uuid::new_v4();
+        let event = DoseEvent::new(Uuid::new_v4(), "Aspirin".to_string(), DoseStatus::Taken, Some(scheduled), 2)
+            .with_reminder(reminder_id);
+
+        assert_eq!(event.scheduled_time, Some(scheduled));
+        assert!(event.actual_time >= scheduled);
+        assert_eq!(event.amount, 2);
+        assert_eq!(event.reminder_id, Some(reminder_id));
+    }
+
+    #[test]
+    fn test_skip_reason_is_kept_and_optional() {
+        for reason in SkipReason::ALL {
+            assert_eq!(SkipReason::from_code(reason.code()), Some(reason));
+        }
+        assert_eq!(SkipReason::from_code("none"), None);
+
+        let event = DoseEvent::new(Uuid::new_v4(), "Vitamin C".to_string(), DoseStatus::Skipped, None, 0)
+            .with_skip_reason(Some(SkipReason::DoctorAdvice));
+        let restored: DoseEvent = serde_json::from_str(&serde_json::to_string(&event).unwrap()).unwrap();
+        assert_eq!(restored.skip_reason, Some(SkipReason::DoctorAdvice));
+
+        // 旧记录没有原因字段
+        let taken = DoseEvent::new(Uuid::new_v4(), "Vitamin C".to_string(), DoseStatus::Taken, None, 1);
+        let json = serde_json::to_string(&taken).unwrap();
+        assert!(!json.contains("skip_reason"));
+        assert_eq!(serde_json::from_str::<DoseEvent>(&json).unwrap().skip_reason, None);
+    }
+}
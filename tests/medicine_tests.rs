@@ -1,7 +1,10 @@
 #[cfg(test)]
 mod tests {
-    use medicine_reminder::Medicine;
-    use chrono::NaiveTime;
+    use medicine_reminder::{
+        Caregiver, EditField, EscalationAction, EscalationPolicy, Medicine, PendingReminder, PrnCheck, PrnPolicy,
+        Schedule,
+    };
+    use chrono::{Duration, Local, NaiveTime};
 
     #[test]
     fn test_take_dose_with_amount() {
@@ -64,5 +67,215 @@ mod tests {
         assert_eq!(medicine.quantity, 30);
         assert_eq!(medicine.reminder_times, times);
         assert!(medicine.is_active);
+        assert!(medicine.caregiver.is_none());
+    }
+
+    #[test]
+    fn test_prn_policy_parse() {
+        assert_eq!(
+            PrnPolicy::parse("PRN 4h 6"),
+            Some(PrnPolicy { min_interval_hours: 4, max_daily_doses: 6 })
+        );
+        assert_eq!(
+            PrnPolicy::parse("prn 8 3"),
+            Some(PrnPolicy { min_interval_hours: 8, max_daily_doses: 3 })
+        );
+        assert!(PrnPolicy::parse("prn 4h 0").is_none());
+        assert!(PrnPolicy::parse("prn 4h").is_none());
+        assert!(PrnPolicy::parse("08:00").is_none());
+    }
+
+    #[test]
+    fn test_prn_minimum_interval() {
+        let policy = PrnPolicy { min_interval_hours: 4, max_daily_doses: 6 };
+        let now = Local::now();
+
+        assert_eq!(policy.check(&[], now), PrnCheck::Allowed);
+
+        let last = now - Duration::hours(3);
+        assert_eq!(
+            policy.check(&[last], now),
+            PrnCheck::TooSoon { next_allowed: last + Duration::hours(4) }
+        );
+
+        let last = now - Duration::hours(4);
+        assert_eq!(policy.check(&[last], now), PrnCheck::Allowed);
+    }
+
+    #[test]
+    fn test_prn_daily_cap() {
+        let policy = PrnPolicy { min_interval_hours: 1, max_daily_doses: 3 };
+        let now = Local::now();
+        let doses = [
+            now - Duration::hours(30), // 已超出24小时窗口
+            now - Duration::hours(20),
+            now - Duration::hours(10),
+            now - Duration::hours(2),
+        ];
+
+        // 窗口内最早的一次在4小时后过期
+        assert_eq!(
+            policy.check(&doses, now),
+            PrnCheck::DailyLimitReached { next_allowed: doses[1] + Duration::hours(24) }
+        );
+        assert_eq!(policy.check(&doses[..3], now), PrnCheck::Allowed);
+    }
+
+    #[test]
+    fn test_take_prn_dose_records_and_prunes() {
+        let policy = PrnPolicy { min_interval_hours: 4, max_daily_doses: 6 };
+        let mut medicine = Medicine::new_as_needed("Ibuprofen".to_string(), 2, policy);
+        let now = Local::now();
+        medicine.prn_doses.push(now - Duration::hours(25));
+
+        assert!(medicine.reminder_times.is_empty());
+        assert!(medicine.take_prn_dose(1, now));
+        assert_eq!(medicine.prn_doses, vec![now]);
+        assert_eq!(medicine.quantity, 1);
+
+        assert!(!medicine.take_prn_dose(2, now));
+        assert_eq!(medicine.prn_doses, vec![now]);
+    }
+
+    #[test]
+    fn test_escalation_after_unanswered_follow_ups() {
+        let caregiver = Caregiver {
+            chat_id: 42,
+            escalate_after: 2,
+            patient_name: None,
+        };
+        let mut reminder = PendingReminder::new(
+            uuid::Uuid::new_v4(),
+            "Vitamin C".to_string(),
+            Local::now(),
+        );
+
+        // 首次提醒不计入后续提醒次数
+        assert_eq!(reminder.follow_up_count(), 0);
+        reminder.increment_reminder();
+        assert!(!reminder.should_escalate(&caregiver));
+
+        reminder.increment_reminder();
+        assert_eq!(reminder.follow_up_count(), 2);
+        assert!(reminder.should_escalate(&caregiver));
+
+        // 只通知一次
+        reminder.escalated = true;
+        assert!(!reminder.should_escalate(&caregiver));
+
+        reminder.escalated = false;
+        reminder.confirm();
+        assert!(!reminder.should_escalate(&caregiver));
+    }
+
+    #[test]
+    fn test_default_escalation_policy_matches_fixed_cadence() {
+        let policy = EscalationPolicy::default();
+        assert_eq!(policy.interval(1), Duration::minutes(5));
+        assert_eq!(policy.interval(2), Duration::minutes(10));
+        assert_eq!(policy.interval(3), Duration::minutes(15));
+        assert_eq!(policy.interval(20), Duration::minutes(15));
+
+        let mut reminder = PendingReminder::new(uuid::Uuid::new_v4(), "Test".to_string(), Local::now());
+        reminder.reminder_count = 50;
+        assert!(!reminder.follow_ups_exhausted(&policy));
+        assert!(reminder.next_follow_up(&policy).is_some());
+    }
+
+    #[test]
+    fn test_escalation_policy_parse_and_limit() {
+        let policy = EscalationPolicy::parse("10,30 2 caregiver").unwrap();
+        assert_eq!(policy.intervals, vec![10, 30]);
+        assert_eq!(policy.max_follow_ups, Some(2));
+        assert_eq!(policy.after, EscalationAction::NotifyCaregiver);
+        assert_eq!(EscalationPolicy::parse("5 3").unwrap().after, EscalationAction::MarkMissed);
+        assert_eq!(EscalationPolicy::parse("20").unwrap().max_follow_ups, None);
+        assert!(EscalationPolicy::parse("0,10").is_none());
+        assert!(EscalationPolicy::parse("5 3 later").is_none());
+        assert!(EscalationPolicy::parse("5 x").is_none());
+
+        let scheduled = Local::now() - Duration::hours(1);
+        let mut reminder = PendingReminder::new(uuid::Uuid::new_v4(), "Test".to_string(), scheduled);
+        assert_eq!(reminder.next_follow_up(&policy), Some(scheduled + Duration::minutes(10)));
+        reminder.increment_reminder();
+        reminder.increment_reminder();
+        assert!(reminder.follow_ups_exhausted(&policy));
+
+        reminder.follow_ups_finished = true;
+        assert_eq!(reminder.next_follow_up(&policy), None);
+    }
+
+    #[test]
+    fn test_snooze_overrides_follow_up_cadence() {
+        let policy = EscalationPolicy::parse("15 1 stop").unwrap();
+        let scheduled = Local::now() - Duration::hours(1);
+        let mut reminder = PendingReminder::new(uuid::Uuid::new_v4(), "Test".to_string(), scheduled);
+
+        let until = scheduled + Duration::minutes(42);
+        reminder.snooze(until);
+        assert_eq!(reminder.next_follow_up(&policy), Some(until));
+
+        // 已停止提醒时仍按延迟时间提醒一次
+        reminder.follow_ups_finished = true;
+        assert_eq!(reminder.next_follow_up(&policy), Some(until));
+        reminder.increment_reminder();
+        assert_eq!(reminder.snoozed_until, None);
+        assert_eq!(reminder.next_follow_up(&policy), None);
+
+        reminder.snooze(until);
+        reminder.confirm();
+        assert_eq!(reminder.next_follow_up(&policy), None);
+    }
+
+    #[test]
+    fn test_skip_closes_reminder_without_confirming_a_dose() {
+        let policy = EscalationPolicy::default();
+        let mut reminder = PendingReminder::new(uuid::Uuid::new_v4(), "Test".to_string(), Local::now());
+        reminder.skip();
+        assert!(reminder.skipped);
+        assert!(reminder.is_confirmed);
+        assert_eq!(reminder.next_follow_up(&policy), None);
+    }
+
+    #[test]
+    fn test_apply_edit_keeps_medicine_id() {
+        let mut medicine = Medicine::new(
+            "Vitamin C".to_string(),
+            10,
+            vec![NaiveTime::from_hms_opt(8, 0, 0).unwrap()],
+        );
+        let id = medicine.id;
+        medicine.low_stock_notified = true;
+
+        assert!(medicine.apply_edit(EditField::Name, "  Vitamin D "));
+        assert!(medicine.apply_edit(EditField::Stock, "25"));
+        assert!(medicine.apply_edit(EditField::Times, "07:30, 21:00"));
+        assert!(medicine.apply_edit(EditField::DoseAmount, "2"));
+        assert!(medicine.apply_edit(EditField::Notes, "after meals"));
+        assert!(medicine.apply_edit(EditField::Schedule, "every 2d"));
+
+        assert_eq!(medicine.id, id);
+        assert_eq!(medicine.name, "Vitamin D");
+        assert_eq!(medicine.quantity, 25);
+        assert!(!medicine.low_stock_notified);
+        assert_eq!(
+            medicine.reminder_times,
+            vec![NaiveTime::from_hms_opt(7, 30, 0).unwrap(), NaiveTime::from_hms_opt(21, 0, 0).unwrap()]
+        );
+        assert_eq!(medicine.dose_amount, 2);
+        assert_eq!(medicine.notes.as_deref(), Some("after meals"));
+        assert_eq!(medicine.schedule, Schedule::parse("every 2d").unwrap());
+
+        // 无效输入不做修改
+        assert!(!medicine.apply_edit(EditField::Name, "  "));
+        assert!(!medicine.apply_edit(EditField::Stock, "-1"));
+        assert!(!medicine.apply_edit(EditField::Times, "25:00"));
+        assert!(!medicine.apply_edit(EditField::DoseAmount, "0"));
+        assert!(!medicine.apply_edit(EditField::Schedule, "sometimes"));
+        assert_eq!(medicine.name, "Vitamin D");
+        assert_eq!(medicine.quantity, 25);
+
+        assert!(medicine.apply_edit(EditField::Notes, "-"));
+        assert_eq!(medicine.notes, None);
     }
 }
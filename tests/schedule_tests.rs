@@ -0,0 +1,162 @@
+#[cfg(test)]
+mod tests {
+    use chrono::{NaiveDate, NaiveDateTime, NaiveTime, Weekday};
+    use medicine_reminder::{Recurrence, Schedule, MAX_INTERVAL_HOURS};
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    fn time(h: u32, m: u32) -> NaiveTime {
+        NaiveTime::from_hms_opt(h, m, 0).unwrap()
+    }
+
+    fn at(d: NaiveDate, h: u32, m: u32) -> NaiveDateTime {
+        d.and_time(time(h, m))
+    }
+
+    #[test]
+    fn test_daily_schedule() {
+        let schedule = Schedule::default();
+        let anchor = date(2024, 1, 1);
+        let times = [time(20, 0), time(8, 0)];
+
+        let occurrences = schedule.occurrences_between(
+            &times,
+            anchor,
+            at(date(2024, 1, 2), 0, 0),
+            at(date(2024, 1, 3), 8, 0),
+        );
+        assert_eq!(
+            occurrences,
+            vec![
+                at(date(2024, 1, 2), 8, 0),
+                at(date(2024, 1, 2), 20, 0),
+                at(date(2024, 1, 3), 8, 0),
+            ]
+        );
+
+        // 创建之前的日期不提醒
+        assert!(!schedule.is_active_on(date(2023, 12, 31), anchor));
+    }
+
+    #[test]
+    fn test_weekday_schedule() {
+        let schedule = Schedule::parse("mon, wed,fri").unwrap();
+        assert_eq!(
+            schedule.recurrence,
+            Recurrence::Weekdays {
+                days: vec![Weekday::Mon, Weekday::Wed, Weekday::Fri]
+            }
+        );
+
+        let anchor = date(2024, 1, 1); // 星期一
+        assert!(schedule.is_active_on(date(2024, 1, 1), anchor));
+        assert!(!schedule.is_active_on(date(2024, 1, 2), anchor));
+        assert!(schedule.is_active_on(date(2024, 1, 3), anchor));
+        assert!(schedule.is_active_on(date(2024, 1, 5), anchor));
+        assert!(!schedule.is_active_on(date(2024, 1, 7), anchor));
+    }
+
+    #[test]
+    fn test_every_n_days_schedule() {
+        let schedule = Schedule::parse("every 3d").unwrap();
+        let anchor = date(2024, 1, 1);
+
+        let active: Vec<u32> = (1..=10)
+            .filter(|&d| schedule.is_active_on(date(2024, 1, d), anchor))
+            .collect();
+        assert_eq!(active, vec![1, 4, 7, 10]);
+    }
+
+    #[test]
+    fn test_every_n_hours_schedule() {
+        let schedule = Schedule::parse("every 8h from 2024-01-01").unwrap();
+        let anchor = date(2023, 6, 1);
+        let times = [time(6, 0)];
+
+        let occurrences = schedule.occurrences_between(
+            &times,
+            anchor,
+            at(date(2024, 1, 2), 0, 0),
+            at(date(2024, 1, 2), 23, 59),
+        );
+        assert_eq!(
+            occurrences,
+            vec![
+                at(date(2024, 1, 2), 6, 0),
+                at(date(2024, 1, 2), 14, 0),
+                at(date(2024, 1, 2), 22, 0),
+            ]
+        );
+
+        // 开始日期之前没有提醒
+        let before = schedule.occurrences_between(
+            &times,
+            anchor,
+            at(date(2023, 12, 31), 0, 0),
+            at(date(2023, 12, 31), 23, 59),
+        );
+        assert!(before.is_empty());
+    }
+
+    #[test]
+    fn test_cycle_schedule() {
+        let schedule = Schedule::parse("cycle 21/7").unwrap();
+        let anchor = date(2024, 1, 1);
+
+        assert!(schedule.is_active_on(date(2024, 1, 1), anchor));
+        assert!(schedule.is_active_on(date(2024, 1, 21), anchor));
+        assert!(!schedule.is_active_on(date(2024, 1, 22), anchor));
+        assert!(!schedule.is_active_on(date(2024, 1, 28), anchor));
+        assert!(schedule.is_active_on(date(2024, 1, 29), anchor));
+    }
+
+    #[test]
+    fn test_course_end_date() {
+        let schedule = Schedule::parse("daily from 2024-01-01 to 2024-01-05").unwrap();
+        let anchor = date(2023, 1, 1);
+
+        assert!(!schedule.is_active_on(date(2023, 12, 31), anchor));
+        assert!(schedule.is_active_on(date(2024, 1, 5), anchor));
+        assert!(!schedule.is_active_on(date(2024, 1, 6), anchor));
+    }
+
+    #[test]
+    fn test_invalid_schedules() {
+        assert!(Schedule::parse("every 0d").is_none());
+        assert!(Schedule::parse("every 2x").is_none());
+        assert!(Schedule::parse("cycle 21").is_none());
+        assert!(Schedule::parse("someday").is_none());
+        assert!(Schedule::parse("daily from 2024-02-01 to 2024-01-01").is_none());
+        assert!(Schedule::parse("from 2024-01-01 daily").is_none());
+        assert!(Schedule::parse("cycle 4000000000/4000000000").is_none());
+        assert!(Schedule::parse("every 4000000000h").is_none());
+        assert!(Schedule::parse("every 4000000000d").is_none());
+    }
+
+    #[test]
+    fn test_extreme_schedules_do_not_overflow() {
+        let anchor = date(2024, 1, 1);
+        let cycle = Schedule {
+            recurrence: Recurrence::Cycle { days_on: u32::MAX, days_off: u32::MAX },
+            ..Schedule::default()
+        };
+        assert!(cycle.is_active_on(date(2024, 6, 1), anchor));
+
+        // 下一次服药超出日期范围时不再继续
+        let every = Schedule {
+            recurrence: Recurrence::EveryNHours { hours: u32::MAX },
+            ..Schedule::default()
+        };
+        let occurrences = every.occurrences_between(&[time(8, 0)], anchor, at(anchor, 0, 0), NaiveDateTime::MAX);
+        assert_eq!(occurrences, vec![at(anchor, 8, 0)]);
+        assert!(every
+            .occurrences_between(&[time(8, 0)], anchor, at(date(2024, 1, 2), 0, 0), NaiveDateTime::MAX)
+            .is_empty());
+
+        let max = Schedule::parse(&format!("every {}h", MAX_INTERVAL_HOURS)).unwrap();
+        let occurrences = max.occurrences_between(&[time(8, 0)], anchor, at(anchor, 0, 0), at(date(2026, 1, 1), 0, 0));
+        assert_eq!(occurrences.len(), 3);
+    }
+}
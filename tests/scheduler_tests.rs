@@ -0,0 +1,166 @@
+#[cfg(test)]
+mod tests {
+    use chrono::{DateTime, Duration, Local, NaiveTime, TimeZone};
+    use medicine_reminder::scheduler::{due_window, plan_due_doses, DueKind, DueQueue, MAX_CATCH_UP_DAYS};
+    use medicine_reminder::{AppData, DoseStatus, Medicine, PendingReminder, UserData};
+
+    fn at(day: u32, hour: u32, minute: u32) -> DateTime<Local> {
+        Local.with_ymd_and_hms(2024, 1, day, hour, minute, 0).unwrap()
+    }
+
+    fn user_with(times: &[(u32, u32)], quantity: u32) -> (UserData, Medicine) {
+        let mut medicine = Medicine::new(
+            "Vitamin C".to_string(),
+            quantity,
+            times.iter().map(|&(h, m)| NaiveTime::from_hms_opt(h, m, 0).unwrap()).collect(),
+        );
+        medicine.created_at = at(1, 0, 0);
+        let mut user = UserData::default();
+        user.medicines.insert(medicine.id, medicine.clone());
+        (user, medicine)
+    }
+
+    #[test]
+    fn test_due_window() {
+        let now = at(10, 12, 0);
+        assert_eq!(due_window(None, now), (now - Duration::minutes(1), now));
+        assert_eq!(due_window(Some(at(10, 9, 0)), now), (at(10, 9, 0), now));
+        // 停机太久时只补查最近几天
+        assert_eq!(due_window(Some(at(1, 0, 0)), now), (now - Duration::days(MAX_CATCH_UP_DAYS), now));
+        // 时钟回拨时不重复检查
+        assert_eq!(due_window(Some(at(10, 13, 0)), now), (now, now));
+    }
+
+    #[test]
+    fn test_dose_within_grace_is_reminded_late() {
+        let (user, medicine) = user_with(&[(8, 0)], 10);
+        let plan = plan_due_doses(&user, at(10, 7, 0), at(10, 8, 40));
+        assert!(plan.missed.is_empty());
+        assert_eq!(plan.reminders.len(), 1);
+        assert_eq!(plan.reminders[0].medicine_id, medicine.id);
+        assert_eq!(plan.reminders[0].scheduled_time, at(10, 8, 0));
+    }
+
+    #[test]
+    fn test_doses_beyond_grace_are_missed() {
+        let (user, _) = user_with(&[(8, 0), (12, 0), (20, 0)], 10);
+        // 停机从 7:00 到 12:30：8:00 超过60分钟宽限记为错过，12:00 补发提醒
+        let plan = plan_due_doses(&user, at(10, 7, 0), at(10, 12, 30));
+        assert_eq!(plan.missed.len(), 1);
+        assert_eq!(plan.missed[0].status, DoseStatus::Missed);
+        assert_eq!(plan.missed[0].scheduled_time, Some(at(10, 8, 0)));
+        assert_eq!(plan.reminders.len(), 1);
+        assert_eq!(plan.reminders[0].scheduled_time, at(10, 12, 0));
+
+        // 宽限时间为0时全部记为错过
+        let (mut user, medicine) = user_with(&[(8, 0), (12, 0)], 10);
+        user.medicines.get_mut(&medicine.id).unwrap().grace_minutes = 0;
+        let plan = plan_due_doses(&user, at(10, 7, 0), at(10, 12, 30));
+        assert_eq!(plan.missed.len(), 2);
+        assert!(plan.reminders.is_empty());
+    }
+
+    #[test]
+    fn test_only_latest_dose_within_grace_is_reminded() {
+        let (user, _) = user_with(&[(8, 0), (8, 30)], 10);
+        let plan = plan_due_doses(&user, at(10, 7, 0), at(10, 8, 45));
+        assert_eq!(plan.reminders.len(), 1);
+        assert_eq!(plan.reminders[0].scheduled_time, at(10, 8, 30));
+        assert_eq!(plan.missed.len(), 1);
+        assert_eq!(plan.missed[0].scheduled_time, Some(at(10, 8, 0)));
+    }
+
+    #[test]
+    fn test_pending_and_out_of_stock_doses() {
+        let (mut user, medicine) = user_with(&[(8, 0)], 10);
+        // 同一时段已有提醒时不再创建；其他时段未确认的提醒不影响新的提醒
+        let pending = PendingReminder::new(medicine.id, medicine.name.clone(), at(10, 8, 0));
+        user.pending_reminders.insert(pending.id, pending);
+        let plan = plan_due_doses(&user, at(10, 7, 59), at(10, 8, 0));
+        assert!(plan.is_empty());
+        let plan = plan_due_doses(&user, at(11, 7, 59), at(11, 8, 0));
+        assert_eq!(plan.reminders.len(), 1);
+
+        let (user, medicine) = user_with(&[(8, 0)], 0);
+        let plan = plan_due_doses(&user, at(10, 7, 59), at(10, 8, 0));
+        assert!(plan.reminders.is_empty());
+        assert_eq!(plan.out_of_stock, vec![(medicine.id, medicine.name.clone())]);
+    }
+
+    #[test]
+    fn test_due_queue_orders_doses_and_follow_ups() {
+        let (mut user, medicine) = user_with(&[(8, 0), (20, 0)], 10);
+        user.user_settings.weekly_report = false;
+        let mut reminder = PendingReminder::new(medicine.id, medicine.name.clone(), at(10, 11, 58));
+        reminder.reminder_count = 2;
+        user.pending_reminders.insert(reminder.id, reminder);
+        let mut data = AppData::default();
+        data.users.insert(1, user);
+
+        let mut queue = DueQueue::plan(&data, at(10, 12, 0));
+        // 服药、后续提醒，以及提醒在20:00服药时过期
+        assert_eq!(queue.len(), 3);
+        // 第2次提醒后10分钟再提醒，早于20:00的服药
+        assert_eq!(queue.peek().map(|item| (item.at, item.kind)), Some((at(10, 12, 8), DueKind::FollowUp)));
+        assert!(queue.pop_due(at(10, 12, 7)).is_empty());
+        assert_eq!(queue.pop_due(at(10, 20, 0)), vec![DueKind::Dose, DueKind::FollowUp]);
+        assert!(queue.is_empty());
+
+        // 确认后只剩下一次服药；启用每周报告时加入下周一的报告
+        let user = data.users.get_mut(&1).unwrap();
+        user.pending_reminders.values_mut().for_each(|r| r.confirm());
+        user.user_settings.weekly_report = true;
+        let mut queue = DueQueue::plan(&data, at(10, 21, 0));
+        assert_eq!(queue.pop_due(at(11, 8, 0)), vec![DueKind::Dose]);
+        assert_eq!(queue.peek().map(|item| (item.at, item.kind)), Some((at(15, 9, 0), DueKind::WeeklyReport)));
+    }
+
+    #[test]
+    fn test_due_queue_keeps_dose_due_since_last_processed() {
+        let (mut user, _) = user_with(&[(8, 0)], 10);
+        user.user_settings.weekly_report = false;
+        let mut data = AppData::default();
+        data.users.insert(1, user);
+        data.last_processed = Some(at(10, 7, 0));
+
+        // 睡眠到08:00后重新计划，刚到期的服药仍然在队列中
+        let woke_at = at(10, 8, 0) + Duration::milliseconds(2);
+        let mut queue = DueQueue::plan(&data, woke_at);
+        assert_eq!(queue.peek().map(|item| (item.at, item.kind)), Some((at(10, 8, 0), DueKind::Dose)));
+        assert_eq!(queue.pop_due(woke_at), vec![DueKind::Dose]);
+
+        // 处理之后下一次服药是第二天
+        data.last_processed = Some(woke_at);
+        let mut queue = DueQueue::plan(&data, woke_at);
+        assert!(queue.pop_due(woke_at).is_empty());
+        assert_eq!(queue.peek().map(|item| (item.at, item.kind)), Some((at(11, 8, 0), DueKind::Dose)));
+    }
+
+    #[test]
+    fn test_stale_reminder_expires_and_stops_blocking() {
+        let (mut user, medicine) = user_with(&[(8, 0), (20, 0)], 10);
+        let morning = PendingReminder::new(medicine.id, medicine.name.clone(), at(10, 8, 0));
+        let morning_id = morning.id;
+        user.pending_reminders.insert(morning_id, morning);
+
+        // 默认到下一次服药时过期
+        assert_eq!(user.reminder_expiry(&user.pending_reminders[&morning_id]), at(10, 20, 0));
+        assert!(user.expire_reminders(at(10, 19, 59)).is_empty());
+
+        let expired = user.expire_reminders(at(10, 20, 0));
+        assert_eq!(expired.len(), 1);
+        assert_eq!(expired[0].status, DoseStatus::Missed);
+        assert_eq!(expired[0].reminder_id, Some(morning_id));
+        assert!(user.pending_reminders[&morning_id].missed);
+        assert_eq!(user.dose_history.len(), 1);
+        assert_eq!(plan_due_doses(&user, at(10, 19, 59), at(10, 20, 0)).reminders.len(), 1);
+
+        // 自定义过期时间；延迟中的提醒不过期
+        user.medicines.get_mut(&medicine.id).unwrap().expire_after_minutes = Some(90);
+        let mut evening = PendingReminder::new(medicine.id, medicine.name.clone(), at(10, 20, 0));
+        assert_eq!(user.reminder_expiry(&evening), at(10, 21, 30));
+        evening.snooze(at(10, 22, 0));
+        user.pending_reminders.insert(evening.id, evening);
+        assert!(user.expire_reminders(at(10, 21, 45)).is_empty());
+    }
+}
@@ -0,0 +1,119 @@
+#[cfg(test)]
+mod tests {
+    use chrono::{DateTime, Duration, Local, NaiveDate, NaiveTime, TimeZone};
+    use medicine_reminder::timezone::server_timezone;
+    use medicine_reminder::stats::{build_report, next_weekly_report, weekly_report_due, weekly_report_period};
+    use medicine_reminder::{DoseEvent, DoseStatus, PendingReminder, PendingReminders};
+    use uuid::Uuid;
+
+    fn at(day: u32, hour: u32, minute: u32) -> DateTime<Local> {
+        Local.with_ymd_and_hms(2024, 1, day, hour, minute, 0).unwrap()
+    }
+
+    fn slot_event(medicine_id: Uuid, status: DoseStatus, scheduled: DateTime<Local>, delay_minutes: i64) -> DoseEvent {
+        let mut event = DoseEvent::new(medicine_id, "Vitamin C".to_string(), status, Some(scheduled), 1)
+            .with_reminder(Uuid::new_v4());
+        event.actual_time = scheduled + Duration::minutes(delay_minutes);
+        event
+    }
+
+    #[test]
+    fn test_report_counts_outcomes_lateness_and_streaks() {
+        let medicine_id = Uuid::new_v4();
+        let history = vec![
+            slot_event(medicine_id, DoseStatus::Taken, at(1, 8, 0), 10),
+            slot_event(medicine_id, DoseStatus::Missed, at(2, 8, 0), 0),
+            slot_event(medicine_id, DoseStatus::Taken, at(3, 8, 0), 0),
+            slot_event(medicine_id, DoseStatus::Taken, at(4, 8, 0), 50),
+            slot_event(medicine_id, DoseStatus::Skipped, at(5, 8, 0), 0),
+            slot_event(medicine_id, DoseStatus::Taken, at(6, 8, 0), 0),
+        ];
+
+        let report = build_report(&history, &PendingReminders::new(), at(1, 0, 0), at(8, 0, 0), at(8, 0, 0), server_timezone());
+        assert_eq!(report.medicines.len(), 1);
+        let stats = &report.medicines[0];
+        assert_eq!((stats.on_time, stats.late, stats.skipped, stats.missed), (3, 1, 1, 1));
+        assert_eq!(stats.adherence_percent().map(|p| p.round()), Some(67.0));
+        // (10 + 0 + 50 + 0) / 4
+        assert_eq!(stats.average_lateness_minutes, Some(15));
+        assert_eq!(stats.current_streak, 1);
+        assert_eq!(stats.best_streak, 2);
+        assert_eq!(report.most_missed_slots, vec![(NaiveTime::from_hms_opt(8, 0, 0).unwrap(), 1)]);
+    }
+
+    #[test]
+    fn test_snoozes_are_folded_into_the_final_outcome() {
+        let medicine_id = Uuid::new_v4();
+        let reminder_id = Uuid::new_v4();
+        let mut snoozed = slot_event(medicine_id, DoseStatus::Snoozed, at(1, 8, 0), 5);
+        snoozed.reminder_id = Some(reminder_id);
+        let mut taken = slot_event(medicine_id, DoseStatus::Taken, at(1, 8, 0), 40);
+        taken.reminder_id = Some(reminder_id);
+        // 只有延迟记录的时段还没有结果
+        let only_snoozed = slot_event(medicine_id, DoseStatus::Snoozed, at(2, 8, 0), 5);
+
+        let report = build_report(
+            &[snoozed, taken, only_snoozed],
+            &PendingReminders::new(),
+            at(1, 0, 0),
+            at(8, 0, 0),
+            at(8, 0, 0),
+            server_timezone(),
+        );
+        let stats = &report.medicines[0];
+        assert_eq!(stats.total(), 1);
+        assert_eq!(stats.late, 1);
+    }
+
+    #[test]
+    fn test_overdue_unconfirmed_reminders_count_as_missed() {
+        let medicine_id = Uuid::new_v4();
+        let mut pending = PendingReminders::new();
+        for reminder in [
+            PendingReminder::new(medicine_id, "Vitamin C".to_string(), at(7, 8, 0)),
+            // 刚到时间的提醒还不算错过
+            PendingReminder::new(medicine_id, "Vitamin C".to_string(), at(7, 20, 0)),
+        ] {
+            pending.insert(reminder.id, reminder);
+        }
+        let mut confirmed = PendingReminder::new(medicine_id, "Vitamin C".to_string(), at(6, 8, 0));
+        confirmed.confirm();
+        pending.insert(confirmed.id, confirmed);
+
+        let report = build_report(&[], &pending, at(1, 0, 0), at(8, 0, 0), at(7, 20, 10), server_timezone());
+        let stats = &report.medicines[0];
+        assert_eq!(stats.total(), 1);
+        assert_eq!(stats.missed, 1);
+        assert_eq!(stats.adherence_percent(), Some(0.0));
+    }
+
+    #[test]
+    fn test_report_ignores_prn_doses_and_other_periods() {
+        let medicine_id = Uuid::new_v4();
+        let prn = DoseEvent::new(medicine_id, "Ibuprofen".to_string(), DoseStatus::Taken, None, 1);
+        let outside = slot_event(medicine_id, DoseStatus::Taken, at(10, 8, 0), 0);
+
+        let report = build_report(&[prn, outside], &PendingReminders::new(), at(1, 0, 0), at(8, 0, 0), at(8, 0, 0), server_timezone());
+        assert!(report.is_empty());
+    }
+
+    #[test]
+    fn test_weekly_report_schedule() {
+        // 2024-01-08 是周一
+        assert!(weekly_report_due(at(8, 9, 0), server_timezone(), None));
+        assert!(!weekly_report_due(at(8, 8, 59), server_timezone(), None));
+        assert!(!weekly_report_due(at(8, 10, 0), server_timezone(), Some(NaiveDate::from_ymd_opt(2024, 1, 8).unwrap())));
+        assert!(weekly_report_due(at(8, 10, 0), server_timezone(), Some(NaiveDate::from_ymd_opt(2024, 1, 1).unwrap())));
+        assert!(!weekly_report_due(at(9, 9, 0), server_timezone(), None));
+
+        let (from, to) = weekly_report_period(NaiveDate::from_ymd_opt(2024, 1, 8).unwrap(), server_timezone());
+        assert_eq!(from, at(1, 0, 0));
+        assert_eq!(to, at(8, 0, 0));
+
+        let sent_monday = Some(NaiveDate::from_ymd_opt(2024, 1, 8).unwrap());
+        assert_eq!(next_weekly_report(at(8, 8, 0), server_timezone(), None), at(8, 9, 0));
+        assert_eq!(next_weekly_report(at(8, 10, 0), server_timezone(), None), at(8, 10, 0));
+        assert_eq!(next_weekly_report(at(8, 10, 0), server_timezone(), sent_monday), at(15, 9, 0));
+        assert_eq!(next_weekly_report(at(10, 12, 0), server_timezone(), sent_monday), at(15, 9, 0));
+    }
+}
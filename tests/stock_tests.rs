@@ -0,0 +1,115 @@
+#[cfg(test)]
+mod tests {
+    use chrono::{DateTime, Duration, Local, NaiveDate, NaiveTime, TimeZone};
+    use medicine_reminder::timezone::server_timezone;
+    use medicine_reminder::{
+        forecast_supply, stock_level, typical_dose, DoseEvent, DoseStatus, Medicine, PrnPolicy, StockAlert, StockLevel,
+        UserData,
+    };
+
+    fn at(day: u32, hour: u32) -> DateTime<Local> {
+        Local.with_ymd_and_hms(2024, 1, day, hour, 0, 0).unwrap()
+    }
+
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 1, day).unwrap()
+    }
+
+    fn twice_daily(quantity: u32) -> Medicine {
+        let mut medicine = Medicine::new(
+            "Vitamin C".to_string(),
+            quantity,
+            vec![NaiveTime::from_hms_opt(8, 0, 0).unwrap(), NaiveTime::from_hms_opt(20, 0, 0).unwrap()],
+        );
+        medicine.created_at = at(1, 0);
+        medicine
+    }
+
+    fn taken(medicine: &Medicine, amount: u32, scheduled: Option<DateTime<Local>>, actual: DateTime<Local>) -> DoseEvent {
+        let mut event = DoseEvent::new(medicine.id, medicine.name.clone(), DoseStatus::Taken, scheduled, amount);
+        event.actual_time = actual;
+        event
+    }
+
+    #[test]
+    fn test_forecast_follows_schedule_and_typical_dose() {
+        let medicine = twice_daily(5);
+        // 2024-01-10 12:00 之后：10日20:00、11日08:00、11日20:00、12日08:00、12日20:00 共5次，第6次在13日
+        let forecast = forecast_supply(&medicine, &[], at(10, 12), server_timezone());
+        assert_eq!(forecast.dose_amount, 1);
+        assert_eq!(forecast.runs_out_on, Some(date(13)));
+        assert_eq!(forecast.days_left, Some(3));
+
+        // 通常每次服用2个时，5个只够2次
+        let history = vec![
+            taken(&medicine, 2, Some(at(9, 8)), at(9, 8)),
+            taken(&medicine, 2, Some(at(9, 20)), at(9, 20)),
+            taken(&medicine, 1, Some(at(10, 8)), at(10, 8)),
+        ];
+        assert_eq!(typical_dose(&history, medicine.id), 2);
+        let forecast = forecast_supply(&medicine, &history, at(10, 12), server_timezone());
+        assert_eq!(forecast.runs_out_on, Some(date(11)));
+    }
+
+    #[test]
+    fn test_forecast_respects_course_end_and_prn_usage() {
+        let mut medicine = twice_daily(100);
+        medicine.schedule.end_date = Some(date(20));
+        let forecast = forecast_supply(&medicine, &[], at(10, 12), server_timezone());
+        assert_eq!(forecast.runs_out_on, None);
+
+        let mut prn = Medicine::new_as_needed("Ibuprofen".to_string(), 10, PrnPolicy::parse("prn 4h 6").unwrap());
+        prn.created_at = at(1, 0);
+        assert_eq!(forecast_supply(&prn, &[], at(10, 12), server_timezone()).runs_out_on, None);
+
+        // 14天内共服用7个，每天0.5个，剩余10个可用20天
+        let history: Vec<DoseEvent> = (0..7).map(|i| taken(&prn, 1, None, at(10, 12) - Duration::days(i))).collect();
+        let forecast = forecast_supply(&prn, &history, at(10, 12), server_timezone());
+        assert_eq!(forecast.days_left, Some(20));
+        assert_eq!(forecast.runs_out_on, Some(date(30)));
+    }
+
+    #[test]
+    fn test_stock_level_uses_threshold_and_days() {
+        let mut medicine = twice_daily(5);
+        let forecast = forecast_supply(&medicine, &[], at(10, 12), server_timezone());
+        // 默认不足3天时提醒，还剩3天
+        assert_eq!(stock_level(&medicine, &forecast), StockLevel::Ok);
+
+        medicine.stock_alert = StockAlert::parse("5 0").unwrap();
+        assert_eq!(stock_level(&medicine, &forecast), StockLevel::Low);
+
+        medicine.stock_alert = StockAlert::parse("0 4").unwrap();
+        assert_eq!(medicine.stock_alert.low_stock_threshold, None);
+        assert_eq!(stock_level(&medicine, &forecast), StockLevel::Low);
+
+        medicine.quantity = 0;
+        assert_eq!(stock_level(&medicine, &forecast), StockLevel::Empty);
+
+        assert_eq!(StockAlert::parse("default"), Some(StockAlert::default()));
+        assert!(StockAlert::parse("ten").is_none());
+        assert!(StockAlert::parse("1 2 3").is_none());
+    }
+
+    #[test]
+    fn test_low_stock_alert_is_sent_once_until_refill() {
+        let mut medicine = twice_daily(2);
+        medicine.stock_alert = StockAlert::parse("2").unwrap();
+        let medicine_id = medicine.id;
+        let mut user = UserData::default();
+        user.medicines.insert(medicine_id, medicine);
+
+        let (level, _) = user.take_low_stock_alert(medicine_id, at(10, 12)).unwrap();
+        assert_eq!(level, StockLevel::Low);
+        assert!(user.take_low_stock_alert(medicine_id, at(10, 12)).is_none());
+
+        let medicine = user.medicines.get_mut(&medicine_id).unwrap();
+        medicine.add_quantity(30);
+        assert!(user.take_low_stock_alert(medicine_id, at(10, 12)).is_none());
+
+        let medicine = user.medicines.get_mut(&medicine_id).unwrap();
+        assert!(medicine.take_dose(31));
+        let (level, _) = user.take_low_stock_alert(medicine_id, at(10, 12)).unwrap();
+        assert_eq!(level, StockLevel::Low);
+    }
+}
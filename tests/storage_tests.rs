@@ -0,0 +1,399 @@
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, Local, NaiveTime};
+    use medicine_reminder::bot::State;
+    use medicine_reminder::{
+        storage::Language, AppData, Caregiver, DoseEvent, DoseStatus, JsonStorage, Medicine, PendingReminder,
+        SqliteStorage, StorageBackend, REOPENED_EXPIRY_MINUTES, UNDO_WINDOW_MINUTES,
+    };
+    use std::path::PathBuf;
+    use uuid::Uuid;
+
+    const LEGACY_JSON: &str = r#"{
+        "medicines": {},
+        "pending_reminders": {},
+        "user_settings": { "language": "English" }
+    }"#;
+
+    #[test]
+    fn test_users_are_partitioned_by_chat() {
+        let mut data = AppData::default();
+        let medicine = Medicine::new(
+            "Vitamin C".to_string(),
+            30,
+            vec![NaiveTime::from_hms_opt(8, 0, 0).unwrap()],
+        );
+
+        data.user_mut(1).medicines.insert(medicine.id, medicine);
+        data.user_mut(2).user_settings.language = Language::English;
+
+        assert_eq!(data.user(1).unwrap().medicines.len(), 1);
+        assert!(data.user(2).unwrap().medicines.is_empty());
+        assert!(data.user(3).is_none());
+        assert_eq!(data.language_for(1), Language::Chinese);
+        assert_eq!(data.language_for(2), Language::English);
+        assert_eq!(data.language_for(3), Language::Chinese);
+    }
+
+    #[test]
+    fn test_medicine_caregiver_overrides_user_caregiver() {
+        let mut data = AppData::default();
+        let user = data.user_mut(1);
+        let default_caregiver = Caregiver {
+            chat_id: 10,
+            escalate_after: 3,
+            patient_name: None,
+        };
+        user.user_settings.caregiver = Some(default_caregiver.clone());
+
+        let mut medicine = Medicine::new("Aspirin".to_string(), 10, vec![]);
+        assert_eq!(user.caregiver_for(&medicine), Some(&default_caregiver));
+
+        let override_caregiver = Caregiver {
+            chat_id: 20,
+            escalate_after: 1,
+            patient_name: None,
+        };
+        medicine.caregiver = Some(override_caregiver.clone());
+        assert_eq!(user.caregiver_for(&medicine), Some(&override_caregiver));
+    }
+
+    #[test]
+    fn test_legacy_data_is_claimed_by_chat() {
+        let mut data: AppData = serde_json::from_str(LEGACY_JSON).unwrap();
+        assert!(data.has_legacy_data());
+        assert!(data.users.is_empty());
+
+        assert!(data.claim_legacy_data(42));
+        assert!(!data.has_legacy_data());
+        assert_eq!(data.language_for(42), Language::English);
+
+        // 已归属的数据不应再以旧格式写出
+        let json = serde_json::to_value(&data).unwrap();
+        assert!(json.get("medicines").is_none());
+        assert!(json["users"].get("42").is_some());
+    }
+
+    #[test]
+    fn test_unclaimed_legacy_data_is_preserved() {
+        let data: AppData = serde_json::from_str(LEGACY_JSON).unwrap();
+        let json = serde_json::to_string(&data).unwrap();
+        let reloaded: AppData = serde_json::from_str(&json).unwrap();
+        assert!(reloaded.has_legacy_data());
+    }
+
+    fn temp_path(extension: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("medicine-reminder-test-{}.{}", Uuid::new_v4(), extension))
+    }
+
+    fn sample_data() -> (AppData, Uuid) {
+        let mut data = AppData::default();
+        let medicine = Medicine::new(
+            "Vitamin C".to_string(),
+            30,
+            vec![NaiveTime::from_hms_opt(8, 0, 0).unwrap()],
+        );
+        let medicine_id = medicine.id;
+        let reminder = PendingReminder::new(medicine.id, medicine.name.clone(), Local::now());
+        let event = DoseEvent::new(medicine.id, medicine.name.clone(), DoseStatus::Taken, None, 1);
+
+        let user = data.user_mut(1);
+        user.user_settings.language = Language::English;
+        user.medicines.insert(medicine.id, medicine);
+        user.pending_reminders.insert(reminder.id, reminder);
+        user.record_dose_event(event);
+        data.access.allowed_users.insert(7);
+        data.access.create_invite(7, Local::now());
+        (data, medicine_id)
+    }
+
+    #[test]
+    fn test_sqlite_round_trip_and_incremental_updates() {
+        let path = temp_path("db");
+        let (mut data, medicine_id) = sample_data();
+
+        let storage = SqliteStorage::open(&path).unwrap();
+        assert!(storage.is_empty().unwrap());
+        storage.save(&data).unwrap();
+
+        // 修改、删除和追加都应写入数据库
+        let user = data.user_mut(1);
+        user.medicines.get_mut(&medicine_id).unwrap().take_dose(2);
+        user.pending_reminders.clear();
+        user.record_dose_event(DoseEvent::new(medicine_id, "Vitamin C".to_string(), DoseStatus::Skipped, None, 0));
+        data.user_mut(2).user_settings.language = Language::English;
+        storage.save(&data).unwrap();
+        drop(storage);
+
+        let loaded = SqliteStorage::open(&path).unwrap().load().unwrap();
+        let user = loaded.user(1).unwrap();
+        assert_eq!(user.medicines[&medicine_id].quantity, 28);
+        assert!(user.pending_reminders.is_empty());
+        assert_eq!(user.dose_history.len(), 2);
+        assert_eq!(user.dose_history[1].status, DoseStatus::Skipped);
+        assert_eq!(loaded.language_for(1), Language::English);
+        assert_eq!(loaded.language_for(2), Language::English);
+        assert!(loaded.access.allowed_users.contains(&7));
+        assert_eq!(loaded.access.invites.len(), 1);
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_sqlite_persists_changes_to_saved_dose_events() {
+        let path = temp_path("db");
+        let (mut data, _) = sample_data();
+        let storage = SqliteStorage::open(&path).unwrap();
+        storage.save(&data).unwrap();
+
+        // 已保存的服药记录被修改后也要写入
+        let reminder_id = Uuid::new_v4();
+        data.user_mut(1).dose_history[0].reminder_id = Some(reminder_id);
+        storage.save(&data).unwrap();
+        drop(storage);
+
+        let loaded = SqliteStorage::open(&path).unwrap().load().unwrap();
+        assert_eq!(loaded.user(1).unwrap().dose_history[0].reminder_id, Some(reminder_id));
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_sqlite_keeps_unclaimed_legacy_data() {
+        let storage = SqliteStorage::open_in_memory().unwrap();
+        storage.save(&serde_json::from_str(LEGACY_JSON).unwrap()).unwrap();
+
+        let mut loaded = storage.load().unwrap();
+        assert!(loaded.has_legacy_data());
+        assert!(loaded.claim_legacy_data(42));
+        storage.save(&loaded).unwrap();
+
+        let reloaded = storage.load().unwrap();
+        assert!(!reloaded.has_legacy_data());
+        assert_eq!(reloaded.language_for(42), Language::English);
+    }
+
+    #[test]
+    fn test_json_file_is_migrated_to_sqlite_once() {
+        let json_path = temp_path("json");
+        let (data, medicine_id) = sample_data();
+        let json_storage = JsonStorage::new(json_path.to_str().unwrap());
+        json_storage.save(&data).unwrap();
+
+        let storage = SqliteStorage::open_in_memory().unwrap();
+        assert!(storage.migrate_from_json(&json_storage).unwrap());
+        assert!(!json_path.exists());
+        // 已导入后不再重复导入
+        assert!(!storage.migrate_from_json(&json_storage).unwrap());
+
+        let loaded = storage.load().unwrap();
+        assert_eq!(loaded.user(1).unwrap().medicines[&medicine_id].name, "Vitamin C");
+        assert_eq!(loaded.user(1).unwrap().dose_history.len(), 1);
+
+        let _ = std::fs::remove_file(format!("{}.migrated", json_path.display()));
+    }
+
+    #[test]
+    fn test_dialogue_state_survives_reload() {
+        let state = State::ReceiveSchedule {
+            name: "Vitamin C".to_string(),
+            quantity: 30,
+            reminder_times: vec![NaiveTime::from_hms_opt(8, 0, 0).unwrap()],
+        };
+        let mut data = AppData::default();
+        data.user_mut(1).dialogue_state = Some(serde_json::to_value(&state).unwrap());
+
+        let storage = SqliteStorage::open_in_memory().unwrap();
+        storage.save(&data).unwrap();
+        let from_sqlite = storage.load().unwrap();
+        let json = serde_json::to_string(&from_sqlite).unwrap();
+        let from_json: AppData = serde_json::from_str(&json).unwrap();
+
+        let restored: State =
+            serde_json::from_value(from_json.user(1).unwrap().dialogue_state.clone().unwrap()).unwrap();
+        match restored {
+            State::ReceiveSchedule { name, quantity, reminder_times } => {
+                assert_eq!(name, "Vitamin C");
+                assert_eq!(quantity, 30);
+                assert_eq!(reminder_times.len(), 1);
+            }
+            _ => panic!("对话状态未正确恢复"),
+        }
+    }
+
+    #[test]
+    fn test_closed_reminders_are_pruned() {
+        let (mut data, medicine_id) = sample_data();
+        let user = data.user_mut(1);
+        let mut old = PendingReminder::new(medicine_id, "Vitamin C".to_string(), Local::now() - Duration::days(10));
+        old.confirm();
+        let open_old = PendingReminder::new(medicine_id, "Vitamin C".to_string(), Local::now() - Duration::days(11));
+        user.pending_reminders.insert(old.id, old);
+        user.pending_reminders.insert(open_old.id, open_old);
+
+        assert_eq!(user.prune_closed_reminders(Local::now() - Duration::days(7)), 1);
+        assert_eq!(user.pending_reminders.len(), 2);
+    }
+
+    #[test]
+    fn test_reminders_migrate_to_dose_slots() {
+        let (mut data, medicine_id) = sample_data();
+        let scheduled = Local::now() - Duration::hours(2);
+        let user = data.user_mut(1);
+
+        // 旧数据：同一时段两个随机ID的提醒，服药记录引用已确认的那个
+        let mut confirmed = PendingReminder::new(medicine_id, "Vitamin C".to_string(), scheduled);
+        confirmed.id = Uuid::new_v4();
+        confirmed.confirm();
+        let mut duplicate = PendingReminder::new(medicine_id, "Vitamin C".to_string(), scheduled);
+        duplicate.id = Uuid::new_v4();
+        duplicate.reminder_count = 4;
+        let other_slot = PendingReminder::new(medicine_id, "Vitamin C".to_string(), scheduled + Duration::hours(1));
+        user.dose_history.push(
+            DoseEvent::new(medicine_id, "Vitamin C".to_string(), DoseStatus::Taken, Some(scheduled), 1)
+                .with_reminder(confirmed.id),
+        );
+        for reminder in [confirmed, duplicate, other_slot] {
+            user.pending_reminders.insert(reminder.id, reminder);
+        }
+
+        assert_eq!(data.migrate_reminder_slots(), 2);
+        let user = data.user(1).unwrap();
+        let slot_id = PendingReminder::slot_id(medicine_id, scheduled);
+        // 示例数据中另有一个当前时段的提醒
+        assert_eq!(user.pending_reminders.len(), 3);
+        assert!(user.pending_reminders[&slot_id].is_confirmed);
+        assert_eq!(user.dose_history.last().unwrap().reminder_id, Some(slot_id));
+        assert_eq!(data.migrate_reminder_slots(), 0);
+    }
+
+    #[test]
+    fn test_reminder_slot_migration_persists_in_both_backends() {
+        for name in ["json", "db"] {
+            let path = temp_path(name);
+            let open = || -> Box<dyn StorageBackend> {
+                match name {
+                    "json" => Box::new(JsonStorage::new(path.to_str().unwrap())),
+                    _ => Box::new(SqliteStorage::open(&path).unwrap()),
+                }
+            };
+            let (mut data, medicine_id) = sample_data();
+            let scheduled = Local::now() - Duration::hours(2);
+            let user = data.user_mut(1);
+            let mut reminder = PendingReminder::new(medicine_id, "Vitamin C".to_string(), scheduled);
+            reminder.id = Uuid::new_v4();
+            reminder.confirm();
+            user.dose_history.push(
+                DoseEvent::new(medicine_id, "Vitamin C".to_string(), DoseStatus::Taken, Some(scheduled), 1)
+                    .with_reminder(reminder.id),
+            );
+            user.pending_reminders.insert(reminder.id, reminder);
+            open().save(&data).unwrap();
+
+            // 与启动时相同：加载、迁移、保存
+            let storage = open();
+            let mut data = storage.load().unwrap();
+            assert_eq!(data.migrate_reminder_slots(), 1, "{}", name);
+            storage.save(&data).unwrap();
+            drop(storage);
+
+            let loaded = open().load().unwrap();
+            let user = loaded.user(1).unwrap();
+            let slot_id = PendingReminder::slot_id(medicine_id, scheduled);
+            assert!(user.pending_reminders[&slot_id].is_confirmed, "{}", name);
+            assert_eq!(user.dose_history.last().unwrap().reminder_id, Some(slot_id), "{}", name);
+            let _ = std::fs::remove_file(&path);
+        }
+    }
+
+    #[test]
+    fn test_undo_taken_restores_stock_within_window() {
+        let (mut data, medicine_id) = sample_data();
+        let user = data.user_mut(1);
+        let reminder_id = *user.pending_reminders.keys().next().unwrap();
+        let scheduled = user.pending_reminders[&reminder_id].scheduled_time;
+
+        let medicine = user.medicines.get_mut(&medicine_id).unwrap();
+        medicine.stock_alert.low_stock_threshold = Some(28);
+        medicine.take_dose(2);
+        user.pending_reminders.get_mut(&reminder_id).unwrap().confirm();
+        let taken = DoseEvent::new(medicine_id, "Vitamin C".to_string(), DoseStatus::Taken, Some(scheduled), 2)
+            .with_reminder(reminder_id);
+        let taken_at = taken.actual_time;
+        user.record_dose_event(taken);
+        assert_eq!(user.taken_event(reminder_id).unwrap().amount, 2);
+        assert!(user.take_low_stock_alert(medicine_id, taken_at).is_some());
+
+        // 超过撤销时间后不能撤销
+        let mut late = user.clone();
+        assert!(late.undo_taken(reminder_id, taken_at + Duration::minutes(UNDO_WINDOW_MINUTES + 1)).is_none());
+
+        let undone = user.undo_taken(reminder_id, taken_at + Duration::minutes(1)).unwrap();
+        assert_eq!(undone.amount, 2);
+        assert_eq!(user.medicines[&medicine_id].quantity, 30);
+        // 库存恢复到提醒条件之上，再次低于时重新提醒
+        assert!(!user.medicines[&medicine_id].low_stock_notified);
+        assert!(!user.pending_reminders[&reminder_id].is_confirmed);
+        assert!(user.taken_event(reminder_id).is_none());
+        assert_eq!(user.dose_history.len(), 1);
+        // 已经撤销过的提醒不能再次撤销
+        assert!(user.undo_taken(reminder_id, taken_at + Duration::minutes(1)).is_none());
+    }
+
+    #[test]
+    fn test_undo_reopens_follow_ups_and_expiry() {
+        let (mut data, medicine_id) = sample_data();
+        let user = data.user_mut(1);
+        let reminder_id = *user.pending_reminders.keys().next().unwrap();
+        let scheduled = user.pending_reminders[&reminder_id].scheduled_time;
+        let medicine = user.medicines.get_mut(&medicine_id).unwrap();
+        medicine.expire_after_minutes = Some(60);
+        medicine.escalation.max_follow_ups = Some(2);
+        let policy = medicine.escalation.clone();
+
+        // 后续提醒已用完并延迟过，临近过期时才确认
+        let reminder = user.pending_reminders.get_mut(&reminder_id).unwrap();
+        reminder.reminder_count = 3;
+        reminder.follow_ups_finished = true;
+        reminder.escalated = true;
+        reminder.snooze(scheduled + Duration::minutes(50));
+        reminder.confirm();
+        let confirmed_at = scheduled + Duration::minutes(55);
+        let mut taken = DoseEvent::new(medicine_id, "Vitamin C".to_string(), DoseStatus::Taken, Some(scheduled), 1)
+            .with_reminder(reminder_id);
+        taken.actual_time = confirmed_at;
+        user.record_dose_event(taken);
+
+        let undone_at = confirmed_at + Duration::minutes(2);
+        assert!(user.undo_taken(reminder_id, undone_at).is_some());
+        let reminder = &user.pending_reminders[&reminder_id];
+        assert_eq!(reminder.next_follow_up(&policy), Some(undone_at + policy.interval(1)));
+        assert!(!reminder.escalated);
+
+        // 原来的过期时间已过，但撤销后不会立即记为错过
+        assert!(user.expire_reminders(undone_at + Duration::minutes(10)).is_empty());
+        assert!(!user.pending_reminders[&reminder_id].is_confirmed);
+        assert_eq!(user.expire_reminders(undone_at + Duration::minutes(REOPENED_EXPIRY_MINUTES)).len(), 1);
+    }
+
+    #[test]
+    fn test_pause_closes_open_reminders_and_resumes_on_date() {
+        let (mut data, medicine_id) = sample_data();
+        let user = data.user_mut(1);
+        let now = Local::now();
+        let until = now.with_timezone(&user.timezone()).date_naive() + Duration::days(3);
+
+        let closed = user.pause_medicine(medicine_id, Some(until)).unwrap();
+        assert_eq!(closed.len(), 1);
+        assert!(user.pending_reminders[&closed[0]].is_confirmed);
+        assert_eq!(user.dose_history.last().unwrap().status, DoseStatus::Skipped);
+        assert!(!user.medicines[&medicine_id].is_active);
+        assert!(user.pause_medicine(Uuid::new_v4(), None).is_none());
+
+        // 恢复日期之前保持暂停
+        assert!(user.resume_due_medicines(now + Duration::days(2)).is_empty());
+        assert_eq!(user.resume_due_medicines(now + Duration::days(3)), vec!["Vitamin C".to_string()]);
+        assert!(user.medicines[&medicine_id].is_active);
+        assert!(user.medicines[&medicine_id].paused_until.is_none());
+    }
+}
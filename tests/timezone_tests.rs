@@ -0,0 +1,103 @@
+#[cfg(test)]
+mod tests {
+    use chrono::{DateTime, Local, NaiveDate, NaiveTime, TimeZone, Utc};
+    use chrono_tz::Tz;
+    use medicine_reminder::scheduler::plan_due_doses;
+    use medicine_reminder::timezone::{next_time_of_day, parse_timezone, resolve};
+    use medicine_reminder::{Medicine, Schedule, UserData};
+
+    const BERLIN: Tz = chrono_tz::Europe::Berlin;
+
+    fn utc(month: u32, day: u32, hour: u32, minute: u32) -> DateTime<Local> {
+        Utc.with_ymd_and_hms(2024, month, day, hour, minute, 0).unwrap().with_timezone(&Local)
+    }
+
+    fn local(month: u32, day: u32, hour: u32, minute: u32) -> chrono::NaiveDateTime {
+        NaiveDate::from_ymd_opt(2024, month, day).unwrap().and_hms_opt(hour, minute, 0).unwrap()
+    }
+
+    fn medicine_at(times: &[(u32, u32)]) -> Medicine {
+        let mut medicine = Medicine::new(
+            "Vitamin C".to_string(),
+            10,
+            times.iter().map(|&(h, m)| NaiveTime::from_hms_opt(h, m, 0).unwrap()).collect(),
+        );
+        medicine.created_at = utc(1, 1, 12, 0);
+        medicine
+    }
+
+    #[test]
+    fn test_parse_timezone() {
+        assert_eq!(parse_timezone("Europe/Berlin"), Some(BERLIN));
+        assert_eq!(parse_timezone(" asia/shanghai "), Some(chrono_tz::Asia::Shanghai));
+        assert_eq!(parse_timezone("Mars/Olympus"), None);
+        assert_eq!(parse_timezone(""), None);
+    }
+
+    #[test]
+    fn test_resolve_skipped_and_repeated_times() {
+        // 2024-03-31 02:00 时钟拨快到 03:00，02:30 顺延为 03:30（UTC 01:30）
+        assert_eq!(resolve(BERLIN, local(3, 31, 2, 30)), utc(3, 31, 1, 30));
+        // 2024-10-27 03:00 时钟回拨到 02:00，02:30 出现两次，取第一次（UTC 00:30）
+        assert_eq!(resolve(BERLIN, local(10, 27, 2, 30)), utc(10, 27, 0, 30));
+        assert_eq!(resolve(BERLIN, local(7, 1, 8, 0)), utc(7, 1, 6, 0));
+    }
+
+    #[test]
+    fn test_daily_dose_in_dst_gap_still_fires_once() {
+        let medicine = medicine_at(&[(2, 30)]);
+        let occurrences = medicine.occurrences_in(BERLIN, utc(3, 30, 0, 0), utc(4, 1, 23, 0));
+        assert_eq!(occurrences, vec![utc(3, 30, 1, 30), utc(3, 31, 1, 30), utc(4, 1, 0, 30)]);
+
+        let occurrences = medicine.occurrences_in(BERLIN, utc(10, 26, 0, 0), utc(10, 28, 23, 0));
+        assert_eq!(occurrences, vec![utc(10, 26, 0, 30), utc(10, 27, 0, 30), utc(10, 28, 1, 30)]);
+    }
+
+    #[test]
+    fn test_hourly_schedule_across_dst_changes() {
+        let mut medicine = medicine_at(&[(0, 0)]);
+        medicine.schedule = Schedule::parse("every 1h").unwrap();
+
+        // 拨快：02:00 不存在，顺延后与 03:00 重合，只提醒一次
+        let spring = medicine.occurrences_in(BERLIN, utc(3, 30, 23, 0), utc(3, 31, 2, 0));
+        assert_eq!(spring, vec![utc(3, 30, 23, 0), utc(3, 31, 0, 0), utc(3, 31, 1, 0), utc(3, 31, 2, 0)]);
+
+        // 回拨：第二次出现的 02:00 不再提醒
+        let autumn = medicine.occurrences_in(BERLIN, utc(10, 26, 22, 0), utc(10, 27, 3, 0));
+        assert_eq!(autumn, vec![utc(10, 26, 22, 0), utc(10, 26, 23, 0), utc(10, 27, 0, 0), utc(10, 27, 2, 0), utc(10, 27, 3, 0)]);
+    }
+
+    #[test]
+    fn test_due_doses_follow_user_timezone() {
+        let medicine = medicine_at(&[(8, 0)]);
+        let mut user = UserData::default();
+        user.user_settings.timezone = Some(chrono_tz::Asia::Tokyo);
+        user.medicines.insert(medicine.id, medicine.clone());
+
+        // 东京 08:00 即 UTC 前一天 23:00
+        let plan = plan_due_doses(&user, utc(7, 1, 22, 59), utc(7, 1, 23, 0));
+        assert_eq!(plan.reminders.len(), 1);
+        assert_eq!(plan.reminders[0].scheduled_time, utc(7, 1, 23, 0));
+        assert!(plan_due_doses(&user, utc(7, 2, 7, 59), utc(7, 2, 8, 0)).is_empty());
+
+        // 拨快当天 02:30 的服药在 03:30 提醒，不会因为检查窗口跨过切换而漏掉
+        let mut user = UserData::default();
+        user.user_settings.timezone = Some(BERLIN);
+        let medicine = medicine_at(&[(2, 30)]);
+        user.medicines.insert(medicine.id, medicine);
+        let before = plan_due_doses(&user, utc(3, 31, 0, 59), utc(3, 31, 1, 0));
+        assert!(before.is_empty());
+        let plan = plan_due_doses(&user, utc(3, 31, 1, 0), utc(3, 31, 1, 30));
+        assert_eq!(plan.reminders.len(), 1);
+        assert_eq!(plan.reminders[0].scheduled_time, utc(3, 31, 1, 30));
+    }
+
+    #[test]
+    fn test_next_time_of_day() {
+        let time = NaiveTime::from_hms_opt(9, 30, 0).unwrap();
+        // 柏林冬季为 UTC+1
+        assert_eq!(next_time_of_day(BERLIN, time, utc(1, 10, 7, 0)), utc(1, 10, 8, 30));
+        assert_eq!(next_time_of_day(BERLIN, time, utc(1, 10, 8, 30)), utc(1, 11, 8, 30));
+        assert_eq!(next_time_of_day(BERLIN, time, utc(1, 10, 23, 30)), utc(1, 11, 8, 30));
+    }
+}
@@ -0,0 +1,106 @@
+#[cfg(test)]
+mod tests {
+    use chrono::{DateTime, Local, NaiveDate, NaiveTime, TimeZone};
+    use chrono_tz::Asia::{Shanghai, Tokyo};
+    use chrono_tz::Europe::Paris;
+    use chrono_tz::America::Los_Angeles;
+    use chrono_tz::Tz;
+    use medicine_reminder::travel::{TravelEvent, TravelPlan};
+    use medicine_reminder::{Medicine, UserData};
+
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 1, day).unwrap()
+    }
+
+    fn time(hour: u32, minute: u32) -> NaiveTime {
+        NaiveTime::from_hms_opt(hour, minute, 0).unwrap()
+    }
+
+    fn at(tz: Tz, day: u32, hour: u32) -> DateTime<Local> {
+        tz.with_ymd_and_hms(2024, 1, day, hour, 0, 0).unwrap().with_timezone(&Local)
+    }
+
+    fn plan(home: Tz, destination: Tz, step_minutes: u32) -> TravelPlan {
+        TravelPlan::new(Some(home), home, destination, date(10), Some(date(20)), step_minutes)
+    }
+
+    #[test]
+    fn test_parse_travel_dates() {
+        assert_eq!(TravelPlan::parse_dates("2024-01-10"), Some((date(10), None, 60)));
+        assert_eq!(TravelPlan::parse_dates("2024-01-10 2024-01-20"), Some((date(10), Some(date(20)), 60)));
+        assert_eq!(TravelPlan::parse_dates("2024-01-10 90"), Some((date(10), None, 90)));
+        assert_eq!(TravelPlan::parse_dates("2024-01-10 2024-01-20 30"), Some((date(10), Some(date(20)), 30)));
+        assert_eq!(TravelPlan::parse_dates("2024-01-10 2024-01-05"), None);
+        assert_eq!(TravelPlan::parse_dates("2024-01-10 0"), None);
+        assert_eq!(TravelPlan::parse_dates("tomorrow"), None);
+    }
+
+    #[test]
+    fn test_stepwise_adjustment() {
+        // 上海到巴黎冬季时差 -7 小时
+        let paris = plan(Shanghai, Paris, 60);
+        assert_eq!(paris.time_difference_minutes(), -420);
+        assert_eq!(paris.adjustment_on(date(9)), -420);
+        assert_eq!(paris.adjustment_on(date(10)), -420);
+        assert_eq!(paris.adjustment_on(date(11)), -360);
+        assert_eq!(paris.adjustment_on(date(17)), 0);
+        assert_eq!(paris.adjusted_on(), date(17));
+
+        let mut medicine = Medicine::new("Vitamin C".to_string(), 30, vec![time(8, 0), time(5, 0)]);
+        medicine.created_at = at(Shanghai, 1, 0);
+        // 出发当天与在家时的服药时刻相同，跨过零点的时间限制在当天的 00:00
+        assert_eq!(paris.times_on(&medicine, date(10)), vec![time(0, 0), time(1, 0)]);
+        assert_eq!(paris.times_on(&medicine, date(12)), vec![time(0, 0), time(3, 0)]);
+        assert_eq!(paris.times_on(&medicine, date(17)), vec![time(5, 0), time(8, 0)]);
+
+        // 23:30 向后调整不会绕到当天凌晨，而是限制在 23:59
+        let shanghai = plan(Paris, Shanghai, 60);
+        let mut late = Medicine::new("Melatonin".to_string(), 30, vec![time(23, 30), time(0, 30)]);
+        late.created_at = at(Paris, 1, 0);
+        assert_eq!(shanghai.adjustment_on(date(16)), 60);
+        assert_eq!(shanghai.times_on(&late, date(16)), vec![time(1, 30), time(23, 59)]);
+
+        // 两个时间都跨过零点时错开一分钟，不会少服一次
+        let mut both = Medicine::new("Melatonin".to_string(), 30, vec![time(23, 30), time(23, 45)]);
+        both.created_at = at(Paris, 1, 0);
+        assert_eq!(shanghai.times_on(&both, date(16)), vec![time(23, 58), time(23, 59)]);
+        let mut early = Medicine::new("Vitamin C".to_string(), 30, vec![time(5, 0), time(6, 30), time(8, 0)]);
+        early.created_at = at(Shanghai, 1, 0);
+        assert_eq!(paris.times_on(&early, date(10)), vec![time(0, 0), time(0, 1), time(1, 0)]);
+
+        // 取不超过12小时的方向：洛杉矶到东京是 -7 小时而不是 +17 小时
+        let tokyo = plan(Los_Angeles, Tokyo, 120);
+        assert_eq!(tokyo.time_difference_minutes(), -420);
+        assert_eq!(tokyo.adjusted_on(), date(14));
+    }
+
+    #[test]
+    fn test_travel_lifecycle() {
+        let mut medicine = Medicine::new("Vitamin C".to_string(), 30, vec![time(8, 0)]);
+        medicine.created_at = at(Shanghai, 1, 0);
+        let medicine_id = medicine.id;
+        let mut user = UserData::default();
+        user.user_settings.timezone = Some(Shanghai);
+        user.medicines.insert(medicine_id, medicine);
+        user.user_settings.travel = Some(plan(Shanghai, Paris, 60));
+
+        assert_eq!(user.update_travel(at(Shanghai, 9, 23)), None);
+
+        // 上海时间出发当天零点开始：切换到巴黎时区，服药时刻保持不变
+        assert_eq!(user.update_travel(at(Shanghai, 10, 0)), Some(TravelEvent::Started));
+        assert_eq!(user.user_settings.timezone, Some(Paris));
+        assert_eq!(user.medicines[&medicine_id].reminder_times, vec![time(1, 0)]);
+        let next = user.medicines[&medicine_id].occurrences_in(Paris, at(Paris, 9, 18), at(Paris, 10, 12));
+        assert_eq!(next, vec![at(Shanghai, 10, 8)]);
+
+        assert_eq!(user.update_travel(at(Paris, 10, 12)), None);
+        assert_eq!(user.update_travel(at(Paris, 11, 0)), Some(TravelEvent::Adjusted));
+        assert_eq!(user.medicines[&medicine_id].reminder_times, vec![time(2, 0)]);
+
+        assert_eq!(user.update_travel(at(Paris, 20, 0)), Some(TravelEvent::Returned));
+        assert_eq!(user.user_settings.timezone, Some(Shanghai));
+        assert_eq!(user.medicines[&medicine_id].reminder_times, vec![time(8, 0)]);
+        assert!(user.user_settings.travel.is_none());
+        assert!(!user.end_travel());
+    }
+}